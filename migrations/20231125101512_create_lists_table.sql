@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE lists(
+    list_id uuid PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- Everyone who subscribed before lists existed belongs to this one.
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES (
+           '5b8a9fc4-3e47-4c6c-9a47-6f0f2a1f7d21',
+           'newsletter',
+           'Newsletter',
+           now()
+);
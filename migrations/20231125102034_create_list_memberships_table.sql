@@ -0,0 +1,22 @@
+-- Add migration script here
+BEGIN;
+CREATE TABLE list_memberships(
+    list_id uuid NOT NULL
+        REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    confirmed_at timestamptz NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+-- Backfill memberships of the default list for existing subscribers
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+SELECT
+    '5b8a9fc4-3e47-4c6c-9a47-6f0f2a1f7d21',
+    id,
+    status,
+    subscribed_at,
+    CASE WHEN status = 'confirmed' THEN subscribed_at END
+FROM subscriptions;
+COMMIT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+BEGIN;
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+-- Tokens issued before lists existed confirm the default list
+UPDATE subscription_tokens
+SET list_id = '5b8a9fc4-3e47-4c6c-9a47-6f0f2a1f7d21'
+WHERE list_id IS NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
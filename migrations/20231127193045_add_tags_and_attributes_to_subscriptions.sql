@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE subscriptions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+CREATE INDEX subscriptions_tags_idx ON subscriptions USING GIN (tags);
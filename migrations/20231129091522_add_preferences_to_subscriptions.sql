@@ -0,0 +1,13 @@
+-- Add migration script here
+BEGIN;
+-- Token embedded in the preference center link of every email
+ALTER TABLE subscriptions ADD COLUMN preferences_token TEXT NULL;
+UPDATE subscriptions
+SET preferences_token = substr(replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', ''), 1, 25)
+WHERE preferences_token IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN preferences_token SET NOT NULL;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_preferences_token_key UNIQUE (preferences_token);
+-- Either 'every_issue' or 'digest'
+ALTER TABLE subscriptions ADD COLUMN delivery_frequency TEXT NOT NULL DEFAULT 'every_issue';
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+COMMIT;
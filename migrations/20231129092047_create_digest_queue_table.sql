@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE digest_queue
+(
+    newsletter_issue_id uuid NOT NULL
+       REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+       REFERENCES subscriptions (id),
+    queued_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
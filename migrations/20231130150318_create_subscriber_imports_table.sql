@@ -0,0 +1,18 @@
+-- Add migration script here
+BEGIN;
+CREATE TABLE subscriber_imports(
+    import_id uuid NOT NULL,
+    PRIMARY KEY (import_id),
+    imported_rows BIGINT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+-- Rows of an import that were not stored, served back as a CSV report
+CREATE TABLE subscriber_import_rejections(
+    import_id uuid NOT NULL
+        REFERENCES subscriber_imports (import_id),
+    line BIGINT NOT NULL,
+    email TEXT NOT NULL,
+    reason TEXT NOT NULL
+);
+CREATE INDEX subscriber_import_rejections_import_id_idx ON subscriber_import_rejections (import_id);
+COMMIT;
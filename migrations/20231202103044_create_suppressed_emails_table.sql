@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Addresses erased at the subscriber's request, stored as a SHA-256 hash
+-- so that they cannot be imported again.
+CREATE TABLE suppressed_emails(
+    email_hash TEXT NOT NULL,
+    PRIMARY KEY (email_hash),
+    suppressed_at timestamptz NOT NULL
+);
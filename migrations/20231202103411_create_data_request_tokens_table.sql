@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Tokens mailed to people asking for a copy or the erasure of their data.
+-- They are keyed by email: the address may not belong to a subscriber anymore.
+CREATE TABLE data_request_tokens(
+    data_request_token TEXT NOT NULL,
+    PRIMARY KEY (data_request_token),
+    email TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
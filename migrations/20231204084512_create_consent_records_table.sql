@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Proof of opt-in: one record per subscription to a list, completed on confirmation.
+CREATE TABLE consent_records(
+    consent_id uuid NOT NULL,
+    PRIMARY KEY (consent_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    list_id uuid NOT NULL
+        REFERENCES lists (list_id),
+    source TEXT NOT NULL,
+    consent_text_version TEXT NOT NULL,
+    signup_ip TEXT NULL,
+    signup_user_agent TEXT NULL,
+    signed_up_at timestamptz NOT NULL,
+    confirmation_ip TEXT NULL,
+    confirmation_user_agent TEXT NULL,
+    confirmed_at timestamptz NULL
+);
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id);
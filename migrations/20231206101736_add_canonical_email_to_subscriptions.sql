@@ -0,0 +1,22 @@
+-- Add migration script here
+-- Uniqueness moves from the address as typed to its canonical form.
+-- Rows only differing by case keep their raw address as canonical form
+-- (lowercase spellings first) so that they can be merged by hand.
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN canonical_email TEXT NULL;
+    UPDATE subscriptions s
+    SET canonical_email = CASE WHEN ranked.position = 1 THEN lower(s.email) ELSE s.email END
+    FROM (
+        SELECT
+            id,
+            row_number() OVER (
+                PARTITION BY lower(email)
+                ORDER BY email = lower(email) DESC, subscribed_at, id
+            ) AS position
+        FROM subscriptions
+    ) ranked
+    WHERE ranked.id = s.id;
+    ALTER TABLE subscriptions ALTER COLUMN canonical_email SET NOT NULL;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_canonical_email_key UNIQUE (canonical_email);
+    ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+COMMIT;
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Lets repeat submissions re-use a recently issued token.
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Where the hosted subscribe and confirmation pages send people afterwards,
+-- our own pages are shown when NULL.
+ALTER TABLE lists
+    ADD COLUMN subscribed_redirect_url TEXT NULL,
+    ADD COLUMN confirmed_redirect_url TEXT NULL;
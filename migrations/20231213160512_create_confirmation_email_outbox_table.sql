@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Confirmation emails are written here in the same transaction as the
+-- subscription, then delivered by the background worker.
+CREATE TABLE confirmation_email_outbox(
+    outbox_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    subscriber_email TEXT NOT NULL,
+    subscription_token TEXT NOT NULL,
+    enqueued_at timestamptz NOT NULL
+);
@@ -0,0 +1,34 @@
+-- Add migration script here
+BEGIN;
+    -- Emails sent to the subscribers of a list once they confirm.
+    CREATE TABLE automation_sequences(
+        sequence_id uuid PRIMARY KEY,
+        list_id uuid NOT NULL REFERENCES lists (list_id),
+        name TEXT NOT NULL,
+        created_at timestamptz NOT NULL
+    );
+    -- Delays are counted in days from the confirmation.
+    CREATE TABLE automation_steps(
+        sequence_id uuid NOT NULL REFERENCES automation_sequences (sequence_id),
+        position INT NOT NULL,
+        delay_days INT NOT NULL CHECK (delay_days >= 0),
+        title TEXT NOT NULL,
+        text_content TEXT NOT NULL,
+        html_content TEXT NOT NULL,
+        PRIMARY KEY (sequence_id, position)
+    );
+    CREATE TABLE automation_progress(
+        sequence_id uuid NOT NULL REFERENCES automation_sequences (sequence_id),
+        subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+        status TEXT NOT NULL CHECK (status IN ('active', 'completed', 'stopped')),
+        steps_sent INT NOT NULL DEFAULT 0,
+        -- NULL once the sequence is over.
+        next_position INT NULL,
+        next_send_at timestamptz NULL,
+        started_at timestamptz NOT NULL,
+        updated_at timestamptz NOT NULL,
+        PRIMARY KEY (sequence_id, subscriber_id)
+    );
+    CREATE INDEX automation_progress_due_idx
+        ON automation_progress (next_send_at) WHERE status = 'active';
+COMMIT;
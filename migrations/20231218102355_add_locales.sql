@@ -0,0 +1,14 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+    -- Optional translations of an issue, the issue itself being in the default locale.
+    CREATE TABLE newsletter_issue_variants(
+        newsletter_issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (newsletter_issue_id),
+        locale TEXT NOT NULL,
+        title TEXT NOT NULL,
+        text_content TEXT NOT NULL,
+        html_content TEXT NOT NULL,
+        PRIMARY KEY (newsletter_issue_id, locale)
+    );
+COMMIT;
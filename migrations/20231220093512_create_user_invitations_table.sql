@@ -0,0 +1,16 @@
+-- Add migration script here
+BEGIN;
+    -- The seeded admin has no email address.
+    ALTER TABLE users ADD COLUMN email TEXT UNIQUE;
+    ALTER TABLE users ADD COLUMN status TEXT NOT NULL DEFAULT 'active'
+        CHECK (status IN ('active', 'disabled'));
+    ALTER TABLE users ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+    CREATE TABLE user_invitations(
+        invitation_id uuid PRIMARY KEY,
+        email TEXT NOT NULL,
+        invited_by uuid REFERENCES users (user_id) ON DELETE SET NULL,
+        created_at timestamptz NOT NULL,
+        expires_at timestamptz NOT NULL,
+        accepted_at timestamptz
+    );
+COMMIT;
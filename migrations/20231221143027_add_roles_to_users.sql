@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    -- Existing users could do everything so far.
+    ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+        CHECK (role IN ('owner', 'editor', 'analyst'));
+    ALTER TABLE user_invitations ADD COLUMN role TEXT NOT NULL DEFAULT 'editor'
+        CHECK (role IN ('owner', 'editor', 'analyst'));
+COMMIT;
@@ -0,0 +1,16 @@
+-- Add migration script here
+BEGIN;
+    -- Base32-encoded, NULL until the user sets up an authenticator app.
+    ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+    -- Codes of this time step or before cannot be used again.
+    ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+    CREATE TABLE recovery_codes(
+        recovery_code_id uuid NOT NULL,
+        user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+        code_hash TEXT NOT NULL,
+        created_at timestamptz NOT NULL,
+        used_at timestamptz NULL,
+        PRIMARY KEY (recovery_code_id)
+    );
+    CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
+COMMIT;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE login_lockouts(
+    lockout_id uuid NOT NULL,
+    -- Lowercased as typed in, whether or not such a user exists.
+    username TEXT NULL,
+    ip_address TEXT NULL,
+    failed_attempts INT NOT NULL,
+    locked_at timestamptz NOT NULL,
+    locked_until timestamptz NOT NULL,
+    unlocked_at timestamptz NULL,
+    unlocked_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    PRIMARY KEY (lockout_id),
+    CHECK (username IS NOT NULL OR ip_address IS NOT NULL)
+);
+CREATE INDEX login_lockouts_locked_until_idx ON login_lockouts (locked_until);
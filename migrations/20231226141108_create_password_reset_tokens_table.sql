@@ -0,0 +1,14 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE password_reset_tokens(
+        -- SHA-256 of the token, which is only ever sent by email.
+        token_hash TEXT NOT NULL,
+        user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+        created_at timestamptz NOT NULL,
+        expires_at timestamptz NOT NULL,
+        used_at timestamptz NULL,
+        PRIMARY KEY (token_hash)
+    );
+    -- Bumped to log the user out of every session.
+    ALTER TABLE users ADD COLUMN session_epoch INTEGER NOT NULL DEFAULT 0;
+COMMIT;
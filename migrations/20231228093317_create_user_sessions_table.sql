@@ -0,0 +1,16 @@
+-- Add migration script here
+BEGIN;
+    CREATE TABLE user_sessions(
+        session_id uuid NOT NULL,
+        user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+        created_at timestamptz NOT NULL,
+        last_seen_at timestamptz NOT NULL,
+        ip_address TEXT NULL,
+        user_agent TEXT NULL,
+        revoked_at timestamptz NULL,
+        PRIMARY KEY (session_id)
+    );
+    CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+    -- Revoking the tracked sessions replaces bumping the epoch.
+    ALTER TABLE users DROP COLUMN session_epoch;
+COMMIT;
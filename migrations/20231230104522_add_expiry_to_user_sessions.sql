@@ -0,0 +1,7 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE user_sessions ADD COLUMN remember_me BOOLEAN NOT NULL DEFAULT false;
+    -- Sessions from before lifetimes were tracked end right away.
+    ALTER TABLE user_sessions ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now();
+    ALTER TABLE user_sessions ALTER COLUMN expires_at DROP DEFAULT;
+COMMIT;
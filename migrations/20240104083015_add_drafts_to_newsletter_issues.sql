@@ -0,0 +1,13 @@
+-- Add migration script here
+BEGIN;
+    -- Drafts are issues that have not been published yet.
+    ALTER TABLE newsletter_issues
+        ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+    ALTER TABLE newsletter_issues ADD COLUMN created_at timestamptz;
+    UPDATE newsletter_issues SET created_at = published_at;
+    ALTER TABLE newsletter_issues ALTER COLUMN created_at SET NOT NULL;
+    -- How many subscribers the issue was queued for when it was published,
+    -- unknown for the issues published before we started counting.
+    ALTER TABLE newsletter_issues ADD COLUMN recipients INTEGER;
+COMMIT;
@@ -0,0 +1,8 @@
+-- Add migration script here
+BEGIN;
+    -- Confirmation emails that fail to send are retried with a backoff.
+    ALTER TABLE confirmation_email_outbox
+        ADD COLUMN n_retries INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE confirmation_email_outbox
+        ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+COMMIT;
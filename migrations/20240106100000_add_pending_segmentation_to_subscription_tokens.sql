@@ -0,0 +1,9 @@
+-- Add migration script here
+BEGIN;
+    -- Tags and attributes submitted for an existing subscriber are only
+    -- applied once the owner of the address follows the confirmation link.
+    ALTER TABLE subscription_tokens
+        ADD COLUMN pending_tags TEXT[] NOT NULL DEFAULT '{}';
+    ALTER TABLE subscription_tokens
+        ADD COLUMN pending_attributes JSONB NOT NULL DEFAULT '{}';
+COMMIT;
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- The normalization rules `subscriptions.canonical_email` was last derived
+-- with. No row yet: the column was backfilled with `lower(email)` only.
+CREATE TABLE email_normalization_state(
+    id BOOLEAN NOT NULL DEFAULT TRUE,
+    PRIMARY KEY (id),
+    CONSTRAINT email_normalization_state_single_row CHECK (id),
+    fold_gmail_addresses BOOLEAN NOT NULL,
+    updated_at timestamptz NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Password reset requests are answered right away and mailed by the
+-- background worker, which also creates the token.
+CREATE TABLE password_reset_email_outbox(
+    outbox_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    enqueued_at timestamptz NOT NULL,
+    n_retries INTEGER NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- What the key was first used for: method, path and a hash of the payload.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
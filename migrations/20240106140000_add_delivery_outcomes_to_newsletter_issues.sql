@@ -0,0 +1,7 @@
+-- Add migration script here
+BEGIN;
+    -- Deliveries leave the queues whether they succeeded or not, so their
+    -- outcome is counted here.
+    ALTER TABLE newsletter_issues ADD COLUMN n_delivered INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE newsletter_issues ADD COLUMN n_failed INTEGER NOT NULL DEFAULT 0;
+COMMIT;
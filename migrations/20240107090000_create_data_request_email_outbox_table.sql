@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Data requests are answered right away and mailed by the background
+-- worker, which also creates the token.
+CREATE TABLE data_request_email_outbox(
+    outbox_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    enqueued_at timestamptz NOT NULL,
+    n_retries INTEGER NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,244 @@
+//! Bearer tokens for scripts and CI, accepted under `/api/v1`.
+//!
+//! A token acts on behalf of the user who created it, but only for its
+//! scopes, and only for those the role of the user still allows. Only a hash
+//! of each token is stored: it is shown once, when it is created.
+use crate::authentication::{Permission, Role};
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    SubscribersRead,
+    SubscribersWrite,
+    IssuesPublish,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+        ApiScope::IssuesPublish,
+    ];
+
+    pub fn parse(s: &str) -> Result<ApiScope, String> {
+        match s {
+            "subscribers:read" => Ok(Self::SubscribersRead),
+            "subscribers:write" => Ok(Self::SubscribersWrite),
+            "issues:publish" => Ok(Self::IssuesPublish),
+            other => Err(format!("{other} is not a valid scope.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+            ApiScope::IssuesPublish => "issues:publish",
+        }
+    }
+
+    /// The permission a role needs to use the scope.
+    pub fn permission(&self) -> Permission {
+        match self {
+            ApiScope::SubscribersRead | ApiScope::SubscribersWrite => Permission::ManageSubscribers,
+            ApiScope::IssuesPublish => Permission::PublishIssues,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("This token does not have the {0} scope.")]
+pub struct MissingScope(ApiScope);
+
+/// The token a request under `/api/v1` was authenticated with.
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    pub api_token_id: Uuid,
+    pub user_id: Uuid,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiClient {
+    pub fn require(&self, scope: ApiScope) -> Result<(), MissingScope> {
+        if self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(MissingScope(scope))
+        }
+    }
+}
+
+fn generate_api_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect()
+}
+
+fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Store a new token, returning it in clear for the only time.
+#[tracing::instrument(name = "Create an API token", skip(pool))]
+pub async fn create_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    expires_in_days: Option<i32>,
+) -> Result<String, anyhow::Error> {
+    let token = generate_api_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (
+            api_token_id, user_id, name, token_hash, scopes, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now(), now() + make_interval(days => $6))
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_api_token(&token),
+        &scopes[..],
+        expires_in_days
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok(token)
+}
+
+pub struct ApiTokenSummary {
+    pub api_token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// The tokens created by a user, newest first.
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiTokenSummary>, anyhow::Error> {
+    sqlx::query_as!(
+        ApiTokenSummary,
+        r#"
+        SELECT api_token_id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the API tokens.")
+}
+
+/// Returns `false` if the user has no such token left to revoke.
+#[tracing::instrument(name = "Revoke an API token", skip(pool))]
+pub async fn revoke_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    api_token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET revoked_at = now()
+        WHERE api_token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        api_token_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// `None` unless the token exists, was neither revoked nor has expired, and
+/// its user is active. Otherwise the token is marked as just used.
+#[tracing::instrument(name = "Authenticate an API token", skip(pool, token))]
+pub async fn authenticate_api_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<ApiClient>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens t
+        SET last_used_at = now()
+        FROM users u
+        WHERE
+            u.user_id = t.user_id AND
+            t.token_hash = $1 AND
+            t.revoked_at IS NULL AND
+            (t.expires_at IS NULL OR t.expires_at > now()) AND
+            u.status = 'active'
+        RETURNING t.api_token_id, t.user_id, t.scopes, u.role
+        "#,
+        hash_api_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to authenticate the API token.")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let role = Role::parse(&row.role).map_err(anyhow::Error::msg)?;
+    Ok(Some(ApiClient {
+        api_token_id: row.api_token_id,
+        user_id: row.user_id,
+        scopes: granted_scopes(&row.scopes, role),
+    }))
+}
+
+/// The stored scopes the role still allows.
+fn granted_scopes(scopes: &[String], role: Role) -> Vec<ApiScope> {
+    scopes
+        .iter()
+        .filter_map(|s| ApiScope::parse(s).ok())
+        .filter(|s| role.can(s.permission()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{granted_scopes, ApiScope};
+    use crate::authentication::Role;
+
+    #[test]
+    fn scopes_round_trip() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::parse(scope.as_str()), Ok(scope));
+        }
+        assert!(ApiScope::parse("subscribers:delete").is_err());
+    }
+
+    #[test]
+    fn tokens_lose_the_scopes_their_role_no_longer_allows() {
+        let scopes = vec!["subscribers:read".to_string(), "issues:publish".to_string()];
+        assert_eq!(
+            granted_scopes(&scopes, Role::Editor),
+            vec![ApiScope::SubscribersRead, ApiScope::IssuesPublish]
+        );
+        assert!(granted_scopes(&scopes, Role::Analyst).is_empty());
+    }
+}
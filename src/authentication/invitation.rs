@@ -0,0 +1,56 @@
+//! Links inviting someone to create an admin account.
+//!
+//! The link carries the invitation id and an HMAC tag of it, so ids cannot
+//! be guessed. Expiry and one-time use are enforced with `user_invitations`.
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+/// Invitations that were not accepted within this many days stop working.
+pub const INVITATION_VALIDITY_DAYS: i64 = 7;
+
+fn invitation_mac(secret: &Secret<String>, invitation_id: Uuid) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("invitation:{invitation_id}").as_bytes());
+    mac
+}
+
+/// The tag to put in the link of an invitation, hex-encoded.
+pub fn invitation_tag(secret: &Secret<String>, invitation_id: Uuid) -> String {
+    hex::encode(
+        invitation_mac(secret, invitation_id)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Check a tag in constant time.
+pub fn verify_invitation_tag(secret: &Secret<String>, invitation_id: Uuid, tag: &str) -> bool {
+    let Ok(tag) = hex::decode(tag) else {
+        return false;
+    };
+    invitation_mac(secret, invitation_id)
+        .verify_slice(&tag)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{invitation_tag, verify_invitation_tag};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    #[test]
+    fn tags_are_only_valid_for_their_invitation_and_secret() {
+        let secret = Secret::new("a-very-long-secret".to_string());
+        let invitation_id = Uuid::new_v4();
+        let tag = invitation_tag(&secret, invitation_id);
+
+        assert!(verify_invitation_tag(&secret, invitation_id, &tag));
+        assert!(!verify_invitation_tag(&secret, Uuid::new_v4(), &tag));
+        let other_secret = Secret::new("another-secret".to_string());
+        assert!(!verify_invitation_tag(&other_secret, invitation_id, &tag));
+        assert!(!verify_invitation_tag(&secret, invitation_id, "not hex"));
+    }
+}
@@ -0,0 +1,224 @@
+//! Brute-force protection for `/login`.
+//!
+//! Failed logins are counted per username and per IP address. Each failure
+//! is answered a bit more slowly than the previous one, and reaching the
+//! threshold locks the username or the address out for a while. Lockouts
+//! are logged in `login_lockouts`, where admins can lift them.
+//!
+//! Usernames are counted as typed in, whether or not such a user exists,
+//! so the answers do not tell whether a username is taken.
+use crate::configuration::AuthenticationSettings;
+use crate::rate_limit::RateLimiter;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Failed logins are never answered more slowly than this.
+const MAX_FAILED_LOGIN_DELAY: Duration = Duration::from_secs(5);
+
+pub struct LoginThrottle {
+    failures: RateLimiter,
+    settings: AuthenticationSettings,
+}
+
+impl LoginThrottle {
+    pub async fn build(
+        settings: AuthenticationSettings,
+        redis_uri: &Secret<String>,
+    ) -> Result<LoginThrottle, anyhow::Error> {
+        let failures = RateLimiter::build(
+            settings.failed_login_backend,
+            Duration::from_secs(settings.failed_login_window_seconds),
+            redis_uri,
+        )
+        .await?;
+        Ok(Self { failures, settings })
+    }
+
+    /// Whether logins for `username` or from `ip` are locked out.
+    #[tracing::instrument(name = "Check login lockouts", skip(self, pool))]
+    pub async fn is_locked_out(
+        &self,
+        pool: &PgPool,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Result<bool, anyhow::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT lockout_id FROM login_lockouts
+            WHERE
+                (username = $1 OR ip_address = $2) AND
+                locked_until > now() AND
+                unlocked_at IS NULL
+            LIMIT 1
+            "#,
+            normalize(username),
+            ip
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to check the login lockouts.")?;
+        Ok(row.is_some())
+    }
+
+    /// Count a failed login, locking the username or the address out when it
+    /// reaches its threshold. Returns how long to wait before answering.
+    #[tracing::instrument(name = "Record a failed login", skip(self, pool))]
+    pub async fn record_failure(
+        &self,
+        pool: &PgPool,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Result<Duration, anyhow::Error> {
+        let username = normalize(username);
+        let username_key = username_key(&username);
+        let username_failures = self.failures.count(&username_key).await?;
+        // The counters go up one at a time, so each lockout is only logged once.
+        if username_failures == self.settings.max_failed_logins_per_username {
+            self.lock_out(pool, Some(&username), None, username_failures)
+                .await?;
+            self.failures.reset(&username_key).await?;
+        }
+        if let Some(ip) = ip {
+            let ip_key = ip_key(ip);
+            let ip_failures = self.failures.count(&ip_key).await?;
+            if ip_failures == self.settings.max_failed_logins_per_ip {
+                self.lock_out(pool, None, Some(ip), ip_failures).await?;
+                self.failures.reset(&ip_key).await?;
+            }
+        }
+        Ok(failed_login_delay(
+            Duration::from_millis(self.settings.failed_login_delay_milliseconds),
+            username_failures,
+        ))
+    }
+
+    /// Forget the failures of a username once its user logged in.
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        self.failures
+            .reset(&username_key(&normalize(username)))
+            .await
+    }
+
+    async fn lock_out(
+        &self,
+        pool: &PgPool,
+        username: Option<&str>,
+        ip: Option<&str>,
+        failed_attempts: u32,
+    ) -> Result<(), anyhow::Error> {
+        tracing::warn!(?username, ?ip, "Locking out logins after too many failures");
+        sqlx::query!(
+            r#"
+            INSERT INTO login_lockouts (
+                lockout_id, username, ip_address, failed_attempts, locked_at, locked_until
+            )
+            VALUES ($1, $2, $3, $4, now(), now() + make_interval(secs => $5))
+            "#,
+            Uuid::new_v4(),
+            username,
+            ip,
+            failed_attempts as i32,
+            self.settings.lockout_seconds as f64
+        )
+        .execute(pool)
+        .await
+        .context("Failed to store a login lockout.")?;
+        Ok(())
+    }
+
+    /// Lift a lockout before it expires, with a clean slate of attempts.
+    /// Returns `false` if there is no such active lockout.
+    #[tracing::instrument(name = "Lift a login lockout", skip(self, pool))]
+    pub async fn unlock(
+        &self,
+        pool: &PgPool,
+        lockout_id: Uuid,
+        unlocked_by: Uuid,
+    ) -> Result<bool, anyhow::Error> {
+        let lockout = sqlx::query!(
+            r#"
+            UPDATE login_lockouts
+            SET unlocked_at = now(), unlocked_by = $2
+            WHERE lockout_id = $1 AND unlocked_at IS NULL AND locked_until > now()
+            RETURNING username, ip_address
+            "#,
+            lockout_id,
+            unlocked_by
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to lift the login lockout.")?;
+        let Some(lockout) = lockout else {
+            return Ok(false);
+        };
+        if let Some(username) = lockout.username {
+            self.failures.reset(&username_key(&username)).await?;
+        }
+        if let Some(ip) = lockout.ip_address {
+            self.failures.reset(&ip_key(&ip)).await?;
+        }
+        Ok(true)
+    }
+
+    /// Lift the lockouts of a user's username once they reset their password,
+    /// with a clean slate of attempts. Lockouts of addresses are left alone.
+    #[tracing::instrument(name = "Lift the login lockouts of a user", skip(self, pool))]
+    pub async fn unlock_user(&self, pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+        let username = sqlx::query!("SELECT username FROM users WHERE user_id = $1", user_id)
+            .fetch_one(pool)
+            .await
+            .context("Failed to retrieve the username.")?
+            .username;
+        let username = normalize(&username);
+        sqlx::query!(
+            r#"
+            UPDATE login_lockouts
+            SET unlocked_at = now(), unlocked_by = $2
+            WHERE username = $1 AND unlocked_at IS NULL AND locked_until > now()
+            "#,
+            username,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to lift the login lockouts.")?;
+        self.failures.reset(&username_key(&username)).await
+    }
+}
+
+/// Usernames differing only by case share their counter.
+fn normalize(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+fn username_key(username: &str) -> String {
+    format!("login:username:{username}")
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("login:ip:{ip}")
+}
+
+/// `base` after the first failure, doubled after each of the next ones.
+fn failed_login_delay(base: Duration, failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    base.saturating_mul(factor).min(MAX_FAILED_LOGIN_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::failed_login_delay;
+    use std::time::Duration;
+
+    #[test]
+    fn the_delay_doubles_after_each_failure_up_to_a_maximum() {
+        let base = Duration::from_millis(100);
+        assert_eq!(failed_login_delay(base, 1), Duration::from_millis(100));
+        assert_eq!(failed_login_delay(base, 2), Duration::from_millis(200));
+        assert_eq!(failed_login_delay(base, 4), Duration::from_millis(800));
+        assert_eq!(failed_login_delay(base, 40), Duration::from_secs(5));
+    }
+}
@@ -1,13 +1,24 @@
-use crate::session_state::TypedSession;
+use crate::authentication::{authenticate_api_token, Role};
+use crate::configuration::{AuthenticationSettings, SessionSettings};
+use crate::routes::{json_error_response, ApiError};
+use crate::session_state::{TypedSession, SESSION_COOKIE_NAME};
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, SET_COOKIE, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpMessage};
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
+/// The pages users who must set up two-factor authentication can still open.
+const ALLOWED_WITHOUT_TWO_FACTOR: [&str; 2] = ["/admin/two-factor", "/admin/logout"];
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -17,9 +28,59 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user = match (
+        session.get_user_id().map_err(e500)?,
+        session.get_session_id().map_err(e500)?,
+    ) {
+        (Some(user_id), Some(session_id)) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .ok_or_else(|| e500("The connection pool is missing"))?;
+            let session_settings = req
+                .app_data::<web::Data<SessionSettings>>()
+                .ok_or_else(|| e500("The session settings are missing"))?;
+            // Accounts disabled or deleted since logging in lose access right away,
+            // and so do sessions revoked from elsewhere or expired.
+            match get_active_user(pool, user_id, session_id, session_settings)
+                .await
+                .map_err(e500)?
+            {
+                Some(user) => Some((user_id, session_id, user)),
+                None => {
+                    session.log_out();
+                    None
+                }
+            }
+        }
+        // Sessions from before they were tracked are logged out.
+        (Some(_), None) => {
+            session.log_out();
+            None
+        }
+        (None, _) => None,
+    };
+    match user {
+        Some((user_id, session_id, user)) => {
+            let settings = req
+                .app_data::<web::Data<AuthenticationSettings>>()
+                .ok_or_else(|| e500("The authentication settings are missing"))?;
+            if settings.require_two_factor
+                && !user.two_factor_enabled
+                && !ALLOWED_WITHOUT_TWO_FACTOR.contains(&req.path())
+            {
+                let response = see_other("/admin/two-factor");
+                let e = anyhow::anyhow!("The user has not set up two-factor authentication");
+                return Err(InternalError::from_response(e, response).into());
+            }
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(SessionId(session_id));
+            if let Some(remembered_until) = user.remembered_until {
+                let remaining = (remembered_until - chrono::Utc::now())
+                    .to_std()
+                    .unwrap_or_default();
+                req.extensions_mut().insert(RememberedSession(remaining));
+            }
+            req.extensions_mut().insert(user.role);
             next.call(req).await
         }
         None => {
@@ -30,6 +91,43 @@ pub async fn reject_anonymous_users(
     }
 }
 
+/// Authenticate requests with the API token in their `Authorization: Bearer`
+/// header, instead of a session.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_owned());
+    let Some(token) = token else {
+        return Err(unauthorized("A bearer token is required."));
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The connection pool is missing"))?;
+    match authenticate_api_token(pool, &token).await.map_err(e500)? {
+        Some(client) => {
+            req.extensions_mut().insert(client);
+            next.call(req).await
+        }
+        None => Err(unauthorized(
+            "The token is invalid, has expired or has been revoked.",
+        )),
+    }
+}
+
+fn unauthorized(message: &'static str) -> actix_web::Error {
+    let mut response = json_error_response(StatusCode::UNAUTHORIZED, vec![ApiError::new(message)]);
+    response
+        .headers_mut()
+        .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    InternalError::from_response(anyhow::anyhow!(message), response).into()
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct UserId(Uuid);
 
@@ -45,3 +143,99 @@ impl Deref for UserId {
         &self.0
     }
 }
+
+/// The session the current request was made in, as tracked in `user_sessions`.
+#[derive(Copy, Clone, Debug)]
+pub struct SessionId(Uuid);
+
+impl Deref for SessionId {
+    type Target = Uuid;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Set on requests made in a session the user asked to be remembered in,
+/// with what is left of its lifetime.
+#[derive(Copy, Clone, Debug)]
+pub struct RememberedSession(pub std::time::Duration);
+
+/// The session cookie is a browser session cookie, except for remembered
+/// sessions, whose cookie is kept until the session expires.
+pub async fn persist_remembered_sessions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut res = next.call(req).await?;
+    let Some(RememberedSession(lifetime)) = res.request().extensions().get().copied() else {
+        return Ok(res);
+    };
+    let max_age = actix_web::cookie::time::Duration::seconds(lifetime.as_secs() as i64);
+    let headers = res.headers_mut();
+    let cookies: Vec<HeaderValue> = headers.get_all(SET_COOKIE).cloned().collect();
+    headers.remove(SET_COOKIE);
+    for value in cookies {
+        let value = match value.to_str().map(Cookie::parse) {
+            // Removal cookies already have a max age, and are left alone.
+            Ok(Ok(mut cookie))
+                if cookie.name() == SESSION_COOKIE_NAME && cookie.max_age().is_none() =>
+            {
+                cookie.set_max_age(max_age);
+                HeaderValue::from_str(&cookie.to_string()).map_err(e500)?
+            }
+            _ => value,
+        };
+        headers.append(SET_COOKIE, value);
+    }
+    Ok(res)
+}
+
+struct ActiveUser {
+    role: Role,
+    two_factor_enabled: bool,
+    /// When the session expires, if the user asked to be remembered.
+    remembered_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// `None` if the account of the user is no longer active or the session
+/// cannot be used anymore. Otherwise the session is marked as just used.
+#[tracing::instrument(name = "Get an active user", skip(pool, settings))]
+async fn get_active_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+    settings: &SessionSettings,
+) -> Result<Option<ActiveUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_sessions s
+        SET last_seen_at = now()
+        FROM users u
+        WHERE
+            u.user_id = s.user_id AND
+            s.session_id = $2 AND
+            s.user_id = $1 AND
+            s.revoked_at IS NULL AND
+            s.expires_at > now() AND
+            (s.remember_me OR s.last_seen_at > now() - make_interval(mins => $3)) AND
+            u.status = 'active'
+        RETURNING
+            u.role, u.totp_secret IS NOT NULL AS "two_factor_enabled!", s.remember_me,
+            s.expires_at
+        "#,
+        user_id,
+        session_id,
+        settings.idle_timeout_minutes
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to check the status of the user.")?;
+    row.map(|r| {
+        Ok(ActiveUser {
+            role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+            two_factor_enabled: r.two_factor_enabled,
+            remembered_until: r.remember_me.then_some(r.expires_at),
+        })
+    })
+    .transpose()
+}
@@ -1,5 +1,34 @@
+mod api_tokens;
+mod invitation;
+mod login_throttle;
 mod middleware;
 mod password;
+mod password_reset;
+mod roles;
+mod sessions;
+mod two_factor;
 
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use api_tokens::{
+    authenticate_api_token, create_api_token, list_api_tokens, revoke_api_token, ApiClient,
+    ApiScope, ApiTokenSummary, MissingScope,
+};
+pub use invitation::{invitation_tag, verify_invitation_tag, INVITATION_VALIDITY_DAYS};
+pub use login_throttle::LoginThrottle;
+pub use middleware::{
+    persist_remembered_sessions, reject_anonymous_users, reject_invalid_api_tokens,
+    RememberedSession, SessionId, UserId,
+};
+pub use password::{change_password, create_user, validate_credentials, AuthError, Credentials};
+pub use password_reset::{
+    create_password_reset_token, enqueue_password_reset, get_password_reset_username,
+    reset_password, PASSWORD_RESET_VALIDITY_MINUTES,
+};
+pub use roles::{AccessDenied, Permission, Role};
+pub use sessions::{
+    active_sessions, end_all_sessions, end_other_sessions, revoke_session, start_session,
+    UserSession,
+};
+pub use two_factor::{
+    generate_totp_secret, get_totp_secret, provisioning_qr_code, remove_totp_secret,
+    store_totp_secret, totp_step, unix_time, unused_recovery_codes, verify_second_factor,
+};
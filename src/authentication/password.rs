@@ -1,3 +1,5 @@
+use crate::authentication::Role;
+use crate::configuration::PasswordHashingSettings;
 use crate::telemetry::spawn_blocking_with_tracing;
 use actix_web::http::header::HeaderValue;
 use actix_web::http::{header, StatusCode};
@@ -7,7 +9,8 @@ use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::ExposeSecret;
 use secrecy::Secret;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use tracing::Instrument;
 use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug)]
@@ -40,18 +43,22 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// Once the password is verified, a hash weaker than `hashing` asks for is
+/// replaced in the background, so raising the cost needs no password reset.
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool, hashing))]
 pub async fn validate_credentials(
     credentials: Credentials,
     pool: &PgPool,
+    hashing: &PasswordHashingSettings,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
+    // Unknown usernames take as long to reject as wrong passwords.
+    let mut expected_password_hash = Secret::new(format!(
+        "$argon2id$v=19$m={},t={},p={}$\
                 gZiV/M1gPc22ElAH/Jh1Hw$\
-                CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+                CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+        hashing.memory_kib, hashing.iterations, hashing.parallelism
+    ));
 
     if let Some((store_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool)
@@ -62,29 +69,52 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash;
     }
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let settings = hashing.clone();
+    let (outcome, password, stored_password_hash) = spawn_blocking_with_tracing(move || {
+        let outcome =
+            verify_password_hash(&expected_password_hash, &credentials.password, &settings);
+        (outcome, credentials.password, expected_password_hash)
     })
     .await
     .context("Failed to spawn blocking task")
-    .map_err(AuthError::UnexpectedError)?
-    .await
-    .context("")
-    .map_err(AuthError::InvalidCredentials)?;
+    .map_err(AuthError::UnexpectedError)?;
+    let needs_upgrade = outcome.context("").map_err(AuthError::InvalidCredentials)?;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    if needs_upgrade {
+        let pool = pool.clone();
+        let hashing = hashing.clone();
+        tokio::spawn(
+            async move {
+                let upgrade =
+                    upgrade_password_hash(&pool, user_id, stored_password_hash, password, hashing);
+                if let Err(e) = upgrade.await {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to upgrade a password hash. \
+                        It will be retried on the next login.",
+                    );
+                }
+            }
+            .in_current_span(),
+        );
+    }
+    Ok(user_id)
 }
 
+/// Returns whether the hash should be upgraded to `hashing`.
 #[tracing::instrument(
     name = "verify password hash",
-    skip(expected_password_hash, password_candidate)
+    skip(expected_password_hash, password_candidate, hashing)
 )]
-async fn verify_password_hash(
-    expected_password_hash: Secret<String>,
-    password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
+fn verify_password_hash(
+    expected_password_hash: &Secret<String>,
+    password_candidate: &Secret<String>,
+    hashing: &PasswordHashingSettings,
+) -> Result<bool, AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
 
@@ -94,7 +124,59 @@ async fn verify_password_hash(
             &expected_password_hash,
         )
         .context("Invalid Password")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    Ok(is_outdated(&expected_password_hash, hashing))
+}
+
+/// Whether a hash uses another algorithm than Argon2id v1.3, or any cost
+/// below the configured one.
+fn is_outdated(password_hash: &PasswordHash<'_>, hashing: &PasswordHashingSettings) -> bool {
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    match Params::try_from(password_hash) {
+        Ok(params) => {
+            params.m_cost() < hashing.memory_kib
+                || params.t_cost() < hashing.iterations
+                || params.p_cost() < hashing.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+/// Replace the hash of a password that was just verified, unless the
+/// password changed in the meantime.
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(pool, previous_password_hash, password, hashing)
+)]
+async fn upgrade_password_hash(
+    pool: &PgPool,
+    user_id: Uuid,
+    previous_password_hash: Secret<String>,
+    password: Secret<String>,
+    hashing: PasswordHashingSettings,
+) -> Result<(), anyhow::Error> {
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await?
+            .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        previous_password_hash.expose_secret()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the upgraded password hash")?;
+    Ok(())
 }
 
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
@@ -106,7 +188,7 @@ async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND status = 'active'
         "#,
         username
     )
@@ -117,15 +199,18 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
-pub async fn change_password(
+#[tracing::instrument(name = "Change password", skip(password, hashing, executor))]
+pub async fn change_password<'c>(
     user_id: Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    hashing: &PasswordHashingSettings,
+    executor: impl PgExecutor<'c>,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await?
+            .context("Failed to hash password")?;
     sqlx::query!(
         r#"
         UPDATE users
@@ -135,21 +220,56 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change uers's password in the database")?;
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+/// Create an active admin account.
+#[tracing::instrument(name = "Create user", skip(transaction, password, hashing))]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: &str,
+    role: Role,
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+) -> Result<Uuid, anyhow::Error> {
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await?
+            .context("Failed to hash password")?;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password_hash, role, status, created_at)
+        VALUES ($1, $2, $3, $4, $5, 'active', now())
+        "#,
+        user_id,
+        username,
+        email,
+        password_hash.expose_secret(),
+        role.as_str()
     )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    .execute(transaction)
+    .await
+    .context("Failed to store the new user in the database")?;
+    Ok(user_id)
+}
+
+pub(super) fn compute_password_hash(
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let params = hashing
+        .params()
+        .context("Invalid password hashing parameters")?;
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
 
@@ -186,3 +306,54 @@ fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, any
 //         password: Secret::new(password),
 //     })
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_password_hash, is_outdated};
+    use crate::configuration::PasswordHashingSettings;
+    use argon2::password_hash::SaltString;
+    use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version};
+    use secrecy::{ExposeSecret, Secret};
+
+    fn settings(memory_kib: u32, iterations: u32) -> PasswordHashingSettings {
+        PasswordHashingSettings {
+            memory_kib,
+            iterations,
+            parallelism: 1,
+        }
+    }
+
+    fn hash_with(algorithm: Algorithm, memory_kib: u32, iterations: u32) -> String {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let params = Params::new(memory_kib, iterations, 1, None).unwrap();
+        Argon2::new(algorithm, Version::V0x13, params)
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn new_hashes_use_the_configured_parameters() {
+        let hash = compute_password_hash(Secret::new("password".into()), &settings(64, 2)).unwrap();
+        let hash = PasswordHash::new(hash.expose_secret()).unwrap();
+        assert!(!is_outdated(&hash, &settings(64, 2)));
+        assert_eq!(Params::try_from(&hash).unwrap().m_cost(), 64);
+    }
+
+    #[test]
+    fn hashes_with_a_lower_cost_are_outdated() {
+        let hash = hash_with(Algorithm::Argon2id, 64, 1);
+        let hash = PasswordHash::new(&hash).unwrap();
+        assert!(is_outdated(&hash, &settings(128, 1)));
+        assert!(is_outdated(&hash, &settings(64, 2)));
+        // Lowering the configured cost does not downgrade existing hashes.
+        assert!(!is_outdated(&hash, &settings(32, 1)));
+    }
+
+    #[test]
+    fn hashes_from_other_argon2_variants_are_outdated() {
+        let hash = hash_with(Algorithm::Argon2i, 64, 1);
+        let hash = PasswordHash::new(&hash).unwrap();
+        assert!(is_outdated(&hash, &settings(64, 1)));
+    }
+}
@@ -0,0 +1,159 @@
+//! Tokens mailed to users who forgot their password.
+//!
+//! Only a hash of each token is stored, so a leaked database cannot be used
+//! to reset passwords. Tokens expire and work once.
+use crate::authentication::{change_password, end_all_sessions};
+use crate::configuration::PasswordHashingSettings;
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Reset links stop working after this many minutes.
+pub const PASSWORD_RESET_VALIDITY_MINUTES: i64 = 60;
+
+fn generate_reset_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+fn hash_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Queue a reset email for the active user with this email address,
+/// if there is one. The token is only created when the email is sent.
+#[tracing::instrument(name = "Enqueue a password reset email", skip(pool))]
+pub async fn enqueue_password_reset(pool: &PgPool, email: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_email_outbox (outbox_id, user_id, enqueued_at)
+        SELECT $1, user_id, now()
+        FROM users
+        WHERE lower(email) = lower($2) AND status = 'active'
+        "#,
+        Uuid::new_v4(),
+        email
+    )
+    .execute(pool)
+    .await
+    .context("Failed to enqueue the password reset email.")?;
+    Ok(())
+}
+
+/// Store a new reset token for a user and return it.
+#[tracing::instrument(name = "Create a password reset token", skip(transaction))]
+pub async fn create_password_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let token = generate_reset_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, now(), now() + make_interval(mins => $3))
+        "#,
+        hash_reset_token(&token),
+        user_id,
+        PASSWORD_RESET_VALIDITY_MINUTES as i32
+    )
+    .execute(transaction)
+    .await?;
+    Ok(token)
+}
+
+/// The username of the user a token was sent to, if it can still be used.
+#[tracing::instrument(name = "Check a password reset token", skip(pool, token))]
+pub async fn get_password_reset_username(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT u.username
+        FROM password_reset_tokens t
+        JOIN users u ON u.user_id = t.user_id
+        WHERE
+            t.token_hash = $1 AND
+            t.used_at IS NULL AND
+            t.expires_at > now() AND
+            u.status = 'active'
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the password reset token.")?;
+    Ok(row.map(|r| r.username))
+}
+
+/// Use up a token to set a new password, logging the user out everywhere
+/// along with the other tokens they were sent.
+/// Returns `None` if the token cannot be used.
+#[tracing::instrument(name = "Reset a password", skip(pool, token, password, hashing))]
+pub async fn reset_password(
+    pool: &PgPool,
+    token: &str,
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Claiming the token in the transaction makes it usable only once.
+    let claimed = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens t
+        SET used_at = now()
+        FROM users u
+        WHERE
+            u.user_id = t.user_id AND
+            t.token_hash = $1 AND
+            t.used_at IS NULL AND
+            t.expires_at > now() AND
+            u.status = 'active'
+        RETURNING t.user_id
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to claim the password reset token.")?;
+    let Some(claimed) = claimed else {
+        return Ok(None);
+    };
+    change_password(claimed.user_id, password, hashing, &mut transaction).await?;
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        claimed.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to invalidate the other password reset tokens.")?;
+    end_all_sessions(&mut transaction, claimed.user_id).await?;
+    transaction.commit().await?;
+    Ok(Some(claimed.user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_reset_token, hash_reset_token};
+
+    #[test]
+    fn tokens_are_random_and_stored_hashed() {
+        let token = generate_reset_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, generate_reset_token());
+        let hash = hash_reset_token(&token);
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, token);
+        assert_eq!(hash, hash_reset_token(&token));
+    }
+}
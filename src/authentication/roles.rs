@@ -0,0 +1,161 @@
+//! What each admin user is allowed to do.
+//!
+//! Every user has a role, which grants a fixed set of permissions. Handlers
+//! under `/admin` check the permission they need with [`Role::require`].
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Read-only overviews of the lists and automation sequences.
+    ViewStats,
+    PublishIssues,
+    /// Create lists and sequences, change their settings.
+    ManageLists,
+    /// Browse, edit, import, export and erase subscribers.
+    ManageSubscribers,
+    /// Settings affecting every list, such as blocked email domains.
+    ManageSettings,
+    ManageUsers,
+}
+
+impl Permission {
+    fn description(&self) -> &'static str {
+        match self {
+            Permission::ViewStats => "view statistics",
+            Permission::PublishIssues => "publish newsletter issues",
+            Permission::ManageLists => "manage mailing lists and sequences",
+            Permission::ManageSubscribers => "manage subscribers",
+            Permission::ManageSettings => "manage settings",
+            Permission::ManageUsers => "manage users",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Owner,
+    Editor,
+    Analyst,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Analyst];
+
+    pub fn parse(s: &str) -> Result<Role, String> {
+        match s {
+            "owner" => Ok(Self::Owner),
+            "editor" => Ok(Self::Editor),
+            "analyst" => Ok(Self::Analyst),
+            other => Err(format!("{other} is not a valid role.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Analyst => "analyst",
+        }
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Owner => &[
+                ViewStats,
+                PublishIssues,
+                ManageLists,
+                ManageSubscribers,
+                ManageSettings,
+                ManageUsers,
+            ],
+            Role::Editor => &[ViewStats, PublishIssues, ManageLists, ManageSubscribers],
+            Role::Analyst => &[ViewStats],
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+
+    pub fn require(&self, permission: Permission) -> Result<(), AccessDenied> {
+        if self.can(permission) {
+            Ok(())
+        } else {
+            Err(AccessDenied {
+                role: *self,
+                permission,
+            })
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Your role ({role}) does not allow you to {}.", .permission.description())]
+pub struct AccessDenied {
+    role: Role,
+    permission: Permission,
+}
+
+impl ResponseError for AccessDenied {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type(ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Access denied</title>
+    </head>
+    <body>
+        <p>{self}</p>
+        <p>Please ask an owner if you need access.</p>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn analysts_can_only_view_statistics() {
+        assert_ok!(Role::Analyst.require(Permission::ViewStats));
+        assert_err!(Role::Analyst.require(Permission::PublishIssues));
+        assert_err!(Role::Analyst.require(Permission::ManageSubscribers));
+    }
+
+    #[test]
+    fn only_owners_manage_users_and_settings() {
+        for role in Role::ALL {
+            let is_owner = role == Role::Owner;
+            assert_eq!(role.can(Permission::ManageUsers), is_owner);
+            assert_eq!(role.can(Permission::ManageSettings), is_owner);
+        }
+    }
+
+    #[test]
+    fn roles_round_trip_through_their_name() {
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert_err!(Role::parse("admin"));
+    }
+}
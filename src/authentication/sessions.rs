@@ -0,0 +1,143 @@
+//! The sessions users are logged in to.
+//!
+//! Session data lives in Redis, where it cannot be listed per user, so every
+//! login is also tracked in `user_sessions`. A session stops working as soon
+//! as its row is revoked, expires or, unless the user asked to be remembered,
+//! goes unused for longer than the idle timeout.
+use crate::configuration::SessionSettings;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_seen_at: chrono::DateTime<chrono::Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub remember_me: bool,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Track a new session for a user who is logging in.
+#[tracing::instrument(name = "Start a session", skip(pool, settings))]
+pub async fn start_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    remember_me: bool,
+    settings: &SessionSettings,
+) -> Result<Uuid, anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (
+            session_id, user_id, created_at, last_seen_at, ip_address, user_agent,
+            remember_me, expires_at
+        )
+        VALUES ($1, $2, now(), now(), $3, $4, $5, now() + make_interval(secs => $6))
+        "#,
+        session_id,
+        user_id,
+        ip_address,
+        user_agent,
+        remember_me,
+        settings.lifetime(remember_me).as_secs_f64()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the session.")?;
+    Ok(session_id)
+}
+
+/// The sessions of a user that can still be used, most recently used first.
+#[tracing::instrument(name = "List the sessions of a user", skip(pool, settings))]
+pub async fn active_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    settings: &SessionSettings,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT
+            session_id, created_at, last_seen_at, ip_address, user_agent, remember_me,
+            expires_at
+        FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            revoked_at IS NULL AND
+            expires_at > now() AND
+            (remember_me OR last_seen_at > now() - make_interval(mins => $2))
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        settings.idle_timeout_minutes
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the sessions of the user.")
+}
+
+/// Log the user out of one of their sessions.
+/// Returns `false` if they have no such active session.
+#[tracing::instrument(name = "Revoke a session", skip(pool))]
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the session.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Log the user out of all their sessions but `current_session_id`.
+#[tracing::instrument(name = "End other sessions", skip(executor))]
+pub async fn end_other_sessions<'c>(
+    executor: impl PgExecutor<'c>,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE user_id = $1 AND session_id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        current_session_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to end the other sessions of the user.")?;
+    Ok(result.rows_affected())
+}
+
+/// Log the user out of all their sessions.
+#[tracing::instrument(name = "End all sessions", skip(executor))]
+pub async fn end_all_sessions<'c>(
+    executor: impl PgExecutor<'c>,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to end the sessions of the user.")?;
+    Ok(())
+}
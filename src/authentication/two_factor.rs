@@ -0,0 +1,354 @@
+//! Time-based one-time passwords (RFC 6238) as a second login factor.
+//!
+//! Users set up an authenticator app by scanning a QR code of their secret.
+//! Each code works once: the time step of the last accepted code is stored
+//! in `users`. Recovery codes, hashed like passwords, let users who lost
+//! their app log in; each of them works once too.
+use crate::authentication::password::compute_password_hash;
+use crate::configuration::PasswordHashingSettings;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+/// Shown next to the account name in authenticator apps.
+const TOTP_ISSUER: &str = "Newsletter";
+const TOTP_STEP_SECONDS: u64 = 30;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Unambiguous characters, so codes can be copied from paper.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A new random secret of 160 bits, base32-encoded as authenticator apps expect.
+pub fn generate_totp_secret() -> Secret<String> {
+    let bytes: [u8; 20] = rand::thread_rng().gen();
+    Secret::new(
+        totp_rs::Secret::Raw(bytes.to_vec())
+            .to_encoded()
+            .to_string(),
+    )
+}
+
+fn totp(secret: &Secret<String>, account_name: &str) -> Result<TOTP, anyhow::Error> {
+    let bytes = totp_rs::Secret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {e}"))?;
+    // `:` separates the issuer from the account name in provisioning URIs.
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        TOTP_STEP_SECONDS,
+        bytes,
+        Some(TOTP_ISSUER.into()),
+        account_name.replace(':', "_"),
+    )
+    .context("Invalid TOTP parameters")
+}
+
+/// The `otpauth://` URI authenticator apps import the secret from.
+pub fn provisioning_uri(secret: &Secret<String>, username: &str) -> Result<String, anyhow::Error> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+/// The provisioning URI as an SVG QR code.
+pub fn provisioning_qr_code(
+    secret: &Secret<String>,
+    username: &str,
+) -> Result<String, anyhow::Error> {
+    let uri = provisioning_uri(secret, username)?;
+    let code = qrcode::QrCode::new(uri.as_bytes()).context("Failed to encode the QR code")?;
+    let svg = code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    // Inlined in HTML pages, which do not take the XML declaration.
+    match svg.find("<svg") {
+        Some(start) => Ok(svg[start..].to_string()),
+        None => Ok(svg),
+    }
+}
+
+/// The time step `code` was generated for, if it is valid at `unix_time`.
+/// Codes of the previous and next steps are accepted for clock drift.
+pub fn totp_step(
+    secret: &Secret<String>,
+    code: &str,
+    unix_time: u64,
+) -> Result<Option<u64>, anyhow::Error> {
+    let totp = totp(secret, "")?;
+    let code = code.trim();
+    let current_step = unix_time / TOTP_STEP_SECONDS;
+    Ok(
+        (current_step.saturating_sub(1)..=current_step + 1).find(|step| {
+            let expected = totp.generate(step * TOTP_STEP_SECONDS);
+            constant_time_eq::constant_time_eq(expected.as_bytes(), code.as_bytes())
+        }),
+    )
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is set after 1970")
+        .as_secs()
+}
+
+/// New recovery codes, in the `xxxxx-xxxxx` format.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())])
+                .map(char::from)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Whether `code` is in the `xxxxx-xxxxx` format of recovery codes.
+fn is_recovery_code(code: &str) -> bool {
+    code.len() == 11
+        && code.bytes().enumerate().all(|(i, b)| {
+            if i == 5 {
+                b == b'-'
+            } else {
+                RECOVERY_CODE_ALPHABET.contains(&b)
+            }
+        })
+}
+
+/// The TOTP secret of an active user, `None` if they have not set up 2FA.
+#[tracing::instrument(name = "Get TOTP secret", skip(pool))]
+pub async fn get_totp_secret(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<Secret<String>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1 AND status = 'active'"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret.")?;
+    Ok(row.and_then(|r| r.totp_secret).map(Secret::new))
+}
+
+/// Turn on 2FA for a user whose first code was valid at `step`, and return
+/// their recovery codes. Codes from a previous setup stop working.
+#[tracing::instrument(name = "Store TOTP secret", skip(transaction, secret, hashing))]
+pub async fn store_totp_secret(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    secret: &Secret<String>,
+    step: u64,
+    hashing: &PasswordHashingSettings,
+) -> Result<Vec<String>, anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = $2, totp_last_used_step = $3 WHERE user_id = $1"#,
+        user_id,
+        secret.expose_secret(),
+        step as i64
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the previous recovery codes.")?;
+
+    let codes = generate_recovery_codes();
+    let to_hash = codes.clone();
+    let hashing = hashing.clone();
+    let hashes = spawn_blocking_with_tracing(move || {
+        to_hash
+            .into_iter()
+            .map(|code| compute_password_hash(Secret::new(code), &hashing))
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await?
+    .context("Failed to hash the recovery codes")?;
+    for hash in hashes {
+        sqlx::query!(
+            r#"
+            INSERT INTO recovery_codes (recovery_code_id, user_id, code_hash, created_at)
+            VALUES ($1, $2, $3, now())
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            hash.expose_secret()
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store a recovery code.")?;
+    }
+    Ok(codes)
+}
+
+/// Turn off 2FA for a user, along with their recovery codes.
+#[tracing::instrument(name = "Remove TOTP secret", skip(pool))]
+pub async fn remove_totp_secret(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = NULL, totp_last_used_step = NULL WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the TOTP secret.")?;
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the recovery codes.")?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Count unused recovery codes", skip(pool))]
+pub async fn unused_recovery_codes(pool: &PgPool, user_id: Uuid) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the recovery codes.")?;
+    Ok(row.count)
+}
+
+/// Check a code from the authenticator app, or else a recovery code, and
+/// use it up. Returns `false` if the user has not set up 2FA.
+#[tracing::instrument(name = "Verify second factor", skip(pool, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: Secret<String>,
+) -> Result<bool, anyhow::Error> {
+    let Some(secret) = get_totp_secret(pool, user_id).await? else {
+        return Ok(false);
+    };
+    if let Some(step) = totp_step(&secret, code.expose_secret(), unix_time())? {
+        // The condition makes a code usable once, even by concurrent requests.
+        let result = sqlx::query!(
+            r#"
+            UPDATE users SET totp_last_used_step = $2
+            WHERE user_id = $1 AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step as i64
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the use of a TOTP code.")?;
+        return Ok(result.rows_affected() == 1);
+    }
+    use_recovery_code(pool, user_id, code).await
+}
+
+async fn use_recovery_code(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: Secret<String>,
+) -> Result<bool, anyhow::Error> {
+    let code = code.expose_secret().trim().to_lowercase();
+    // Anything else cannot match, and is not worth a round of Argon2.
+    if !is_recovery_code(&code) {
+        return Ok(false);
+    }
+    let candidates = sqlx::query!(
+        r#"
+        SELECT recovery_code_id, code_hash
+        FROM recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the recovery codes.")?;
+    let hashes: Vec<_> = candidates
+        .into_iter()
+        .map(|c| (c.recovery_code_id, c.code_hash))
+        .collect();
+    // `find_map` stops hashing at the first match.
+    let matching = spawn_blocking_with_tracing(move || {
+        hashes.into_iter().find_map(|(id, hash)| {
+            let hash = PasswordHash::new(&hash).ok()?;
+            Argon2::default()
+                .verify_password(code.as_bytes(), &hash)
+                .ok()
+                .map(|_| id)
+        })
+    })
+    .await?;
+    let Some(recovery_code_id) = matching else {
+        return Ok(false);
+    };
+    let result = sqlx::query!(
+        r#"
+        UPDATE recovery_codes SET used_at = now()
+        WHERE recovery_code_id = $1 AND used_at IS NULL
+        "#,
+        recovery_code_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the use of a recovery code.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_codes, generate_totp_secret, is_recovery_code, totp, totp_step};
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn codes_are_valid_for_one_step_either_way() {
+        let secret = generate_totp_secret();
+        let totp = totp(&secret, "").unwrap();
+        let now = 1_700_000_000;
+        let code = totp.generate(now);
+
+        assert_some_eq!(totp_step(&secret, &code, now).unwrap(), now / 30);
+        assert_some_eq!(totp_step(&secret, &code, now + 30).unwrap(), now / 30);
+        assert_none!(totp_step(&secret, &code, now + 90).unwrap());
+        assert_none!(totp_step(&secret, "000000x", now).unwrap());
+    }
+
+    #[test]
+    fn recovery_codes_are_distinct() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), 10);
+        assert!(codes.iter().all(|c| is_recovery_code(c)));
+        let mut unique = codes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), codes.len());
+    }
+
+    #[test]
+    fn only_well_formed_recovery_codes_are_checked() {
+        assert!(is_recovery_code("abcde-23456"));
+        for code in [
+            "123456",
+            "abcde23456",
+            "abcde-2345",
+            "abcde_23456",
+            "abcde-2345o",
+        ] {
+            assert!(!is_recovery_code(code), "{code}");
+        }
+    }
+}
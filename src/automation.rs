@@ -0,0 +1,115 @@
+//! Email sequences sent to the subscribers of a list once they confirm.
+//!
+//! Every subscriber goes through a sequence at most once. Their progress is
+//! tracked in `automation_progress` and the background worker sends each
+//! step when it is due. A sequence stops as soon as the subscriber leaves
+//! its list.
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Steps can be scheduled at most a year after the confirmation.
+pub const MAX_DELAY_DAYS: i32 = 365;
+
+/// Parse the delay of a step, which cannot come before the previous one.
+pub fn parse_delay_days(s: &str, previous_delay_days: Option<i32>) -> Result<i32, String> {
+    let delay_days = match s.trim().parse::<i32>() {
+        Ok(days) if (0..=MAX_DELAY_DAYS).contains(&days) => days,
+        _ => {
+            return Err(format!(
+                "The delay must be a number of days between 0 and {MAX_DELAY_DAYS}."
+            ))
+        }
+    };
+    match previous_delay_days {
+        Some(previous) if delay_days < previous => Err(format!(
+            "A step cannot be sent before the previous one (day {previous})."
+        )),
+        _ => Ok(delay_days),
+    }
+}
+
+/// Start the sequences of a list for a subscriber who just confirmed.
+/// Sequences without steps, or that the subscriber already went through, are skipped.
+#[tracing::instrument(name = "Enroll subscriber in automation sequences", skip(executor))]
+pub async fn enroll_in_sequences<'c>(
+    executor: impl PgExecutor<'c>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO automation_progress (
+            sequence_id, subscriber_id, status, next_position, next_send_at,
+            started_at, updated_at
+        )
+        SELECT s.sequence_id, $2, 'active', first.position,
+            now() + make_interval(days => first.delay_days), now(), now()
+        FROM automation_sequences s
+        JOIN LATERAL (
+            SELECT position, delay_days
+            FROM automation_steps
+            WHERE sequence_id = s.sequence_id
+            ORDER BY position
+            LIMIT 1
+        ) first ON true
+        WHERE s.list_id = $1
+        ON CONFLICT (sequence_id, subscriber_id) DO NOTHING
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Stop the running sequences of lists the subscriber no longer belongs to.
+#[tracing::instrument(name = "Stop automation sequences", skip(executor))]
+pub async fn stop_inactive_sequences<'c>(
+    executor: impl PgExecutor<'c>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE automation_progress p
+        SET status = 'stopped', next_position = NULL, next_send_at = NULL, updated_at = now()
+        FROM automation_sequences s
+        WHERE
+            s.sequence_id = p.sequence_id AND
+            p.subscriber_id = $1 AND
+            p.status = 'active' AND
+            NOT EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE
+                    m.list_id = s.list_id AND
+                    m.subscriber_id = p.subscriber_id AND
+                    m.status = 'confirmed'
+            )
+        "#,
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_delay_days;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn delays_are_whole_days_within_a_year() {
+        assert_ok_eq!(parse_delay_days(" 3 ", None), 3);
+        assert_ok_eq!(parse_delay_days("0", None), 0);
+        assert_err!(parse_delay_days("-1", None));
+        assert_err!(parse_delay_days("366", None));
+        assert_err!(parse_delay_days("1.5", None));
+    }
+
+    #[test]
+    fn a_step_cannot_come_before_the_previous_one() {
+        assert_ok_eq!(parse_delay_days("3", Some(3)), 3);
+        assert_err!(parse_delay_days("2", Some(3)));
+    }
+}
@@ -0,0 +1,94 @@
+//! Command-line equivalent of the admin CSV import.
+//!
+//! ```text
+//! import_subscribers <file.csv> [--mode confirmed|send_confirmation] [--list <slug>] [--report <rejected.csv>]
+//! ```
+//!
+//! The rejected rows are written to `--report`, or to stdout if omitted.
+//! Confirmation emails are sent by the background worker.
+use anyhow::Context;
+use zero2prod::configuration::get_configuration;
+use zero2prod::domain::ListSlug;
+use zero2prod::routes::get_list_id_by_slug;
+use zero2prod::startup::get_connection_pool;
+use zero2prod::subscriber_import::{import_subscribers, ImportMode};
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+const USAGE: &str = "Usage: import_subscribers <file.csv> \
+    [--mode confirmed|send_confirmation] [--list <slug>] [--report <rejected.csv>]";
+
+struct Args {
+    path: String,
+    mode: ImportMode,
+    list: ListSlug,
+    report: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut path = None;
+    let mut mode = ImportMode::SendConfirmation;
+    let mut list = ListSlug::default();
+    let mut report = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value."));
+        match arg.as_str() {
+            "--mode" => mode = ImportMode::parse(&value()?)?,
+            "--list" => list = ListSlug::parse(value()?)?,
+            "--report" => report = Some(value()?),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(format!("Unexpected argument: {arg}")),
+        }
+    }
+    Ok(Args {
+        path: path.ok_or("Missing the path of the CSV file.")?,
+        mode,
+        list,
+        report,
+    })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let subscriber = get_subscriber("import_subscribers".into(), "info".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    let settings = get_configuration().context("Failed to read configuration")?;
+    let pool = get_connection_pool(&settings.database);
+
+    let list_id = get_list_id_by_slug(&pool, &args.list)
+        .await
+        .context("Failed to look up the mailing list.")?
+        .with_context(|| format!("{} is not a known mailing list.", args.list))?;
+    let csv = tokio::fs::File::open(&args.path)
+        .await
+        .with_context(|| format!("Failed to open {}", args.path))?;
+    let report = import_subscribers(
+        &pool,
+        &settings.email_normalization,
+        list_id,
+        args.mode,
+        csv,
+    )
+    .await
+    .context("Failed to import the subscribers.")?;
+
+    match args.report {
+        Some(path) => tokio::fs::write(&path, report.rejected_rows_csv())
+            .await
+            .with_context(|| format!("Failed to write {path}"))?,
+        None => print!("{}", report.rejected_rows_csv()),
+    }
+    eprintln!(
+        "{} subscriber(s) imported, {} row(s) rejected.",
+        report.imported,
+        report.rejected.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! Keeping `subscriptions.canonical_email` in line with the configured
+//! [`EmailNormalization`] rules.
+//!
+//! The rules the column was derived with are stored in
+//! `email_normalization_state`; on startup the column is recomputed with
+//! [`SubscriberEmail::canonical`] whenever the configuration differs.
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// A subscriber as far as its canonical address is concerned.
+#[derive(Debug, Clone)]
+pub struct StoredAddress {
+    pub id: Uuid,
+    pub email: String,
+    pub canonical_email: String,
+}
+
+/// Recompute the canonical addresses if they were derived with other rules.
+/// Returns the number of subscribers whose canonical address changed.
+#[tracing::instrument(name = "Ensure canonical emails are up to date", skip(pool))]
+pub async fn ensure_canonical_emails(
+    pool: &PgPool,
+    rules: &EmailNormalization,
+) -> Result<u64, anyhow::Error> {
+    let stored = sqlx::query!("SELECT fold_gmail_addresses FROM email_normalization_state")
+        .fetch_optional(pool)
+        .await
+        .context("Failed to read the current email normalization rules.")?;
+    if stored.is_some_and(|s| s.fold_gmail_addresses == rules.fold_gmail_addresses) {
+        return Ok(0);
+    }
+    recanonicalize_emails(pool, rules).await
+}
+
+/// Derive `canonical_email` again for every subscriber.
+/// Returns the number of subscribers whose canonical address changed.
+#[tracing::instrument(name = "Recompute canonical emails", skip(pool))]
+pub async fn recanonicalize_emails(
+    pool: &PgPool,
+    rules: &EmailNormalization,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Signups arriving meanwhile would be checked against stale values.
+    sqlx::query!("LOCK TABLE subscriptions IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to lock the subscriptions table.")?;
+    let rows = sqlx::query_as!(
+        StoredAddress,
+        r#"
+        SELECT id, email, canonical_email
+        FROM subscriptions
+        ORDER BY subscribed_at, id
+        "#
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber addresses.")?;
+
+    let changes = assign_canonical_emails(&rows, rules).map_err(anyhow::Error::msg)?;
+    let (ids, canonical_emails): (Vec<Uuid>, Vec<String>) = changes.into_iter().unzip();
+    // Unique constraints are checked row by row: move the rows out of the
+    // way first so that two of them can swap values.
+    sqlx::query!(
+        "UPDATE subscriptions SET canonical_email = id::text WHERE id = ANY($1)",
+        &ids[..]
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the canonical addresses to update.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET canonical_email = u.canonical_email
+        FROM unnest($1::uuid[], $2::text[]) AS u(id, canonical_email)
+        WHERE s.id = u.id
+        "#,
+        &ids[..],
+        &canonical_emails[..]
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the canonical addresses.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_normalization_state (fold_gmail_addresses, updated_at)
+        VALUES ($1, now())
+        ON CONFLICT (id) DO UPDATE
+        SET fold_gmail_addresses = EXCLUDED.fold_gmail_addresses, updated_at = now()
+        "#,
+        rules.fold_gmail_addresses
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the email normalization rules.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new canonical addresses.")?;
+
+    tracing::info!("Updated {} canonical addresses.", ids.len());
+    Ok(ids.len() as u64)
+}
+
+/// The `(id, canonical_email)` pairs that differ from what is stored.
+///
+/// `rows` must be sorted by signup date: when several subscribers now share
+/// a canonical form, the oldest gets it and the others keep their current
+/// value, or their raw address, so that they can be merged by hand.
+pub fn assign_canonical_emails(
+    rows: &[StoredAddress],
+    rules: &EmailNormalization,
+) -> Result<Vec<(Uuid, String)>, String> {
+    let mut taken = HashSet::new();
+    let mut duplicates = Vec::new();
+    let mut assigned = Vec::with_capacity(rows.len());
+    for row in rows {
+        // Addresses that no longer parse keep the value they have.
+        let canonical = match SubscriberEmail::parse(row.email.clone()) {
+            Ok(email) => email.canonical(rules),
+            Err(_) => row.canonical_email.clone(),
+        };
+        if taken.insert(canonical.clone()) {
+            assigned.push((row, canonical));
+        } else {
+            duplicates.push(row);
+        }
+    }
+    for row in duplicates {
+        tracing::warn!(
+            subscriber_id = %row.id,
+            "Another subscriber has the same canonical address, it has to be merged by hand."
+        );
+        let fallback = [&row.canonical_email, &row.email]
+            .into_iter()
+            .find(|candidate| !taken.contains(candidate.as_str()))
+            .ok_or_else(|| {
+                format!(
+                    "No free canonical address for subscriber {}, it has to be merged by hand.",
+                    row.id
+                )
+            })?;
+        taken.insert(fallback.clone());
+        assigned.push((row, fallback.clone()));
+    }
+    Ok(assigned
+        .into_iter()
+        .filter(|(row, canonical)| &row.canonical_email != canonical)
+        .map(|(row, canonical)| (row.id, canonical))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign_canonical_emails, StoredAddress};
+    use crate::domain::EmailNormalization;
+    use uuid::Uuid;
+
+    fn row(email: &str, canonical_email: &str) -> StoredAddress {
+        StoredAddress {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            canonical_email: canonical_email.to_string(),
+        }
+    }
+
+    const FOLD: EmailNormalization = EmailNormalization {
+        fold_gmail_addresses: true,
+    };
+
+    #[test]
+    fn only_changed_addresses_are_returned() {
+        let rows = vec![
+            row("Ursula@Example.com", "ursula@example.com"),
+            row("J.Doe+news@gmail.com", "j.doe+news@gmail.com"),
+        ];
+
+        let changes = assign_canonical_emails(&rows, &FOLD).unwrap();
+
+        assert_eq!(changes, vec![(rows[1].id, "jdoe@gmail.com".to_string())]);
+    }
+
+    #[test]
+    fn the_oldest_subscriber_gets_a_shared_canonical_address() {
+        let rows = vec![
+            row("j.doe@gmail.com", "j.doe@gmail.com"),
+            row("jdoe+news@gmail.com", "jdoe+news@gmail.com"),
+        ];
+
+        let changes = assign_canonical_emails(&rows, &FOLD).unwrap();
+
+        assert_eq!(changes, vec![(rows[0].id, "jdoe@gmail.com".to_string())]);
+    }
+
+    #[test]
+    fn folding_can_be_turned_off_again() {
+        let rows = vec![row("J.Doe+news@gmail.com", "jdoe@gmail.com")];
+
+        let changes = assign_canonical_emails(&rows, &EmailNormalization::default()).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![(rows[0].id, "j.doe+news@gmail.com".to_string())]
+        );
+    }
+}
@@ -1,16 +1,23 @@
-use crate::domain::SubscriberEmail;
+use crate::domain::{EmailNormalization, SubscriberEmail};
 use crate::email_client::EmailClient;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use serde_derive::Deserialize;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::net::IpAddr;
 
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_normalization: EmailNormalization,
+    pub email_domains: EmailDomainSettings,
+    pub subscription_protection: SubscriptionProtectionSettings,
+    pub cors: CorsSettings,
+    pub authentication: AuthenticationSettings,
+    pub session: SessionSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -41,6 +48,151 @@ impl EmailClientSettings {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 }
+#[derive(Deserialize, Clone)]
+pub struct EmailDomainSettings {
+    /// The bundled list of disposable-email domains.
+    pub disposable_domains_path: String,
+}
+
+/// Browser origins allowed to call the JSON API, e.g. our marketing site.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CorsSettings {
+    pub allowed_origins: Vec<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_age_seconds: usize,
+}
+
+/// Where the rate limiter keeps its counters.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackend {
+    /// Per process: each instance of the application counts on its own.
+    Memory,
+    /// Shared by every instance, in the Redis instance used for sessions.
+    Redis,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SubscriptionProtectionSettings {
+    pub rate_limit_backend: RateLimitBackend,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub rate_limit_window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests_per_email: u32,
+    /// Forms submitted faster than this after being rendered are ignored.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_time_seconds: i64,
+    /// How long a confirmation token is sent again instead of a new one.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub token_reuse_cooldown_seconds: i64,
+    /// Requests for personal data share the rate limiter of subscriptions.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_data_requests_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_data_requests_per_email: u32,
+}
+
+impl SubscriptionProtectionSettings {
+    pub fn rate_limit_window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.rate_limit_window_seconds)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AuthenticationSettings {
+    /// Users without two-factor authentication must set it up before
+    /// they can use the admin pages.
+    pub require_two_factor: bool,
+    /// Where failed logins are counted.
+    pub failed_login_backend: RateLimitBackend,
+    /// Failed logins are counted over windows of this length.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failed_login_window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failed_logins_per_username: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failed_logins_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: i64,
+    /// The delay after the first failed login, doubled after each of the next ones.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failed_login_delay_milliseconds: u64,
+    /// Password reset requests are counted by the shared rate limiter,
+    /// over `subscription_protection.rate_limit_window_seconds`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_password_resets_per_ip: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_password_resets_per_email: u32,
+    pub password_hashing: PasswordHashingSettings,
+}
+
+/// The Argon2id cost of new password hashes. Once raised, the hash of each
+/// user is upgraded the next time they log in.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+/// How long admin sessions last and how their cookie is sent.
+#[derive(Deserialize, Clone, Debug)]
+pub struct SessionSettings {
+    /// Sessions end this long after logging in, however active they are.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub absolute_lifetime_minutes: u64,
+    /// Sessions end after this long without a request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_minutes: i32,
+    /// The lifetime of the sessions of users who asked to be remembered.
+    /// They do not time out when idle, and their cookie survives the browser.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub remember_me_lifetime_days: u64,
+    /// Only send the session cookie over HTTPS.
+    pub cookie_secure: bool,
+    pub cookie_same_site: CookieSameSite,
+}
+
+impl SessionSettings {
+    pub fn lifetime(&self, remember_me: bool) -> std::time::Duration {
+        if remember_me {
+            std::time::Duration::from_secs(self.remember_me_lifetime_days * 24 * 60 * 60)
+        } else {
+            std::time::Duration::from_secs(self.absolute_lifetime_minutes * 60)
+        }
+    }
+}
+
+/// The `SameSite` attribute of the session cookie.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<CookieSameSite> for actix_web::cookie::SameSite {
+    fn from(value: CookieSameSite) -> Self {
+        match value {
+            CookieSameSite::Strict => Self::Strict,
+            CookieSameSite::Lax => Self::Lax,
+            CookieSameSite::None => Self::None,
+        }
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -48,6 +200,10 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Reverse proxies whose `X-Forwarded-For` header is believed.
+    /// Requests from any other peer are attributed to the peer itself.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -0,0 +1,50 @@
+/// Identifies where a subscriber opted in (e.g. `footer-form`) or which
+/// version of the consent text they were shown (e.g. `2023-12`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsentLabel(String);
+
+impl ConsentLabel {
+    pub fn parse(s: String) -> Result<ConsentLabel, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_alphanumeric() || ['-', '_', '.', ':'].contains(&c)));
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{s} is not a valid consent source or version."))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for ConsentLabel {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConsentLabel;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn form_identifiers_and_versions_are_accepted() {
+        for label in ["footer-form", "landing_page:rust", "v1.2"] {
+            assert_ok!(ConsentLabel::parse(label.to_string()));
+        }
+    }
+
+    #[test]
+    fn empty_long_or_free_text_labels_are_rejected() {
+        for label in [
+            String::new(),
+            "a".repeat(65),
+            "<script>".into(),
+            "two words".into(),
+        ] {
+            assert_err!(ConsentLabel::parse(label));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+/// How often a subscriber wants to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryFrequency {
+    /// Every issue is delivered as soon as it is published.
+    #[default]
+    EveryIssue,
+    /// Issues are collected and delivered together in a periodic digest.
+    Digest,
+}
+
+impl DeliveryFrequency {
+    pub fn parse(s: &str) -> Result<DeliveryFrequency, String> {
+        match s {
+            "every_issue" => Ok(Self::EveryIssue),
+            "digest" => Ok(Self::Digest),
+            other => Err(format!("{other} is not a valid delivery frequency.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "every_issue",
+            DeliveryFrequency::Digest => "digest",
+        }
+    }
+}
+
+impl AsRef<str> for DeliveryFrequency {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryFrequency;
+    use claims::assert_err;
+
+    #[test]
+    fn frequencies_round_trip_through_their_string_representation() {
+        for frequency in [DeliveryFrequency::EveryIssue, DeliveryFrequency::Digest] {
+            assert_eq!(DeliveryFrequency::parse(frequency.as_str()), Ok(frequency));
+        }
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        for frequency in ["", "daily", "Digest"] {
+            assert_err!(DeliveryFrequency::parse(frequency));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/// The mailing list subscribers end up on when no list is specified.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    // Slugs show up in forms and URLs, so we only accept lowercase
+    // ASCII letters, digits and dashes.
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'));
+        let has_dangling_dash = s.starts_with('-') || s.ends_with('-');
+        if is_empty || is_too_long || contains_forbidden_characters || has_dangling_dash {
+            Err(format!("{s} is not a valid mailing list identifier."))
+        } else {
+            Ok(Self(s))
+        }
+    }
+
+    /// Parse a comma-separated list of slugs, e.g. `rust,announcements`.
+    /// Duplicates are dropped while preserving the original order.
+    pub fn parse_many(s: &str) -> Result<Vec<ListSlug>, String> {
+        let mut slugs: Vec<ListSlug> = Vec::new();
+        for slug in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let slug = ListSlug::parse(slug.to_string())?;
+            if !slugs.contains(&slug) {
+                slugs.push(slug);
+            }
+        }
+        if slugs.is_empty() {
+            return Err("At least one mailing list must be selected.".to_string());
+        }
+        Ok(slugs)
+    }
+}
+
+impl Default for ListSlug {
+    fn default() -> Self {
+        Self(DEFAULT_LIST.to_string())
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ListSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_lowercase_slug_with_dashes_is_valid() {
+        assert_ok!(ListSlug::parse("rust-weekly-2".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn uppercase_and_whitespace_are_rejected() {
+        for slug in ["Rust", "rust weekly", "rust_weekly", "rust/weekly"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn leading_or_trailing_dashes_are_rejected() {
+        assert_err!(ListSlug::parse("-rust".to_string()));
+        assert_err!(ListSlug::parse("rust-".to_string()));
+    }
+
+    #[test]
+    fn many_slugs_are_deduplicated() {
+        let slugs = ListSlug::parse_many("rust, go,rust").unwrap();
+        let slugs: Vec<&str> = slugs.iter().map(AsRef::as_ref).collect();
+        assert_eq!(slugs, vec!["rust", "go"]);
+    }
+
+    #[test]
+    fn an_empty_selection_is_rejected() {
+        assert_err!(ListSlug::parse_many(" , "));
+    }
+}
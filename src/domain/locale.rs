@@ -0,0 +1,99 @@
+/// The languages we have a message catalog for, the first one being the default.
+pub const SUPPORTED_LOCALES: &[&str] = &["en", "fr"];
+
+/// A language subscribers can receive our emails and pages in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale(&'static str);
+
+impl Locale {
+    /// Accepts a language tag such as `fr` or `fr-CA`, matched on its
+    /// primary language.
+    pub fn parse(s: &str) -> Result<Locale, String> {
+        let language = s.trim().split(['-', '_']).next().unwrap_or_default();
+        SUPPORTED_LOCALES
+            .iter()
+            .find(|l| l.eq_ignore_ascii_case(language))
+            .map(|l| Self(l))
+            .ok_or_else(|| format!("{s} is not a supported language."))
+    }
+
+    /// The supported locale the client prefers according to an
+    /// `Accept-Language` header, if any.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut ranges: Vec<(Locale, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let locale = Locale::parse(parts.next()?).ok()?;
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                Some((locale, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // Stable, so equally weighted languages keep the client's order.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges.first().map(|(locale, _)| *locale)
+    }
+
+    pub fn all() -> impl Iterator<Item = Locale> {
+        SUPPORTED_LOCALES.iter().map(|l| Self(l))
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self(SUPPORTED_LOCALES[0])
+    }
+}
+
+impl AsRef<str> for Locale {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use claims::{assert_err, assert_none, assert_ok_eq};
+
+    #[test]
+    fn regional_variants_match_their_language() {
+        assert_ok_eq!(Locale::parse("fr"), Locale::parse("fr").unwrap());
+        assert_ok_eq!(Locale::parse("FR-ca"), Locale::parse("fr").unwrap());
+        assert_ok_eq!(Locale::parse("en_GB"), Locale::default());
+        assert_err!(Locale::parse("de"));
+        assert_err!(Locale::parse(""));
+    }
+
+    #[test]
+    fn the_preferred_supported_language_wins() {
+        let fr = Locale::parse("fr").unwrap();
+        assert_eq!(
+            Locale::from_accept_language("fr-CH, fr;q=0.9, en;q=0.8"),
+            Some(fr)
+        );
+        assert_eq!(
+            Locale::from_accept_language("de, en;q=0.5, fr;q=0.7"),
+            Some(fr)
+        );
+        assert_eq!(
+            Locale::from_accept_language("en;q=0.5, fr;q=0"),
+            Some(Locale::default())
+        );
+        assert_none!(Locale::from_accept_language("de, it;q=0.5"));
+        assert_none!(Locale::from_accept_language("*"));
+    }
+}
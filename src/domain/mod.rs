@@ -1,12 +1,28 @@
+mod consent_label;
+mod delivery_frequency;
+mod list_slug;
+mod locale;
 mod new_subscriber;
 mod password;
+mod redirect_url;
+mod segment;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 mod subscription_token;
 
 // pub use subscriber_email::S;
+pub use consent_label::ConsentLabel;
+pub use delivery_frequency::DeliveryFrequency;
+pub use list_slug::{ListSlug, DEFAULT_LIST};
+pub use locale::{Locale, SUPPORTED_LOCALES};
 pub use new_subscriber::NewSubscriber;
 pub use password::{ChangePasswordParam, Password};
-pub use subscriber_email::SubscriberEmail;
+pub use redirect_url::RedirectUrl;
+pub use segment::Segment;
+pub use subscriber_attributes::SubscriberAttributes;
+pub use subscriber_email::{EmailNormalization, SubscriberEmail};
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
 pub use subscription_token::SubscriberToken;
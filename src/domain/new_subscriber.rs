@@ -1,7 +1,10 @@
 use crate::domain::subscriber_name::SubscriberName;
-use crate::domain::SubscriberEmail;
+use crate::domain::{Locale, SubscriberAttributes, SubscriberEmail, SubscriberTag};
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub tags: Vec<SubscriberTag>,
+    pub attributes: SubscriberAttributes,
+    pub locale: Locale,
 }
@@ -0,0 +1,57 @@
+use reqwest::Url;
+
+/// Where to send subscribers once they are done with one of our hosted pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectUrl(String);
+
+impl RedirectUrl {
+    // We redirect browsers there, so only absolute web URLs make sense.
+    pub fn parse(s: String) -> Result<RedirectUrl, String> {
+        let s = s.trim();
+        match Url::parse(s) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {
+                Ok(Self(url.to_string()))
+            }
+            _ => Err(format!("{s} is not a valid http(s) URL.")),
+        }
+    }
+
+    /// Like [`RedirectUrl::parse`], treating a blank field as no URL.
+    pub fn parse_optional(s: String) -> Result<Option<RedirectUrl>, String> {
+        if s.trim().is_empty() {
+            Ok(None)
+        } else {
+            Self::parse(s).map(Some)
+        }
+    }
+}
+
+impl AsRef<str> for RedirectUrl {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RedirectUrl;
+    use claims::{assert_err, assert_none, assert_ok};
+
+    #[test]
+    fn absolute_web_urls_are_accepted() {
+        assert_ok!(RedirectUrl::parse("https://example.com/thanks".into()));
+        assert_ok!(RedirectUrl::parse(" http://example.com ".into()));
+    }
+
+    #[test]
+    fn relative_and_non_web_urls_are_rejected() {
+        assert_err!(RedirectUrl::parse("/thanks".into()));
+        assert_err!(RedirectUrl::parse("javascript:alert(1)".into()));
+        assert_err!(RedirectUrl::parse("mailto:owner@example.com".into()));
+    }
+
+    #[test]
+    fn a_blank_field_means_no_url() {
+        assert_none!(RedirectUrl::parse_optional("  ".into()).unwrap());
+    }
+}
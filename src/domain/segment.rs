@@ -0,0 +1,455 @@
+use crate::domain::subscriber_attributes::parse_key;
+use crate::domain::SubscriberTag;
+use sqlx::{Postgres, QueryBuilder};
+
+/// A filter over subscribers, written on the publish form, e.g.
+/// `tag:rust AND NOT tag:beta` or `signup_source = "conference"`.
+///
+/// Grammar (keywords are case-insensitive):
+///
+/// ```text
+/// expr       := and_expr ("OR" and_expr)*
+/// and_expr   := not_expr ("AND" not_expr)*
+/// not_expr   := "NOT" not_expr | "(" expr ")" | "tag:" tag | key op literal
+/// op         := "=" | "!=" | "<" | "<=" | ">" | ">="
+/// literal    := "string" | number | true | false
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Compare {
+        key: String,
+        op: Operator,
+        value: Literal,
+    },
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let segment = parser.expr()?;
+        match parser.peek() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {token} in segment expression.")),
+        }
+    }
+
+    /// Append this segment as a boolean SQL expression over the `subscriptions`
+    /// table, aliased as `s`. Every user-provided value is bound as a parameter.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::Tag(tag) => {
+                query
+                    .push("s.tags @> ARRAY[")
+                    .push_bind(tag.as_ref().to_owned())
+                    .push("]");
+            }
+            Segment::Compare { key, op, value } => push_comparison(query, key, *op, value),
+            Segment::Not(inner) => {
+                query.push("NOT (");
+                inner.push_sql(query);
+                query.push(")");
+            }
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let keyword = if matches!(self, Segment::And(..)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                query.push("(");
+                left.push_sql(query);
+                query.push(keyword);
+                right.push_sql(query);
+                query.push(")");
+            }
+        }
+    }
+}
+
+fn push_comparison(
+    query: &mut QueryBuilder<'_, Postgres>,
+    key: &str,
+    op: Operator,
+    value: &Literal,
+) {
+    let sql_op = match op {
+        Operator::Eq => "=",
+        Operator::Ne => "<>",
+        Operator::Lt => "<",
+        Operator::Le => "<=",
+        Operator::Gt => ">",
+        Operator::Ge => ">=",
+    };
+    match (op, value) {
+        // Equality is checked on the JSON value itself, so `age = "30"`
+        // does not match a numeric `30`. A missing attribute is never equal.
+        (Operator::Eq | Operator::Ne, _) => {
+            let json = match value {
+                Literal::String(s) => serde_json::Value::from(s.as_str()),
+                Literal::Number(n) => serde_json::Value::from(*n),
+                Literal::Bool(b) => serde_json::Value::from(*b),
+            };
+            let comparison = if op == Operator::Eq {
+                " = "
+            } else {
+                " IS DISTINCT FROM "
+            };
+            query
+                .push("s.attributes -> ")
+                .push_bind(key.to_owned())
+                .push(comparison)
+                .push_bind(json);
+        }
+        // Orderings only apply to attributes of the same JSON type, the
+        // `CASE` guarantees we never try to cast a string to a number.
+        (_, Literal::Number(n)) => {
+            query
+                .push("CASE WHEN jsonb_typeof(s.attributes -> ")
+                .push_bind(key.to_owned())
+                .push(") = 'number' THEN (s.attributes ->> ")
+                .push_bind(key.to_owned())
+                .push(format!(")::float8 {sql_op} "))
+                .push_bind(*n)
+                .push(" ELSE false END");
+        }
+        (_, Literal::String(s)) => {
+            query
+                .push("CASE WHEN jsonb_typeof(s.attributes -> ")
+                .push_bind(key.to_owned())
+                .push(") = 'string' THEN (s.attributes ->> ")
+                .push_bind(key.to_owned())
+                .push(format!(") {sql_op} "))
+                .push_bind(s.to_owned())
+                .push(" ELSE false END");
+        }
+        (_, Literal::Bool(_)) => unreachable!("Booleans are rejected by the parser"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Tag(String),
+    String(String),
+    Number(f64),
+    Op(Operator),
+    LParen,
+    RParen,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "`{s}`"),
+            Token::Tag(s) => write!(f, "`tag:{s}`"),
+            Token::String(s) => write!(f, "\"{s}\""),
+            Token::Number(n) => write!(f, "`{n}`"),
+            Token::Op(_) => write!(f, "operator"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.next_if_eq(&'=').is_some();
+                let op = match (c, followed_by_eq) {
+                    ('=', _) => Operator::Eq,
+                    ('!', true) => Operator::Ne,
+                    ('<', false) => Operator::Lt,
+                    ('<', true) => Operator::Le,
+                    ('>', false) => Operator::Gt,
+                    ('>', true) => Operator::Ge,
+                    _ => return Err("Expected `!=` in segment expression.".to_string()),
+                };
+                tokens.push(Token::Op(op));
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => return Err("Unterminated string in segment.".to_string()),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated string in segment.".to_string()),
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || "_-.:".contains(*c))
+                {
+                    word.push(c);
+                }
+                if word.is_empty() {
+                    return Err(format!("Unexpected character `{c}` in segment expression."));
+                }
+                let token = if let Some(tag) = word.strip_prefix("tag:") {
+                    Token::Tag(tag.to_string())
+                } else if let Ok(n) = word.parse::<f64>() {
+                    if !n.is_finite() {
+                        return Err(format!("`{word}` is not a valid number."));
+                    }
+                    Token::Number(n)
+                } else {
+                    Token::Ident(word)
+                };
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Segment, String> {
+        let mut segment = self.and_expr()?;
+        while self.next_is_keyword("or") {
+            segment = Segment::Or(Box::new(segment), Box::new(self.and_expr()?));
+        }
+        Ok(segment)
+    }
+
+    fn and_expr(&mut self) -> Result<Segment, String> {
+        let mut segment = self.not_expr()?;
+        while self.next_is_keyword("and") {
+            segment = Segment::And(Box::new(segment), Box::new(self.not_expr()?));
+        }
+        Ok(segment)
+    }
+
+    fn not_expr(&mut self) -> Result<Segment, String> {
+        if self.next_is_keyword("not") {
+            return Ok(Segment::Not(Box::new(self.not_expr()?)));
+        }
+        match self.next() {
+            Some(Token::LParen) => {
+                let segment = self.expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(segment),
+                    _ => Err("Missing `)` in segment expression.".to_string()),
+                }
+            }
+            Some(Token::Tag(tag)) => Ok(Segment::Tag(SubscriberTag::parse(tag)?)),
+            Some(Token::Ident(key)) => {
+                let key = parse_key(&key)?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => return Err(format!("Expected a comparison after `{key}`.")),
+                };
+                let value = match self.next() {
+                    Some(Token::String(s)) => Literal::String(s),
+                    Some(Token::Number(n)) => Literal::Number(n),
+                    Some(Token::Ident(word)) if word == "true" || word == "false" => {
+                        Literal::Bool(word == "true")
+                    }
+                    _ => return Err(format!("Expected a value to compare `{key}` with.")),
+                };
+                if matches!(value, Literal::Bool(_)) && !matches!(op, Operator::Eq | Operator::Ne) {
+                    return Err(format!(
+                        "`{key}` can only be compared to a boolean with `=` or `!=`."
+                    ));
+                }
+                Ok(Segment::Compare { key, op, value })
+            }
+            Some(token) => Err(format!("Unexpected {token} in segment expression.")),
+            None => Err("Unexpected end of segment expression.".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Literal, Operator, Segment};
+    use crate::domain::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+    use sqlx::{Postgres, QueryBuilder};
+
+    fn tag(s: &str) -> Box<Segment> {
+        Box::new(Segment::Tag(SubscriberTag::parse(s.to_string()).unwrap()))
+    }
+
+    fn sql(s: &str) -> String {
+        let mut query = QueryBuilder::<Postgres>::new("");
+        Segment::parse(s).unwrap().push_sql(&mut query);
+        query.sql().to_string()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = Segment::parse("tag:a OR tag:b AND NOT tag:c").unwrap();
+        assert_eq!(
+            segment,
+            Segment::Or(
+                tag("a"),
+                Box::new(Segment::And(tag("b"), Box::new(Segment::Not(tag("c")))))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let segment = Segment::parse("(tag:a or tag:b) and tag:c").unwrap();
+        assert_eq!(
+            segment,
+            Segment::And(Box::new(Segment::Or(tag("a"), tag("b"))), tag("c"))
+        );
+    }
+
+    #[test]
+    fn comparisons_are_parsed_with_typed_literals() {
+        let cases = [
+            (
+                "signup_source = \"conference\"",
+                Operator::Eq,
+                Literal::String("conference".into()),
+            ),
+            ("age >= 18", Operator::Ge, Literal::Number(18.0)),
+            ("beta != true", Operator::Ne, Literal::Bool(true)),
+        ];
+        for (input, op, value) in cases {
+            let Segment::Compare {
+                op: parsed_op,
+                value: parsed_value,
+                ..
+            } = Segment::parse(input).unwrap()
+            else {
+                panic!("{input} was not parsed as a comparison");
+            };
+            assert_eq!(parsed_op, op);
+            assert_eq!(parsed_value, value);
+        }
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for input in [
+            "",
+            "tag:",
+            "tag:rust AND",
+            "(tag:rust",
+            "tag:rust)",
+            "age >",
+            "age 18",
+            "beta > true",
+            "Age = 3",
+            "name = \"unterminated",
+            "tag:rust; DROP TABLE subscriptions",
+        ] {
+            assert_err!(Segment::parse(input), "{input} should be rejected");
+        }
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        for input in [
+            "age = nan",
+            "age < inf",
+            "age > -infinity",
+            "score >= 1e400",
+        ] {
+            assert_err!(Segment::parse(input), "{input} should be rejected");
+        }
+    }
+
+    #[test]
+    fn valid_expressions_are_accepted() {
+        for input in [
+            "tag:rust",
+            "NOT tag:beta",
+            "tag:rust AND NOT tag:beta",
+            "signup_source = \"conference\" or age < 21.5",
+            "city = \"Port \\\"Harcourt\\\"\"",
+        ] {
+            assert_ok!(Segment::parse(input), "{input} should be accepted");
+        }
+    }
+
+    #[test]
+    fn values_are_bound_rather_than_inlined() {
+        let sql = sql("tag:rust AND signup_source = \"x' OR 1=1 --\"");
+        assert_eq!(sql, "(s.tags @> ARRAY[$1] AND s.attributes -> $2 = $3)");
+    }
+
+    #[test]
+    fn orderings_guard_against_mismatched_types() {
+        let sql = sql("age > 18");
+        assert_eq!(
+            sql,
+            "CASE WHEN jsonb_typeof(s.attributes -> $1) = 'number' \
+            THEN (s.attributes ->> $2)::float8 > $3 ELSE false END"
+        );
+    }
+}
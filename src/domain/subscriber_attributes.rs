@@ -0,0 +1,146 @@
+use serde_json::{Map, Number, Value};
+
+/// Custom fields attached to a subscriber, stored as a JSON object.
+///
+/// Values are typed from their textual representation: `true`/`false`
+/// become booleans, anything that parses as a number becomes a number,
+/// everything else is kept as a string.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn parse<I, K, V>(pairs: I) -> Result<SubscriberAttributes, String>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut attributes = Map::new();
+        for (key, value) in pairs {
+            let key = parse_key(key.as_ref())?;
+            let value = parse_value(value.as_ref())?;
+            attributes.insert(key, value);
+        }
+        Ok(Self(attributes))
+    }
+
+    /// Parse one `key=value` pair per line, e.g. the content of a textarea.
+    pub fn parse_lines(s: &str) -> Result<SubscriberAttributes, String> {
+        let mut pairs = Vec::new();
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{line} is not a `key=value` pair."))?;
+            pairs.push((key.trim(), value.trim()));
+        }
+        Self::parse(pairs)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Render the attributes back as one `key=value` pair per line.
+    pub fn to_lines(&self) -> String {
+        self.0
+            .iter()
+            .map(|(key, value)| match value {
+                Value::String(s) => format!("{key}={s}"),
+                other => format!("{key}={other}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn into_json(self) -> Value {
+        Value::Object(self.0)
+    }
+}
+
+impl TryFrom<Value> for SubscriberAttributes {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Object(map) => Ok(Self(map)),
+            other => Err(format!("{other} is not a JSON object.")),
+        }
+    }
+}
+
+/// Attribute keys double as identifiers in segment expressions,
+/// so they follow the usual identifier rules.
+pub(crate) fn parse_key(key: &str) -> Result<String, String> {
+    let mut chars = key.chars();
+    let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_lowercase());
+    let is_identifier = chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    let is_reserved = ["tag", "and", "or", "not", "true", "false"].contains(&key);
+    if !starts_with_letter || !is_identifier || is_reserved || key.len() > 64 {
+        Err(format!("{key} is not a valid attribute name."))
+    } else {
+        Ok(key.to_string())
+    }
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if value.chars().count() > 1024 {
+        return Err("Attribute values cannot be longer than 1024 characters.".to_string());
+    }
+    let value = if let Ok(b) = value.parse::<bool>() {
+        Value::Bool(b)
+    } else if let Ok(n) = value.parse::<i64>() {
+        Value::from(n)
+    } else if let Some(n) = value.parse::<f64>().ok().and_then(Number::from_f64) {
+        Value::Number(n)
+    } else {
+        Value::String(value.to_string())
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberAttributes;
+    use claims::assert_err;
+    use serde_json::json;
+
+    #[test]
+    fn values_are_typed() {
+        let attributes = SubscriberAttributes::parse([
+            ("signup_source", "conference"),
+            ("age", "42"),
+            ("score", "4.5"),
+            ("beta", "true"),
+        ])
+        .unwrap();
+        assert_eq!(
+            attributes.into_json(),
+            json!({"signup_source": "conference", "age": 42, "score": 4.5, "beta": true})
+        );
+    }
+
+    #[test]
+    fn lines_are_parsed_as_key_value_pairs() {
+        let attributes = SubscriberAttributes::parse_lines("city = Lagos\n\nage=30\n").unwrap();
+        assert_eq!(attributes.into_json(), json!({"city": "Lagos", "age": 30}));
+    }
+
+    #[test]
+    fn lines_without_an_equal_sign_are_rejected() {
+        assert_err!(SubscriberAttributes::parse_lines("city"));
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        for key in ["", "City", "1st", "signup-source", "tag", "and"] {
+            assert_err!(SubscriberAttributes::parse([(key, "value")]));
+        }
+    }
+
+    #[test]
+    fn attributes_round_trip_through_lines() {
+        let lines = "age=30\ncity=Lagos";
+        let attributes = SubscriberAttributes::parse_lines(lines).unwrap();
+        assert_eq!(attributes.to_lines(), lines);
+    }
+}
@@ -14,6 +14,38 @@ impl SubscriberEmail {
     }
 }
 
+/// Providers known to deliver `j.doe+news@gmail.com` to `jdoe@gmail.com`.
+const GMAIL_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+
+/// Deployment-specific rules used to derive the canonical form of an address.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default)]
+pub struct EmailNormalization {
+    /// Ignore dots and `+` suffixes in the local part of Gmail addresses.
+    pub fold_gmail_addresses: bool,
+}
+
+impl SubscriberEmail {
+    /// The form used to tell whether two addresses reach the same mailbox:
+    /// lowercased, with the domain in its ASCII (punycode) form and the
+    /// provider-specific rules enabled in `rules` applied.
+    pub fn canonical(&self, rules: &EmailNormalization) -> String {
+        let (local, _) = self.0.rsplit_once('@').unwrap_or_default();
+        let mut local = local.to_lowercase();
+        let mut domain = self.ascii_domain();
+        if rules.fold_gmail_addresses && GMAIL_DOMAINS.contains(&domain.as_str()) {
+            local = local.split('+').next().unwrap_or_default().replace('.', "");
+            domain = GMAIL_DOMAINS[0].to_string();
+        }
+        format!("{local}@{domain}")
+    }
+
+    /// The lowercased domain of the address, in its ASCII (punycode) form.
+    pub fn ascii_domain(&self) -> String {
+        let (_, domain) = self.0.rsplit_once('@').unwrap_or_default();
+        idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase())
+    }
+}
+
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -28,7 +60,7 @@ impl AsRef<str> for SubscriberEmail {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberEmail;
+    use super::{EmailNormalization, SubscriberEmail};
     use claims::assert_err;
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
@@ -58,6 +90,36 @@ mod tests {
         let email = "@gmail.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+    fn canonical(email: &str, fold_gmail_addresses: bool) -> String {
+        SubscriberEmail::parse(email.to_string())
+            .unwrap()
+            .canonical(&EmailNormalization {
+                fold_gmail_addresses,
+            })
+    }
+    #[test]
+    fn canonical_form_is_lowercased() {
+        assert_eq!(canonical("Foo@Example.com", false), "foo@example.com");
+    }
+    #[test]
+    fn canonical_form_uses_punycode_domains() {
+        assert_eq!(
+            canonical("tay@Bücher.example", false),
+            "tay@xn--bcher-kva.example"
+        );
+    }
+    #[test]
+    fn gmail_addresses_are_only_folded_when_enabled() {
+        assert_eq!(
+            canonical("T.ay+news@googlemail.com", false),
+            "t.ay+news@googlemail.com"
+        );
+        assert_eq!(canonical("T.ay+news@googlemail.com", true), "tay@gmail.com");
+        assert_eq!(
+            canonical("t.ay+news@example.com", true),
+            "t.ay+news@example.com"
+        );
+    }
     #[quickcheck_macros::quickcheck]
     fn valid_email_is_accepted(valid_email: ValidEmailFixture) -> bool {
         // assert_ok!(SubscriberEmail::parse(valid_email));
@@ -0,0 +1,74 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    // Tags are matched case-insensitively, so we store them lowercased.
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.len() > 64;
+        let contains_forbidden_characters = tag
+            .chars()
+            .any(|c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{s} is not a valid subscriber tag."))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+
+    /// Parse a comma-separated list of tags, e.g. `rust, beta`.
+    /// Duplicates are dropped, an empty input yields no tags.
+    pub fn parse_many(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = Vec::new();
+        for tag in s.split(',').filter(|t| !t.trim().is_empty()) {
+            let tag = SubscriberTag::parse(tag.to_string())?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_lowercased() {
+        let tag = SubscriberTag::parse(" Rust ".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "rust");
+    }
+
+    #[test]
+    fn dashes_and_underscores_are_accepted() {
+        assert_ok!(SubscriberTag::parse("early_bird-2023".to_string()));
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse(" ".to_string()));
+    }
+
+    #[test]
+    fn tags_with_spaces_or_punctuation_are_rejected() {
+        for tag in ["early bird", "tag:rust", "rust!", "\"beta\""] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn many_tags_are_deduplicated() {
+        let tags = SubscriberTag::parse_many("rust, Beta,rust,,").unwrap();
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["rust", "beta"]);
+    }
+}
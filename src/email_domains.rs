@@ -0,0 +1,182 @@
+//! Which email domains may subscribe.
+//!
+//! Domains listed in the bundled disposable-domain file and the ones blocked
+//! by admins are rejected, unless an admin allowed them. Rules also apply to
+//! subdomains. Both lists are kept in memory and reloaded on demand.
+use crate::configuration::EmailDomainSettings;
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// An admin-defined rule for a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainRule {
+    Blocked,
+    Allowed,
+}
+
+impl DomainRule {
+    pub fn parse(s: &str) -> Result<DomainRule, String> {
+        match s {
+            "blocked" => Ok(Self::Blocked),
+            "allowed" => Ok(Self::Allowed),
+            other => Err(format!("{other} is not a valid domain rule.")),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainRule::Blocked => "blocked",
+            DomainRule::Allowed => "allowed",
+        }
+    }
+}
+
+/// Turn an admin-submitted domain into the form we compare addresses against.
+pub fn parse_domain(s: &str) -> Result<String, String> {
+    let domain = idna::domain_to_ascii(s.trim().trim_end_matches('.'))
+        .map_err(|_| format!("{s} is not a valid domain."))?;
+    let is_valid = domain.contains('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    if is_valid {
+        Ok(domain)
+    } else {
+        Err(format!("{s} is not a valid domain."))
+    }
+}
+
+#[derive(Debug, Default)]
+struct DomainLists {
+    disposable: HashSet<String>,
+    blocked: HashSet<String>,
+    allowed: HashSet<String>,
+}
+
+impl DomainLists {
+    fn check(&self, domain: &str) -> Result<(), String> {
+        let matches = |list: &HashSet<String>| suffixes(domain).any(|d| list.contains(d));
+        if matches(&self.allowed) {
+            Ok(())
+        } else if matches(&self.blocked) {
+            Err(format!("Subscriptions from {domain} are not accepted."))
+        } else if matches(&self.disposable) {
+            Err(format!(
+                "{domain} provides disposable email addresses, please use a permanent one."
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// `a.b.example.com`, `b.example.com`, `example.com`, `com`.
+fn suffixes(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |d| d.split_once('.').map(|(_, rest)| rest))
+}
+
+pub struct EmailDomainPolicy {
+    disposable_domains_path: PathBuf,
+    lists: RwLock<DomainLists>,
+}
+
+impl EmailDomainPolicy {
+    pub async fn load(
+        settings: &EmailDomainSettings,
+        pool: &PgPool,
+    ) -> Result<EmailDomainPolicy, anyhow::Error> {
+        let policy = Self {
+            disposable_domains_path: settings.disposable_domains_path.clone().into(),
+            lists: RwLock::new(DomainLists::default()),
+        };
+        policy.reload(pool).await?;
+        Ok(policy)
+    }
+
+    /// Read the disposable-domain file and the admin rules again.
+    /// Returns the number of disposable domains.
+    #[tracing::instrument(name = "Reload email domain lists", skip(self, pool))]
+    pub async fn reload(&self, pool: &PgPool) -> Result<usize, anyhow::Error> {
+        let disposable = read_domain_file(&self.disposable_domains_path).await?;
+        let rules = sqlx::query!(r#"SELECT domain, rule FROM email_domain_rules"#)
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve the email domain rules.")?;
+        let mut lists = DomainLists {
+            disposable,
+            ..DomainLists::default()
+        };
+        for rule in rules {
+            match DomainRule::parse(&rule.rule).map_err(anyhow::Error::msg)? {
+                DomainRule::Blocked => lists.blocked.insert(rule.domain),
+                DomainRule::Allowed => lists.allowed.insert(rule.domain),
+            };
+        }
+        let n_disposable = lists.disposable.len();
+        *self.lists.write().unwrap() = lists;
+        Ok(n_disposable)
+    }
+
+    pub fn disposable_domain_count(&self) -> usize {
+        self.lists.read().unwrap().disposable.len()
+    }
+
+    /// Returns a message for the subscriber if their address is not accepted.
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        self.lists.read().unwrap().check(&email.ascii_domain())
+    }
+}
+
+async fn read_domain_file(path: &Path) -> Result<HashSet<String>, anyhow::Error> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| parse_domain(line).map_err(|e| anyhow::anyhow!("{e} ({})", path.display())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_domain, DomainLists};
+    use claims::{assert_err, assert_ok};
+
+    fn lists() -> DomainLists {
+        DomainLists {
+            disposable: ["mailinator.com".into(), "yopmail.com".into()].into(),
+            blocked: ["spam.example".into()].into(),
+            allowed: ["yopmail.com".into()].into(),
+        }
+    }
+
+    #[test]
+    fn disposable_and_blocked_domains_are_rejected_with_their_subdomains() {
+        assert_err!(lists().check("mailinator.com"));
+        assert_err!(lists().check("eu.mailinator.com"));
+        assert_err!(lists().check("spam.example"));
+        assert_ok!(lists().check("notmailinator.com"));
+        assert_ok!(lists().check("example.com"));
+    }
+
+    #[test]
+    fn allowed_domains_override_the_other_lists() {
+        assert_ok!(lists().check("yopmail.com"));
+    }
+
+    #[test]
+    fn domains_are_normalized() {
+        assert_eq!(
+            parse_domain(" Bücher.Example. "),
+            Ok("xn--bcher-kva.example".into())
+        );
+        assert_err!(parse_domain("localhost"));
+        assert_err!(parse_domain("user@example.com"));
+    }
+}
@@ -0,0 +1,85 @@
+//! Message catalogs of the emails and pages seen by subscribers.
+//!
+//! There is one catalog per supported locale in `locales/<locale>.json`,
+//! bundled at compile time. Messages missing from a catalog fall back to
+//! the default locale. `{name}` placeholders are filled in by [`t`], which
+//! does not escape anything: HTML arguments must be escaped by the caller.
+use crate::domain::Locale;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::HttpRequest;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("fr", include_str!("../locales/fr.json")),
+];
+
+type Catalog = HashMap<String, String>;
+
+fn catalogs() -> &'static HashMap<&'static str, Catalog> {
+    static CATALOGS_BY_LOCALE: OnceLock<HashMap<&'static str, Catalog>> = OnceLock::new();
+    CATALOGS_BY_LOCALE.get_or_init(|| {
+        CATALOGS
+            .iter()
+            .map(|(locale, json)| {
+                let catalog = serde_json::from_str(json)
+                    .unwrap_or_else(|e| panic!("The {locale} message catalog is invalid: {e}"));
+                (*locale, catalog)
+            })
+            .collect()
+    })
+}
+
+/// The message `key` in `locale`, with the placeholders replaced by `args`.
+pub fn t(locale: Locale, key: &str, args: &[(&str, &str)]) -> String {
+    let lookup = |locale: Locale| catalogs().get(locale.as_ref())?.get(key);
+    let Some(message) = lookup(locale).or_else(|| lookup(Locale::default())) else {
+        tracing::error!("Missing message: {key}");
+        return key.to_string();
+    };
+    args.iter().fold(message.clone(), |message, (name, value)| {
+        message.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// The locale a browser asks for, the default one if we do not support it.
+pub fn request_locale(request: &HttpRequest) -> Locale {
+    request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|h| h.to_str().ok())
+        .and_then(Locale::from_accept_language)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catalogs, t};
+    use crate::domain::Locale;
+
+    #[test]
+    fn every_supported_locale_has_a_complete_catalog() {
+        let default = &catalogs()[Locale::default().as_ref()];
+        for locale in Locale::all() {
+            let catalog = &catalogs()[locale.as_ref()];
+            let mut missing: Vec<_> = default
+                .keys()
+                .filter(|k| !catalog.contains_key(*k))
+                .collect();
+            missing.sort();
+            assert!(missing.is_empty(), "{locale} is missing {missing:?}");
+            assert!(catalog.keys().all(|k| default.contains_key(k)));
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let fr = Locale::parse("fr").unwrap();
+        assert_eq!(
+            t(fr, "subscribe_page.title", &[("list_name", "Rust")]),
+            "S'abonner à Rust"
+        );
+        assert_eq!(t(fr, "no.such.message", &[]), "no.such.message");
+    }
+}
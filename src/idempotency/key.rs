@@ -1,3 +1,6 @@
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+
 #[derive(Debug)]
 pub struct IdempotencyKey(String);
 
@@ -26,3 +29,15 @@ impl AsRef<str> for IdempotencyKey {
         &self.0
     }
 }
+
+/// What an idempotency key is used for: the method, the path and a hash of
+/// `payload`, so that a key cannot be replayed against another endpoint or
+/// with other parameters.
+pub fn request_fingerprint(request: &HttpRequest, payload: &[u8]) -> String {
+    format!(
+        "{} {} {}",
+        request.method(),
+        request.path(),
+        hex::encode(Sha256::digest(payload))
+    )
+}
@@ -1,6 +1,6 @@
 mod key;
 mod persistence;
 
-pub use key::IdempotencyKey;
+pub use key::{request_fingerprint, IdempotencyKey};
 pub use persistence::{get_saved_response, save_response};
 pub use persistence::{try_processing, NextAction};
@@ -106,12 +106,15 @@ type PostgresTransaction = Transaction<'static, Postgres>;
 pub enum NextAction {
     StartProcessing(PostgresTransaction),
     ReturnSavedResponse(HttpResponse),
+    /// The key was already used for another request.
+    RejectReusedKey,
 }
 
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
+    request_fingerprint: &str,
 ) -> Result<NextAction, anyhow::Error> {
     let mut transaction = pool.begin().await?;
 
@@ -126,13 +129,15 @@ pub async fn try_processing(
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
+            request_fingerprint,
             created_at
           )
-          VALUES ($1, $2, now())
+          VALUES ($1, $2, $3, now())
           ON CONFLICT DO NOTHING
         "#,
         user_id,
-        idempotency_key.as_ref()
+        idempotency_key.as_ref(),
+        request_fingerprint
     )
     .execute(&mut transaction)
     .await?
@@ -141,6 +146,22 @@ pub async fn try_processing(
     if n_inserted_rows > 0 {
         Ok(NextAction::StartProcessing(transaction))
     } else {
+        let stored_fingerprint = sqlx::query!(
+            r#"
+            SELECT request_fingerprint
+            FROM idempotency
+            WHERE user_id = $1 AND idempotency_key = $2
+            "#,
+            user_id,
+            idempotency_key.as_ref()
+        )
+        .fetch_one(pool)
+        .await?
+        .request_fingerprint;
+        // Keys stored before fingerprints were recorded are trusted.
+        if stored_fingerprint.is_some_and(|f| f != request_fingerprint) {
+            return Ok(NextAction::RejectReusedKey);
+        }
         let saved_response = get_saved_response(&pool, &idempotency_key, user_id)
             .await?
             .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
@@ -1,8 +1,15 @@
+use crate::authentication::create_password_reset_token;
+use crate::automation::stop_inactive_sequences;
 use crate::configuration::Settings;
-use crate::domain::SubscriberEmail;
+use crate::domain::{Locale, SubscriberEmail};
 use crate::email_client::EmailClient;
+use crate::i18n::t;
+use crate::routes::{
+    create_data_request_token, send_confirmation_email, send_data_request_email,
+    send_password_reset_email,
+};
 use crate::startup::get_connection_pool;
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
 use std::time::Duration;
 use tracing::{field::display, Span};
 use uuid::Uuid;
@@ -23,28 +30,39 @@ pub enum ExecutionOutcome {
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (mut transaction, issue_id, email) = task.unwrap();
 
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&email));
 
+    let Some(recipient) = get_recipient(pool, &email).await? else {
+        tracing::info!(
+            "Skipping a subscriber who unsubscribed or paused their mail \
+            since the issue was published."
+        );
+        delete_task(transaction, issue_id, &email).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
     // TODO: send email
-    match SubscriberEmail::parse(email.clone()) {
+    let delivered = match SubscriberEmail::parse(email.clone()) {
         Ok(email) => {
-            let issue = get_issue(&pool, issue_id).await?;
+            let locale = recipient.locale;
+            let issue = get_issue(pool, issue_id, locale).await?;
+            let footer = PreferencesFooter::new(base_url, &recipient.preferences_token, locale);
 
             if let Err(e) = email_client
                 .send_email(
                     &email,
                     &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+                    &format!("{}{}", issue.html_content, footer.html),
+                    &format!("{}{}", issue.text_content, footer.text),
                 )
                 .await
             {
@@ -54,6 +72,9 @@ pub async fn try_execute_task(
                     "Failed to deliver issue to a confirmed subscriber. \
                     Skipping.",
                 );
+                false
+            } else {
+                true
             }
         }
         Err(e) => {
@@ -67,9 +88,11 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. \
                 Their stored contact details are invalid",
             );
+            false
         }
-    }
+    };
 
+    record_delivery_outcome(&mut transaction, &[issue_id], delivered).await?;
     delete_task(transaction, issue_id, &email).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
@@ -125,32 +148,663 @@ async fn delete_task(
     Ok(())
 }
 
+/// Count a delivery of each of `issue_ids` as delivered or failed.
+async fn record_delivery_outcome(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_ids: &[Uuid],
+    delivered: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            n_delivered = n_delivered + CASE WHEN $2 THEN 1 ELSE 0 END,
+            n_failed = n_failed + CASE WHEN $2 THEN 0 ELSE 1 END
+        WHERE newsletter_issue_id = ANY($1)
+        "#,
+        issue_ids,
+        delivered
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
 }
 
+/// The issue in `locale`, in the default locale if it was not translated.
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    locale: Locale,
+) -> Result<NewsletterIssue, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
-        FROM newsletter_issues
+        SELECT
+            COALESCE(v.title, i.title) AS "title!",
+            COALESCE(v.text_content, i.text_content) AS "text_content!",
+            COALESCE(v.html_content, i.html_content) AS "html_content!"
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_issue_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id AND v.locale = $2
         WHERE 
-            newsletter_issue_id = $1
+            i.newsletter_issue_id = $1
         "#,
-        issue_id
+        issue_id,
+        locale.as_ref()
     )
     .fetch_one(pool)
     .await?;
     Ok(issue)
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+struct Recipient {
+    preferences_token: String,
+    locale: Locale,
+}
+
+/// The subscriber behind `email`, if they still want to receive issues:
+/// confirmed, not paused and on at least one mailing list.
+#[tracing::instrument(skip_all)]
+async fn get_recipient(pool: &PgPool, email: &str) -> Result<Option<Recipient>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT s.preferences_token, s.locale
+        FROM subscriptions s
+        WHERE
+            s.email = $1 AND
+            s.status = 'confirmed' AND
+            (s.paused_until IS NULL OR s.paused_until <= now()) AND
+            EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.subscriber_id = s.id AND m.status = 'confirmed'
+            )
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r.map(|r| Recipient {
+        preferences_token: r.preferences_token,
+        locale: stored_locale(&r.locale),
+    }))
+}
+
+/// Locales are validated on the way in, but may since have stopped being supported.
+fn stored_locale(locale: &str) -> Locale {
+    Locale::parse(locale).unwrap_or_default()
+}
+
+/// Link to the preference center appended to every email we send.
+struct PreferencesFooter {
+    html: String,
+    text: String,
+}
+
+impl PreferencesFooter {
+    fn new(base_url: &str, preferences_token: &str, locale: Locale) -> Self {
+        let link = format!("{}/preferences?token={}", base_url, preferences_token);
+        let label = t(locale, "email_footer.manage_preferences", &[]);
+        Self {
+            html: format!(
+                "<hr /><p><a href=\"{}\">{}</a></p>",
+                link,
+                htmlescape::encode_minimal(&label)
+            ),
+            text: format!("\n\n--\n{}: {}", label, link),
+        }
+    }
+}
+
+/// Send one digest to a subscriber whose oldest queued issue has been
+/// waiting for at least a week, bundling every issue queued for them.
+#[tracing::instrument(skip_all, fields(subscriber_email = tracing::field::Empty), err)]
+pub async fn try_send_digest(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT d.subscriber_id, s.email, s.preferences_token, s.locale
+        FROM digest_queue d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE
+            d.queued_at <= now() - interval '7 days' AND
+            (s.paused_until IS NULL OR s.paused_until <= now())
+        FOR UPDATE OF d
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(r) = r else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("subscriber_email", display(&r.email));
+    let locale = stored_locale(&r.locale);
+
+    let issues = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            COALESCE(v.title, i.title) AS "title!",
+            COALESCE(v.text_content, i.text_content) AS "text_content!",
+            COALESCE(v.html_content, i.html_content) AS "html_content!"
+        FROM digest_queue d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        LEFT JOIN newsletter_issue_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id AND v.locale = $2
+        WHERE d.subscriber_id = $1
+        ORDER BY i.published_at
+        FOR UPDATE OF d
+        SKIP LOCKED
+        "#,
+        r.subscriber_id,
+        locale.as_ref()
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+
+    let delivered = match SubscriberEmail::parse(r.email) {
+        Ok(email) => {
+            let footer = PreferencesFooter::new(base_url, &r.preferences_token, locale);
+            let html_content = issues
+                .iter()
+                .map(|i| {
+                    format!(
+                        "<h1>{}</h1>{}",
+                        htmlescape::encode_minimal(&i.title),
+                        i.html_content
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("<hr />");
+            let text_content = issues
+                .iter()
+                .map(|i| format!("{}\n\n{}", i.title, i.text_content))
+                .collect::<Vec<_>>()
+                .join("\n\n---\n\n");
+            if let Err(e) = email_client
+                .send_email(
+                    &email,
+                    &t(locale, "digest_email.subject", &[]),
+                    &format!("{}{}", html_content, footer.html),
+                    &format!("{}{}", text_content, footer.text),
+                )
+                .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver a digest to a subscriber. \
+                    Skipping.",
+                );
+                false
+            } else {
+                true
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a digest subscriber. \
+                Their stored contact details are invalid",
+            );
+            false
+        }
+    };
+
+    let issue_ids: Vec<Uuid> = issues.iter().map(|i| i.newsletter_issue_id).collect();
+    record_delivery_outcome(&mut transaction, &issue_ids, delivered).await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM digest_queue
+        WHERE subscriber_id = $1 AND newsletter_issue_id = ANY($2)
+        "#,
+        r.subscriber_id,
+        &issue_ids[..]
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Deliver one email from the confirmation outbox.
+/// Transient failures are retried later with an exponential backoff;
+/// the row is only dropped once it is sent or the address is rejected.
+#[tracing::instrument(skip_all, fields(subscriber_email = tracing::field::Empty), err)]
+pub async fn try_send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT o.outbox_id, o.subscriber_email, o.subscription_token, o.n_retries, s.locale
+        FROM confirmation_email_outbox o
+        JOIN subscriptions s ON s.id = o.subscriber_id
+        WHERE o.execute_after <= now()
+        ORDER BY o.enqueued_at
+        FOR UPDATE OF o
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(r) = r else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("subscriber_email", display(&r.subscriber_email));
+
+    match SubscriberEmail::parse(r.subscriber_email) {
+        Ok(email) => {
+            if let Err(e) = send_confirmation_email(
+                email_client,
+                &email,
+                stored_locale(&r.locale),
+                base_url,
+                &r.subscription_token,
+            )
+            .await
+            {
+                if !is_permanent_failure(&e) {
+                    let backoff = retry_backoff_seconds(r.n_retries);
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver a confirmation email. \
+                        Retrying in {} seconds.",
+                        backoff,
+                    );
+                    sqlx::query!(
+                        r#"
+                        UPDATE confirmation_email_outbox
+                        SET n_retries = n_retries + 1,
+                            execute_after = now() + make_interval(secs => $2)
+                        WHERE outbox_id = $1
+                        "#,
+                        r.outbox_id,
+                        backoff as f64
+                    )
+                    .execute(&mut transaction)
+                    .await?;
+                    transaction.commit().await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver a confirmation email. \
+                    The address was rejected, skipping.",
+                );
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmation email. \
+                The stored contact details are invalid",
+            );
+        }
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM confirmation_email_outbox WHERE outbox_id = $1"#,
+        r.outbox_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Mail the next password reset link that was asked for, creating its token.
+/// Failures are retried like confirmation emails.
+#[tracing::instrument(skip_all, fields(username = tracing::field::Empty), err)]
+pub async fn try_send_password_reset_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT o.outbox_id, o.n_retries, u.user_id, u.username, u.email
+        FROM password_reset_email_outbox o
+        JOIN users u ON u.user_id = o.user_id
+        WHERE o.execute_after <= now()
+        ORDER BY o.enqueued_at
+        FOR UPDATE OF o
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(r) = r else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("username", display(&r.username));
+
+    match r.email.map(SubscriberEmail::parse) {
+        Some(Ok(email)) => {
+            // The token is only kept if it was sent.
+            let mut savepoint = Acquire::begin(&mut transaction).await?;
+            let token = create_password_reset_token(&mut savepoint, r.user_id).await?;
+            let sent =
+                send_password_reset_email(email_client, &email, base_url, &r.username, &token)
+                    .await;
+            match &sent {
+                Ok(()) => savepoint.commit().await?,
+                Err(_) => savepoint.rollback().await?,
+            }
+            if let Err(e) = sent {
+                if !is_permanent_failure(&e) {
+                    let backoff = retry_backoff_seconds(r.n_retries);
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver a password reset email. \
+                        Retrying in {} seconds.",
+                        backoff,
+                    );
+                    sqlx::query!(
+                        r#"
+                        UPDATE password_reset_email_outbox
+                        SET n_retries = n_retries + 1,
+                            execute_after = now() + make_interval(secs => $2)
+                        WHERE outbox_id = $1
+                        "#,
+                        r.outbox_id,
+                        backoff as f64
+                    )
+                    .execute(&mut transaction)
+                    .await?;
+                    transaction.commit().await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver a password reset email. \
+                    The address was rejected, skipping.",
+                );
+            }
+        }
+        _ => {
+            tracing::warn!("Skipping a password reset email. The user has no valid email address.");
+        }
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM password_reset_email_outbox WHERE outbox_id = $1"#,
+        r.outbox_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Mail the next data request link that was asked for, creating its token.
+/// Failures are retried like confirmation emails.
+#[tracing::instrument(skip_all, fields(subscriber_email = tracing::field::Empty), err)]
+pub async fn try_send_data_request_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT o.outbox_id, o.n_retries, s.email
+        FROM data_request_email_outbox o
+        JOIN subscriptions s ON s.id = o.subscriber_id
+        WHERE o.execute_after <= now()
+        ORDER BY o.enqueued_at
+        FOR UPDATE OF o
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(r) = r else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("subscriber_email", display(&r.email));
+
+    match SubscriberEmail::parse(r.email.clone()) {
+        Ok(email) => {
+            // The token is only kept if it was sent.
+            let mut savepoint = Acquire::begin(&mut transaction).await?;
+            let token = create_data_request_token(&mut savepoint, &r.email).await?;
+            let sent = send_data_request_email(email_client, &email, base_url, &token).await;
+            match &sent {
+                Ok(()) => savepoint.commit().await?,
+                Err(_) => savepoint.rollback().await?,
+            }
+            if let Err(e) = sent {
+                if !is_permanent_failure(&e) {
+                    let backoff = retry_backoff_seconds(r.n_retries);
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver a data request email. \
+                        Retrying in {} seconds.",
+                        backoff,
+                    );
+                    sqlx::query!(
+                        r#"
+                        UPDATE data_request_email_outbox
+                        SET n_retries = n_retries + 1,
+                            execute_after = now() + make_interval(secs => $2)
+                        WHERE outbox_id = $1
+                        "#,
+                        r.outbox_id,
+                        backoff as f64
+                    )
+                    .execute(&mut transaction)
+                    .await?;
+                    transaction.commit().await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver a data request email. \
+                    The address was rejected, skipping.",
+                );
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a data request email. \
+                The stored contact details are invalid",
+            );
+        }
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM data_request_email_outbox WHERE outbox_id = $1"#,
+        r.outbox_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Seconds to wait before the next attempt: 30s, doubling up to an hour.
+fn retry_backoff_seconds(n_retries: i32) -> i64 {
+    let exponent = n_retries.clamp(0, 7) as u32;
+    (30 * 2i64.pow(exponent)).min(60 * 60)
+}
+
+/// The email service refused the request itself (4xx other than 429),
+/// so sending it again would fail the same way.
+fn is_permanent_failure(e: &reqwest::Error) -> bool {
+    e.status().is_some_and(|status| {
+        status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    })
+}
+
+/// Send the next step of one automation sequence that is due.
+/// Sequences of subscribers who left the list are stopped instead.
+#[tracing::instrument(skip_all, fields(subscriber_email = tracing::field::Empty), err)]
+pub async fn try_send_sequence_step(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            p.sequence_id,
+            p.subscriber_id,
+            p.next_position AS "next_position!",
+            s.email,
+            s.preferences_token,
+            s.locale,
+            s.status AS subscriber_status,
+            m.status AS "membership_status?",
+            st.title,
+            st.text_content,
+            st.html_content
+        FROM automation_progress p
+        JOIN automation_sequences q ON q.sequence_id = p.sequence_id
+        JOIN automation_steps st
+            ON st.sequence_id = p.sequence_id AND st.position = p.next_position
+        JOIN subscriptions s ON s.id = p.subscriber_id
+        LEFT JOIN list_memberships m
+            ON m.list_id = q.list_id AND m.subscriber_id = p.subscriber_id
+        WHERE
+            p.status = 'active' AND
+            p.next_send_at <= now() AND
+            (s.paused_until IS NULL OR s.paused_until <= now())
+        FOR UPDATE OF p
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(r) = r else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("subscriber_email", display(&r.email));
+
+    if r.subscriber_status == "unsubscribed" || r.membership_status.as_deref() != Some("confirmed")
+    {
+        stop_inactive_sequences(&mut transaction, r.subscriber_id).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
+    match SubscriberEmail::parse(r.email) {
+        Ok(email) => {
+            let footer =
+                PreferencesFooter::new(base_url, &r.preferences_token, stored_locale(&r.locale));
+            if let Err(e) = email_client
+                .send_email(
+                    &email,
+                    &r.title,
+                    &format!("{}{}", r.html_content, footer.html),
+                    &format!("{}{}", r.text_content, footer.text),
+                )
+                .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver an automation step to a subscriber. \
+                    Skipping.",
+                );
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping an automation step. \
+                The subscriber's stored contact details are invalid",
+            );
+        }
+    }
+
+    // Delays are counted from the start of the sequence, so a step that
+    // is already due is sent on the next run.
+    sqlx::query!(
+        r#"
+        UPDATE automation_progress p
+        SET
+            steps_sent = p.steps_sent + 1,
+            next_position = next.position,
+            next_send_at = p.started_at + make_interval(days => next.delay_days),
+            status = CASE WHEN next.position IS NULL THEN 'completed' ELSE 'active' END,
+            updated_at = now()
+        FROM (
+            SELECT
+                (SELECT position FROM automation_steps
+                 WHERE sequence_id = $1 AND position > $3
+                 ORDER BY position LIMIT 1) AS position,
+                (SELECT delay_days FROM automation_steps
+                 WHERE sequence_id = $1 AND position > $3
+                 ORDER BY position LIMIT 1) AS delay_days
+        ) next
+        WHERE p.sequence_id = $1 AND p.subscriber_id = $2
+        "#,
+        r.sequence_id,
+        r.subscriber_id,
+        r.next_position
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        // Confirmations and links asked for first: someone is waiting for them.
+        let mut outcome = try_send_confirmation_email(&pool, &email_client, &base_url).await;
+        if let Ok(ExecutionOutcome::EmptyQueue) = outcome {
+            outcome = try_send_password_reset_email(&pool, &email_client, &base_url).await;
+        }
+        if let Ok(ExecutionOutcome::EmptyQueue) = outcome {
+            outcome = try_send_data_request_email(&pool, &email_client, &base_url).await;
+        }
+        if let Ok(ExecutionOutcome::EmptyQueue) = outcome {
+            outcome = try_execute_task(&pool, &email_client, &base_url).await;
+        }
+        if let Ok(ExecutionOutcome::EmptyQueue) = outcome {
+            outcome = try_send_sequence_step(&pool, &email_client, &base_url).await;
+        }
+        if let Ok(ExecutionOutcome::EmptyQueue) = outcome {
+            outcome = try_send_digest(&pool, &email_client, &base_url).await;
+        }
+        match outcome {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -165,5 +819,10 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+    )
+    .await
 }
@@ -1,10 +1,15 @@
 use crate::routes::{health_check, subscribe};
 
 pub mod authentication;
+pub mod automation;
+pub mod canonical_emails;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_domains;
+pub mod i18n;
 pub mod idempotency;
+pub mod rate_limit;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
@@ -12,3 +17,5 @@ pub mod telemetry;
 pub mod utils;
 
 pub mod issue_delivery_worker;
+pub mod personal_data;
+pub mod subscriber_import;
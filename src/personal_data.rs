@@ -0,0 +1,338 @@
+//! Subject access and right-to-erasure requests.
+//!
+//! We do not keep a history of status changes nor track opens and clicks:
+//! the archive covers every table holding data about an email address.
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use anyhow::Context;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The key of an address in the suppression list: the hash of its canonical
+/// form. Only hashes are kept, so they could not be recomputed if the
+/// normalization settings changed: Gmail addresses are always folded.
+pub fn email_hash(email: &str) -> String {
+    let email = email.trim();
+    let canonical = match SubscriberEmail::parse(email.to_owned()) {
+        Ok(email) => email.canonical(&EmailNormalization {
+            fold_gmail_addresses: true,
+        }),
+        Err(_) => email.to_lowercase(),
+    };
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// Returns `true` if `email` was erased at its owner's request.
+#[tracing::instrument(name = "Check the suppression list", skip(pool, email))]
+pub async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"SELECT email_hash FROM suppressed_emails WHERE email_hash = $1"#,
+        email_hash(email)
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(record.is_some())
+}
+
+/// Everything we hold about `email` as a JSON document,
+/// `None` if we do not hold anything.
+#[tracing::instrument(name = "Build a personal data archive", skip(pool, email))]
+pub async fn personal_data_archive(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<serde_json::Value>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, tags, attributes,
+            delivery_frequency, paused_until, locale
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    let subscriber_id = subscriber.as_ref().map(|s| s.id);
+
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status, m.subscribed_at, m.confirmed_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list memberships.")?;
+    let tokens = sqlx::query!(
+        r#"
+        SELECT t.subscription_token, l.slug, t.expired
+        FROM subscription_tokens t
+        JOIN lists l ON l.list_id = t.list_id
+        WHERE t.subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscription tokens.")?;
+    let pending_deliveries = sqlx::query!(
+        r#"
+        SELECT i.newsletter_issue_id, i.title
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending deliveries.")?;
+    let pending_digest = sqlx::query!(
+        r#"
+        SELECT i.newsletter_issue_id, i.title, d.queued_at
+        FROM digest_queue d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.queued_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending digest.")?;
+    let consents = sqlx::query!(
+        r#"
+        SELECT
+            l.slug, c.source, c.consent_text_version, c.signup_ip, c.signup_user_agent,
+            c.signed_up_at, c.confirmation_ip, c.confirmation_user_agent, c.confirmed_at
+        FROM consent_records c
+        JOIN lists l ON l.list_id = c.list_id
+        WHERE c.subscriber_id = $1
+        ORDER BY c.signed_up_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the consent records.")?;
+    let automations = sqlx::query!(
+        r#"
+        SELECT q.name, p.status, p.steps_sent, p.next_send_at, p.started_at
+        FROM automation_progress p
+        JOIN automation_sequences q ON q.sequence_id = p.sequence_id
+        WHERE p.subscriber_id = $1
+        ORDER BY p.started_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the automation progress.")?;
+    let import_rejections = sqlx::query!(
+        r#"
+        SELECT i.created_at, r.line, r.reason
+        FROM subscriber_import_rejections r
+        JOIN subscriber_imports i ON i.import_id = r.import_id
+        WHERE r.email = $1
+        ORDER BY i.created_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the import reports.")?;
+
+    if subscriber.is_none() && pending_deliveries.is_empty() && import_rejections.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(json!({
+        "email": email,
+        "generated_at": chrono::Utc::now().to_rfc3339(),
+        "subscription": subscriber.map(|s| json!({
+            "id": s.id,
+            "email": s.email,
+            "name": s.name,
+            "status": s.status,
+            "subscribed_at": s.subscribed_at.to_rfc3339(),
+            "tags": s.tags,
+            "attributes": s.attributes,
+            "delivery_frequency": s.delivery_frequency,
+            "paused_until": s.paused_until.map(|p| p.to_rfc3339()),
+            "locale": s.locale,
+        })),
+        "list_memberships": memberships.iter().map(|m| json!({
+            "list": m.slug,
+            "status": m.status,
+            "subscribed_at": m.subscribed_at.to_rfc3339(),
+            "confirmed_at": m.confirmed_at.map(|c| c.to_rfc3339()),
+        })).collect::<Vec<_>>(),
+        "consent_records": consents.iter().map(|c| json!({
+            "list": c.slug,
+            "source": c.source,
+            "consent_text_version": c.consent_text_version,
+            "signup_ip": c.signup_ip,
+            "signup_user_agent": c.signup_user_agent,
+            "signed_up_at": c.signed_up_at.to_rfc3339(),
+            "confirmation_ip": c.confirmation_ip,
+            "confirmation_user_agent": c.confirmation_user_agent,
+            "confirmed_at": c.confirmed_at.map(|c| c.to_rfc3339()),
+        })).collect::<Vec<_>>(),
+        "subscription_tokens": tokens.iter().map(|t| json!({
+            "token": t.subscription_token,
+            "list": t.slug,
+            "expired": t.expired,
+        })).collect::<Vec<_>>(),
+        "pending_deliveries": pending_deliveries.iter().map(|d| json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+        })).collect::<Vec<_>>(),
+        "pending_digest": pending_digest.iter().map(|d| json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+            "queued_at": d.queued_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+        "automations": automations.iter().map(|a| json!({
+            "sequence": a.name,
+            "status": a.status,
+            "steps_sent": a.steps_sent,
+            "next_send_at": a.next_send_at.map(|n| n.to_rfc3339()),
+            "started_at": a.started_at.to_rfc3339(),
+        })).collect::<Vec<_>>(),
+        "import_rejections": import_rejections.iter().map(|r| json!({
+            "imported_at": r.created_at.to_rfc3339(),
+            "line": r.line,
+            "reason": r.reason,
+        })).collect::<Vec<_>>(),
+    })))
+}
+
+/// Delete every row about `email` and add its hash to the suppression list.
+#[tracing::instrument(name = "Erase personal data", skip(pool, email))]
+pub async fn erase_personal_data(pool: &PgPool, email: &str) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = sqlx::query!(r#"SELECT id FROM subscriptions WHERE email = $1"#, email)
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to retrieve the subscriber.")?;
+    if let Some(subscriber) = subscriber {
+        delete_subscriber(&mut transaction, subscriber.id)
+            .await
+            .context("Failed to delete the subscriber.")?;
+    }
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the pending deliveries.")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriber_import_rejections WHERE email = $1"#,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the import report rows.")?;
+    sqlx::query!(r#"DELETE FROM data_request_tokens WHERE email = $1"#, email)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the data request tokens.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email_hash, suppressed_at)
+        VALUES ($1, now())
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        email_hash(email)
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to add the email to the suppression list.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase personal data.")?;
+    Ok(())
+}
+
+async fn delete_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM list_memberships WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM consent_records WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM automation_progress WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM confirmation_email_outbox WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM digest_queue WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::email_hash;
+
+    #[test]
+    fn email_hashes_ignore_case_and_surrounding_whitespace() {
+        assert_eq!(
+            email_hash(" Ursula@Example.com"),
+            email_hash("ursula@example.com")
+        );
+        assert_eq!(email_hash("ursula@example.com").len(), 64);
+    }
+
+    #[test]
+    fn email_hashes_use_the_canonical_form() {
+        assert_eq!(
+            email_hash("U.rsula+news@googlemail.com"),
+            email_hash("ursula@gmail.com")
+        );
+        assert_eq!(
+            email_hash("ursula@Bücher.example"),
+            email_hash("ursula@xn--bcher-kva.example")
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Fixed-window rate limiting, in memory or in Redis.
+use crate::configuration::RateLimitBackend;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Most keys tracked in memory. Expired windows are dropped first, then
+/// the oldest ones, so that a flood of new keys cannot exhaust memory.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+struct Window {
+    started_at: Instant,
+    hits: u32,
+}
+
+enum Counters {
+    Memory(Mutex<HashMap<String, Window>>),
+    Redis(ConnectionManager),
+}
+
+pub struct RateLimiter {
+    counters: Counters,
+    window: Duration,
+    max_keys: usize,
+}
+
+impl RateLimiter {
+    pub async fn build(
+        backend: RateLimitBackend,
+        window: Duration,
+        redis_uri: &Secret<String>,
+    ) -> Result<RateLimiter, anyhow::Error> {
+        let counters = match backend {
+            RateLimitBackend::Memory => Counters::Memory(Mutex::new(HashMap::new())),
+            RateLimitBackend::Redis => {
+                let client = redis::Client::open(redis_uri.expose_secret().as_str())
+                    .context("Invalid Redis URI")?;
+                let connection = ConnectionManager::new(client)
+                    .await
+                    .context("Failed to connect to Redis")?;
+                Counters::Redis(connection)
+            }
+        };
+        Ok(Self {
+            counters,
+            window,
+            max_keys: MAX_TRACKED_KEYS,
+        })
+    }
+
+    /// Count a hit for `key`.
+    /// Returns `false` if it went over `limit` hits in the current window.
+    pub async fn hit(&self, key: &str, limit: u32) -> Result<bool, anyhow::Error> {
+        Ok(self.count(key).await? <= limit)
+    }
+
+    /// Count a hit for `key` and return the hits of the current window.
+    pub async fn count(&self, key: &str) -> Result<u32, anyhow::Error> {
+        let hits = match &self.counters {
+            Counters::Memory(windows) => {
+                let mut windows = windows.lock().unwrap();
+                let now = Instant::now();
+                if windows.len() >= self.max_keys && !windows.contains_key(key) {
+                    windows.retain(|_, w| now.duration_since(w.started_at) < self.window);
+                    while windows.len() >= self.max_keys {
+                        let oldest = windows
+                            .iter()
+                            .min_by_key(|(_, w)| w.started_at)
+                            .map(|(k, _)| k.clone())
+                            .expect("The map is not empty");
+                        windows.remove(&oldest);
+                    }
+                }
+                let window = windows.entry(key.to_string()).or_insert(Window {
+                    started_at: now,
+                    hits: 0,
+                });
+                if now.duration_since(window.started_at) >= self.window {
+                    *window = Window {
+                        started_at: now,
+                        hits: 0,
+                    };
+                }
+                window.hits += 1;
+                window.hits
+            }
+            Counters::Redis(connection) => {
+                let mut connection = connection.clone();
+                let key = format!("rate_limit:{key}");
+                // Both in one transaction, so a window never lives without an expiry.
+                let (hits,): (u32,) = redis::pipe()
+                    .atomic()
+                    .cmd("SET")
+                    .arg(&key)
+                    .arg(0)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(self.window.as_secs().max(1))
+                    .ignore()
+                    .cmd("INCR")
+                    .arg(&key)
+                    .query_async(&mut connection)
+                    .await
+                    .context("Failed to count a hit in Redis")?;
+                hits
+            }
+        };
+        Ok(hits)
+    }
+
+    /// Start a new window for `key`.
+    pub async fn reset(&self, key: &str) -> Result<(), anyhow::Error> {
+        match &self.counters {
+            Counters::Memory(windows) => {
+                windows.lock().unwrap().remove(key);
+            }
+            Counters::Redis(connection) => {
+                redis::cmd("DEL")
+                    .arg(format!("rate_limit:{key}"))
+                    .query_async::<_, ()>(&mut connection.clone())
+                    .await
+                    .context("Failed to reset a rate limit window")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use crate::configuration::RateLimitBackend;
+    use secrecy::Secret;
+    use std::time::Duration;
+
+    async fn memory_limiter(window: Duration) -> RateLimiter {
+        RateLimiter::build(
+            RateLimitBackend::Memory,
+            window,
+            &Secret::new(String::new()),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn hits_over_the_limit_are_refused_per_key() {
+        let limiter = memory_limiter(Duration::from_secs(60)).await;
+        assert!(limiter.hit("a", 2).await.unwrap());
+        assert!(limiter.hit("a", 2).await.unwrap());
+        assert!(!limiter.hit("a", 2).await.unwrap());
+        assert!(limiter.hit("b", 2).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn counters_can_be_reset() {
+        let limiter = memory_limiter(Duration::from_secs(60)).await;
+        assert_eq!(limiter.count("a").await.unwrap(), 1);
+        assert_eq!(limiter.count("a").await.unwrap(), 2);
+        limiter.reset("a").await.unwrap();
+        assert_eq!(limiter.count("a").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_oldest_keys_are_evicted_when_the_map_is_full() {
+        let mut limiter = memory_limiter(Duration::from_secs(60)).await;
+        limiter.max_keys = 2;
+        limiter.count("a").await.unwrap();
+        limiter.count("a").await.unwrap();
+        limiter.count("b").await.unwrap();
+        limiter.count("c").await.unwrap();
+
+        // `a`, the oldest, made room for `c`.
+        assert_eq!(limiter.count("a").await.unwrap(), 1);
+        assert_eq!(limiter.count("c").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn counters_are_reset_when_the_window_is_over() {
+        let limiter = memory_limiter(Duration::from_millis(10)).await;
+        assert!(limiter.hit("a", 1).await.unwrap());
+        assert!(!limiter.hit("a", 1).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(limiter.hit("a", 1).await.unwrap());
+    }
+}
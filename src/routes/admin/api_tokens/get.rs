@@ -0,0 +1,101 @@
+use crate::authentication::{list_api_tokens, ApiScope, Role, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// The API tokens of the user, and a form to create new ones with the
+/// scopes their role allows.
+pub async fn api_tokens_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let now = chrono::Utc::now();
+    let mut tokens_html = String::new();
+    for token in list_api_tokens(&pool, **user_id).await.map_err(e500)? {
+        let status_html = match (token.revoked_at, token.expires_at) {
+            (Some(_), _) => "Revoked".to_string(),
+            (None, Some(expires_at)) if expires_at <= now => "Expired".to_string(),
+            (None, _) => format!(
+                r#"Active <form action="/admin/api-tokens/revoke" method="post">
+                    <input type="hidden" name="api_token_id" value="{}">
+                    <button type="submit">Revoke</button>
+                </form>"#,
+                token.api_token_id
+            ),
+        };
+        writeln!(
+            tokens_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{status_html}</td></tr>",
+            encode_minimal(&token.name),
+            token.scopes.join(", "),
+            token.created_at.format("%Y-%m-%d %H:%M UTC"),
+            token
+                .expires_at
+                .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_else(|| "Never".into()),
+            token
+                .last_used_at
+                .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_else(|| "Never".into()),
+        )
+        .unwrap();
+    }
+
+    let mut scopes_html = String::new();
+    for scope in ApiScope::ALL.iter().filter(|s| role.can(s.permission())) {
+        writeln!(
+            scopes_html,
+            r#"<label><input type="checkbox" name="scope_{scope}" value="on"> {scope}</label>"#
+        )
+        .unwrap();
+    }
+    if scopes_html.is_empty() {
+        scopes_html = format!("<p>Your role ({}) does not allow any scope.</p>", *role);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>API tokens</title>
+    </head>
+    <body>
+        {msg_html}
+        <table>
+            <tr><th>Name</th><th>Scopes</th><th>Created at</th><th>Expires at</th><th>Last used at</th><th>Status</th></tr>
+            {tokens_html}
+        </table>
+        <form action="/admin/api-tokens" method="post">
+            <label>Name
+                <input type="text" placeholder="e.g. CI publisher" name="name" required>
+            </label>
+            {scopes_html}
+            <label>Expires
+                <select name="expires_in_days">
+                    <option value="30">In 30 days</option>
+                    <option value="90">In 90 days</option>
+                    <option value="365">In a year</option>
+                    <option value="">Never</option>
+                </select>
+            </label>
+            <button type="submit">Create token</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
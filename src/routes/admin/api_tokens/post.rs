@@ -0,0 +1,139 @@
+use crate::authentication::{create_api_token, revoke_api_token, ApiScope, Role, UserId};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Every checked scope is submitted as a `scope_<scope>` field.
+const SCOPE_FIELD_PREFIX: &str = "scope_";
+
+/// Tokens can be made to expire after at most this many days, or never.
+const MAX_EXPIRY_DAYS: i32 = 365;
+
+#[derive(Deserialize)]
+pub struct ApiTokenFormData {
+    name: String,
+    /// Empty for tokens that never expire.
+    #[serde(default)]
+    expires_in_days: String,
+    #[serde(flatten)]
+    scopes: HashMap<String, String>,
+}
+
+struct NewApiToken {
+    name: String,
+    scopes: Vec<ApiScope>,
+    expires_in_days: Option<i32>,
+}
+
+fn parse_api_token(form: ApiTokenFormData, role: Role) -> Result<NewApiToken, String> {
+    let name = form.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err("The name must be between 1 and 100 characters long.".into());
+    }
+    let expires_in_days = match form.expires_in_days.trim() {
+        "" => None,
+        days => match days.parse::<i32>() {
+            Ok(days) if (1..=MAX_EXPIRY_DAYS).contains(&days) => Some(days),
+            _ => {
+                return Err(format!(
+                    "Tokens must expire within {MAX_EXPIRY_DAYS} days, or never."
+                ))
+            }
+        },
+    };
+    let mut scopes = Vec::new();
+    for scope in ApiScope::ALL {
+        if form
+            .scopes
+            .contains_key(&format!("{SCOPE_FIELD_PREFIX}{scope}"))
+        {
+            if !role.can(scope.permission()) {
+                return Err(format!(
+                    "Your role ({role}) does not allow the {scope} scope."
+                ));
+            }
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err("Choose at least one scope.".into());
+    }
+    Ok(NewApiToken {
+        name,
+        scopes,
+        expires_in_days,
+    })
+}
+
+/// Create a token and show it. It is never shown again.
+#[tracing::instrument(name = "Add an API token", skip(form, pool), fields(user_id=%&*user_id))]
+pub async fn add_api_token(
+    form: web::Form<ApiTokenFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let new_token = match parse_api_token(form.0, *role) {
+        Ok(new_token) => new_token,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/api-tokens"));
+        }
+    };
+    let token = create_api_token(
+        &pool,
+        **user_id,
+        &new_token.name,
+        &new_token.scopes,
+        new_token.expires_in_days,
+    )
+    .await
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>New API token</title>
+    </head>
+    <body>
+        <p>The token {} has been created. Copy it now, it will not be shown again.</p>
+        <p><code id="api-token">{token}</code></p>
+        <p>Send it in the <code>Authorization: Bearer</code> header of requests to <code>/api/v1</code>.</p>
+        <p><a href="/admin/api-tokens">Back to your tokens</a></p>
+    </body>
+</html>"#,
+            encode_minimal(&new_token.name)
+        )))
+}
+
+#[derive(Deserialize)]
+pub struct RevokeFormData {
+    api_token_id: Uuid,
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(form, pool), fields(user_id=%&*user_id))]
+pub async fn revoke_token(
+    form: web::Form<RevokeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_api_token(&pool, **user_id, form.0.api_token_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The token has been revoked.").send();
+    } else {
+        FlashMessage::error("This token is no longer active.").send();
+    }
+    Ok(see_other("/admin/api-tokens"))
+}
@@ -1,17 +1,62 @@
-use crate::authentication::UserId;
+use crate::authentication::{Permission, Role, UserId};
 use crate::utils::e500;
 use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
 use sqlx::PgPool;
+use std::fmt::Write;
 use uuid::Uuid;
 
+/// The pages linked from the dashboard, with the permission they need.
+const ACTIONS: [(&str, &str, Permission); 7] = [
+    (
+        "/admin/newsletters",
+        "Send a newsletter issue",
+        Permission::PublishIssues,
+    ),
+    (
+        "/admin/lists",
+        "Manage mailing lists",
+        Permission::ViewStats,
+    ),
+    (
+        "/admin/sequences",
+        "Manage automation sequences",
+        Permission::ViewStats,
+    ),
+    (
+        "/admin/subscribers",
+        "Browse subscribers",
+        Permission::ManageSubscribers,
+    ),
+    (
+        "/admin/imports",
+        "Import subscribers",
+        Permission::ManageSubscribers,
+    ),
+    (
+        "/admin/email-domains",
+        "Manage blocked email domains",
+        Permission::ManageSettings,
+    ),
+    ("/admin/users", "Manage users", Permission::ManageUsers),
+];
+
 pub async fn admin_dashboard(
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    let role = role.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    // Only link to the pages the role can open.
+    let mut actions_html = String::new();
+    for (path, label, permission) in ACTIONS {
+        if role.can(permission) {
+            writeln!(actions_html, r#"<li><a href="{path}">{label}</a></li>"#).unwrap();
+        }
+    }
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -23,10 +68,14 @@ pub async fn admin_dashboard(
         <title>Admin dashboard</title>
     </head>
     <body>
-        <p>Welcome {username}!</p>
+        <p>Welcome {username}! You are signed in as {role}.</p>
         <p>Available actions:</p>
         <ol>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/two-factor">Two-factor authentication</a></li>
+        <li><a href="/admin/sessions">Active sessions</a></li>
+        <li><a href="/admin/api-tokens">API tokens</a></li>
+        {actions_html}
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
                 <input type="submit" value="Logout">
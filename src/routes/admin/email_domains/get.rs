@@ -0,0 +1,90 @@
+use crate::authentication::{Permission, Role};
+use crate::email_domains::EmailDomainPolicy;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+struct DomainRuleRow {
+    domain: String,
+    rule: String,
+}
+
+pub async fn email_domains_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSettings)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let rules = sqlx::query_as!(
+        DomainRuleRow,
+        r#"SELECT domain, rule FROM email_domain_rules ORDER BY rule, domain"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the email domain rules.")
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for rule in rules {
+        let domain = encode_minimal(&rule.domain);
+        writeln!(
+            rows_html,
+            r#"<tr><td>{domain}</td><td>{}</td><td>
+                <form action="/admin/email-domains/delete" method="post">
+                    <input type="hidden" name="domain" value="{domain}">
+                    <button type="submit">Remove</button>
+                </form>
+            </td></tr>"#,
+            encode_minimal(&rule.rule)
+        )
+        .unwrap();
+    }
+    let n_disposable = domain_policy.disposable_domain_count();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Email domains</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>{n_disposable} disposable domain(s) are rejected on top of the domains below.</p>
+        <table>
+            <tr><th>Domain</th><th>Rule</th><th></th></tr>
+            {rows_html}
+        </table>
+        <form action="/admin/email-domains" method="post">
+            <label>Domain
+                <input
+                        type="text"
+                        placeholder="e.g. example.com"
+                        name="domain"
+                        required
+                >
+            </label>
+            <label><input type="radio" name="rule" value="blocked" checked> Block</label>
+            <label><input type="radio" name="rule" value="allowed"> Allow</label>
+            <button type="submit">Save rule</button>
+        </form>
+        <form action="/admin/email-domains/reload" method="post">
+            <button type="submit">Reload the domain lists</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
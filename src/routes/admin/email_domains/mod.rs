@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::email_domains_form;
+pub use post::{add_email_domain_rule, delete_email_domain_rule, reload_email_domains};
@@ -0,0 +1,101 @@
+use crate::authentication::{Permission, Role};
+use crate::email_domains::{parse_domain, DomainRule, EmailDomainPolicy};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize)]
+pub struct RuleFormData {
+    domain: String,
+    rule: String,
+}
+
+/// Block or allow a domain, replacing any rule it already had.
+#[tracing::instrument(name = "Add an email domain rule", skip(form, pool, domain_policy))]
+pub async fn add_email_domain_rule(
+    form: web::Form<RuleFormData>,
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSettings)?;
+    let parsed = parse_domain(&form.domain)
+        .and_then(|domain| DomainRule::parse(&form.rule).map(|rule| (domain, rule)));
+    let (domain, rule) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/email-domains"));
+        }
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO email_domain_rules (domain, rule, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (domain) DO UPDATE SET rule = EXCLUDED.rule
+        "#,
+        domain,
+        rule.as_str()
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    domain_policy.reload(&pool).await.map_err(e500)?;
+
+    FlashMessage::info(format!("{domain} is now {}.", rule.as_str())).send();
+    Ok(see_other("/admin/email-domains"))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteFormData {
+    domain: String,
+}
+
+#[tracing::instrument(name = "Delete an email domain rule", skip(form, pool, domain_policy))]
+pub async fn delete_email_domain_rule(
+    form: web::Form<DeleteFormData>,
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSettings)?;
+    sqlx::query!(
+        r#"DELETE FROM email_domain_rules WHERE domain = $1"#,
+        form.domain
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    domain_policy.reload(&pool).await.map_err(e500)?;
+
+    FlashMessage::info(format!("The rule for {} has been removed.", form.domain)).send();
+    Ok(see_other("/admin/email-domains"))
+}
+
+/// Pick up changes to the disposable-domain file, or rules saved by
+/// another instance of the application.
+#[tracing::instrument(
+    name = "Reload email domain lists on request",
+    skip(pool, domain_policy)
+)]
+pub async fn reload_email_domains(
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSettings)?;
+    match domain_policy.reload(&pool).await {
+        Ok(n_disposable) => FlashMessage::info(format!(
+            "The domain lists have been reloaded: {n_disposable} disposable domain(s)."
+        ))
+        .send(),
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to reload the domain lists.");
+            FlashMessage::error(format!("Failed to reload the domain lists: {e}")).send()
+        }
+    }
+    Ok(see_other("/admin/email-domains"))
+}
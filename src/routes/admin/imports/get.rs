@@ -0,0 +1,129 @@
+use crate::authentication::{Permission, Role};
+use crate::domain::DEFAULT_LIST;
+use crate::subscriber_import::get_import_report;
+use crate::utils::e500;
+use actix_web::error::ErrorNotFound;
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn import_form(
+    flash_messages: IncomingFlashMessages,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // The file input comes last: the other fields have to be known
+    // before we start reading the CSV.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Import subscribers</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Upload a CSV file with a header row containing an <code>email</code> and a <code>name</code> column.
+            Comma-separated tags can go in a <code>tags</code> column, and custom attributes in <code>attr_&lt;key&gt;</code> columns.</p>
+        <form action="/admin/imports" method="post" enctype="multipart/form-data">
+            <label>Mailing list
+                <input
+                        type="text"
+                        placeholder="Enter list identifier"
+                        name="list"
+                        value="{DEFAULT_LIST}"
+                        required
+                >
+            </label>
+            <fieldset>
+                <legend>Imported subscribers</legend>
+                <label><input type="radio" name="mode" value="send_confirmation" checked> Send them a confirmation email</label>
+                <label><input type="radio" name="mode" value="confirmed"> Are already confirmed</label>
+            </fieldset>
+            <label>CSV file
+                <input type="file" name="csv" accept=".csv,text/csv" required>
+            </label>
+            <button type="submit">Import</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+pub async fn import_report(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let import_id = import_id.into_inner();
+    let report = get_import_report(&pool, import_id)
+        .await
+        .context("Failed to retrieve the import report.")
+        .map_err(e500)?
+        .ok_or_else(|| ErrorNotFound(format!("There is no import with id {import_id}.")))?;
+
+    let mut rejected_html = String::new();
+    if !report.rejected.is_empty() {
+        writeln!(
+            rejected_html,
+            r#"<p><a href="/admin/imports/{import_id}/rejected.csv">Download the rejected rows</a></p>"#
+        )
+        .unwrap();
+    }
+    let imported = report.imported;
+    let rejected = report.rejected.len();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Import report</title>
+    </head>
+    <body>
+        <p>{imported} subscriber(s) imported, {rejected} row(s) rejected.</p>
+        {rejected_html}
+        <p><a href="/admin/imports">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+pub async fn import_rejected_rows(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let import_id = import_id.into_inner();
+    let report = get_import_report(&pool, import_id)
+        .await
+        .context("Failed to retrieve the import report.")
+        .map_err(e500)?
+        .ok_or_else(|| ErrorNotFound(format!("There is no import with id {import_id}.")))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "rejected-{import_id}.csv"
+            ))],
+        })
+        .body(report.rejected_rows_csv()))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{import_form, import_rejected_rows, import_report};
+pub use post::import_subscribers_upload;
@@ -0,0 +1,90 @@
+use crate::authentication::{Permission, Role};
+use crate::domain::{EmailNormalization, ListSlug};
+use crate::routes::get_list_id_by_slug;
+use crate::subscriber_import::{import_subscribers, save_import_report, ImportError, ImportMode};
+use crate::utils::{e400, e500, see_other};
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::io::AsyncWriteExt;
+
+/// How much of the CSV we buffer between the upload and the import.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+#[tracing::instrument(
+    name = "Import subscribers from a CSV upload",
+    skip(payload, pool, normalization)
+)]
+pub async fn import_subscribers_upload(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    normalization: web::Data<EmailNormalization>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let mut list = None;
+    let mut mode = None;
+    while let Some(mut field) = payload.try_next().await.map_err(e400)? {
+        match field.name() {
+            "list" => list = Some(read_text_field(&mut field).await?),
+            "mode" => mode = Some(read_text_field(&mut field).await?),
+            "csv" => {
+                // The CSV is imported as it is read, so the other fields must
+                // come first: falling back to defaults could mail a whole
+                // audience that was meant to be imported as confirmed.
+                let (Some(list), Some(mode)) = (list.take(), mode.take()) else {
+                    return Err(e400(
+                        "The list and mode fields must be sent before the CSV file.",
+                    ));
+                };
+                let list = ListSlug::parse(list).map_err(e400)?;
+                let mode = ImportMode::parse(&mode).map_err(e400)?;
+                let list_id = get_list_id_by_slug(&pool, &list)
+                    .await
+                    .context("Failed to look up the mailing list.")
+                    .map_err(e500)?
+                    .ok_or_else(|| e400(format!("{list} is not a known mailing list.")))?;
+
+                // The upload is not `Send`, so we pipe it to the importer
+                // and drive both halves from this task.
+                let (mut writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+                let upload = async move {
+                    while let Some(chunk) = field.try_next().await.map_err(e400)? {
+                        writer.write_all(&chunk).await.map_err(e500)?;
+                    }
+                    writer.shutdown().await.map_err(e500)
+                };
+                let import = import_subscribers(&pool, &normalization, list_id, mode, reader);
+                let (upload, import) = tokio::join!(upload, import);
+                let report = match import {
+                    Ok(report) => report,
+                    Err(ImportError::ValidationError(e)) => {
+                        FlashMessage::error(e).send();
+                        return Ok(see_other("/admin/imports"));
+                    }
+                    Err(e) => return Err(e500(e)),
+                };
+                upload?;
+
+                let import_id = save_import_report(&pool, &report)
+                    .await
+                    .context("Failed to save the import report.")
+                    .map_err(e500)?;
+                return Ok(see_other(&format!("/admin/imports/{import_id}")));
+            }
+            _ => {}
+        }
+    }
+    Err(e400("No CSV file was uploaded."))
+}
+
+async fn read_text_field(field: &mut Field) -> Result<String, actix_web::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(e400)? {
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8(bytes).map_err(e400)
+}
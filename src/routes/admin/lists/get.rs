@@ -0,0 +1,156 @@
+use crate::authentication::{Permission, Role};
+use crate::domain::ListSlug;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn lists_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ViewStats)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for list in get_lists_overview(&pool).await.map_err(e500)? {
+        let slug = htmlescape::encode_minimal(&list.slug);
+        writeln!(
+            rows_html,
+            r#"<tr><td>{slug}</td><td>{}</td><td>{}</td><td>
+                <form action="/admin/lists/redirects" method="post">
+                    <input type="hidden" name="slug" value="{slug}">
+                    <label>After subscribing
+                        <input type="url" name="subscribed_redirect_url" value="{}">
+                    </label>
+                    <label>After confirming
+                        <input type="url" name="confirmed_redirect_url" value="{}">
+                    </label>
+                    <button type="submit">Save</button>
+                </form>
+            </td></tr>"#,
+            htmlescape::encode_minimal(&list.name),
+            list.confirmed_subscribers,
+            htmlescape::encode_minimal(list.subscribed_redirect_url.as_deref().unwrap_or_default()),
+            htmlescape::encode_minimal(list.confirmed_redirect_url.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Mailing lists</title>
+    </head>
+    <body>
+        {msg_html}
+        <table>
+            <tr><th>Identifier</th><th>Name</th><th>Confirmed subscribers</th><th>Redirects</th></tr>
+            {rows_html}
+        </table>
+        <form action="/admin/lists" method="post">
+            <label>Identifier
+                <input
+                        type="text"
+                        placeholder="e.g. rust-weekly"
+                        name="slug"
+                        required
+                >
+            </label>
+            <label>Name
+                <input
+                        type="text"
+                        placeholder="Enter list name"
+                        name="name"
+                        required
+                >
+            </label>
+            <button type="submit">Create list</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+struct ListOverview {
+    slug: String,
+    name: String,
+    confirmed_subscribers: i64,
+    subscribed_redirect_url: Option<String>,
+    confirmed_redirect_url: Option<String>,
+}
+
+#[tracing::instrument(name = "Get mailing lists overview", skip(pool))]
+async fn get_lists_overview(pool: &PgPool) -> Result<Vec<ListOverview>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListOverview,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COUNT(m.subscriber_id) FILTER (WHERE m.status = 'confirmed') as "confirmed_subscribers!",
+            l.subscribed_redirect_url,
+            l.confirmed_redirect_url
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        GROUP BY l.list_id
+        ORDER BY l.created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve mailing lists.")?;
+    Ok(lists)
+}
+
+#[tracing::instrument(name = "Get list id by slug", skip(pool))]
+pub async fn get_list_id_by_slug(
+    pool: &PgPool,
+    slug: &ListSlug,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE slug = $1"#,
+        slug.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(record.map(|r| r.list_id))
+}
+
+/// Resolve every slug to its list id.
+/// Returns the first slug that does not match any list as an error.
+#[tracing::instrument(name = "Get list ids by slugs", skip(pool))]
+pub async fn get_list_ids_by_slugs(
+    pool: &PgPool,
+    slugs: &[ListSlug],
+) -> Result<Result<Vec<Uuid>, ListSlug>, sqlx::Error> {
+    let raw_slugs: Vec<String> = slugs.iter().map(|s| s.as_ref().to_owned()).collect();
+    let records = sqlx::query!(
+        r#"SELECT list_id, slug FROM lists WHERE slug = ANY($1)"#,
+        &raw_slugs[..]
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut list_ids = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        match records.iter().find(|r| r.slug == slug.as_ref()) {
+            Some(r) => list_ids.push(r.list_id),
+            None => return Ok(Err(slug.clone())),
+        }
+    }
+    Ok(Ok(list_ids))
+}
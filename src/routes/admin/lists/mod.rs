@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{get_list_id_by_slug, get_list_ids_by_slugs, lists_form};
+pub use post::{create_list, update_list_redirects};
@@ -0,0 +1,122 @@
+use crate::authentication::{Permission, Role};
+use crate::domain::{ListSlug, RedirectUrl};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageLists)?;
+    let FormData { slug, name } = form.0;
+    let slug = match ListSlug::parse(slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list name cannot be empty.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    let n_inserted_rows = insert_list(&pool, &slug, name).await.map_err(e500)?;
+    if n_inserted_rows == 0 {
+        FlashMessage::error(format!("A list identified by {slug} already exists.")).send();
+    } else {
+        FlashMessage::info(format!("The {slug} list has been created.")).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+#[tracing::instrument(name = "Insert mailing list", skip(pool))]
+async fn insert_list(pool: &PgPool, slug: &ListSlug, name: &str) -> Result<u64, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows)
+}
+
+#[derive(Deserialize)]
+pub struct RedirectsFormData {
+    slug: String,
+    subscribed_redirect_url: String,
+    confirmed_redirect_url: String,
+}
+
+/// Leaving a URL blank brings back our own hosted page.
+#[tracing::instrument(name = "Update the redirects of a mailing list", skip(form, pool))]
+pub async fn update_list_redirects(
+    form: web::Form<RedirectsFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageLists)?;
+    let RedirectsFormData {
+        slug,
+        subscribed_redirect_url,
+        confirmed_redirect_url,
+    } = form.0;
+    let parsed = ListSlug::parse(slug).and_then(|slug| {
+        Ok((
+            slug,
+            RedirectUrl::parse_optional(subscribed_redirect_url)?,
+            RedirectUrl::parse_optional(confirmed_redirect_url)?,
+        ))
+    });
+    let (slug, subscribed_redirect_url, confirmed_redirect_url) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE lists
+        SET subscribed_redirect_url = $2, confirmed_redirect_url = $3
+        WHERE slug = $1
+        "#,
+        slug.as_ref(),
+        subscribed_redirect_url.as_ref().map(AsRef::as_ref),
+        confirmed_redirect_url.as_ref().map(AsRef::as_ref)
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        FlashMessage::error(format!("{slug} is not a known mailing list.")).send();
+    } else {
+        FlashMessage::info(format!(
+            "The redirects of the {slug} list have been updated."
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -0,0 +1,105 @@
+use crate::authentication::{Permission, Role};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Lockouts are listed newest first, up to this many.
+const MAX_LISTED_LOCKOUTS: i64 = 100;
+
+/// The log of logins locked out after too many failures.
+pub async fn lockouts_log(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let mut lockouts_html = String::new();
+    for lockout in get_lockouts(&pool).await.map_err(e500)? {
+        let status_html = match (lockout.unlocked_by, lockout.active) {
+            (Some(unlocked_by), _) => format!("Lifted by {}", encode_minimal(&unlocked_by)),
+            (None, _) if lockout.unlocked_at.is_some() => "Lifted".to_string(),
+            (None, true) => format!(
+                r#"Active <form action="/admin/lockouts/unlock" method="post">
+                    <input type="hidden" name="lockout_id" value="{}">
+                    <button type="submit">Unlock</button>
+                </form>"#,
+                lockout.lockout_id
+            ),
+            (None, false) => "Expired".to_string(),
+        };
+        writeln!(
+            lockouts_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{status_html}</td></tr>",
+            encode_minimal(lockout.username.as_deref().unwrap_or_default()),
+            encode_minimal(lockout.ip_address.as_deref().unwrap_or_default()),
+            lockout.failed_attempts,
+            lockout.locked_at.format("%Y-%m-%d %H:%M UTC"),
+            lockout.locked_until.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Login lockouts</title>
+    </head>
+    <body>
+        {msg_html}
+        <table>
+            <tr><th>Username</th><th>IP address</th><th>Failed attempts</th><th>Locked at</th><th>Locked until</th><th>Status</th></tr>
+            {lockouts_html}
+        </table>
+        <p><a href="/admin/users">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+struct Lockout {
+    lockout_id: Uuid,
+    username: Option<String>,
+    ip_address: Option<String>,
+    failed_attempts: i32,
+    locked_at: chrono::DateTime<chrono::Utc>,
+    locked_until: chrono::DateTime<chrono::Utc>,
+    unlocked_at: Option<chrono::DateTime<chrono::Utc>>,
+    unlocked_by: Option<String>,
+    active: bool,
+}
+
+#[tracing::instrument(name = "Get login lockouts", skip(pool))]
+async fn get_lockouts(pool: &PgPool) -> Result<Vec<Lockout>, anyhow::Error> {
+    sqlx::query_as!(
+        Lockout,
+        r#"
+        SELECT
+            l.lockout_id, l.username, l.ip_address, l.failed_attempts, l.locked_at,
+            l.locked_until, l.unlocked_at, u.username AS "unlocked_by?",
+            (l.unlocked_at IS NULL AND l.locked_until > now()) AS "active!"
+        FROM login_lockouts l
+        LEFT JOIN users u ON u.user_id = l.unlocked_by
+        ORDER BY l.locked_at DESC
+        LIMIT $1
+        "#,
+        MAX_LISTED_LOCKOUTS
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the login lockouts.")
+}
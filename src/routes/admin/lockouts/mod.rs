@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::lockouts_log;
+pub use post::unlock_login;
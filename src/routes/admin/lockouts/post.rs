@@ -0,0 +1,33 @@
+use crate::authentication::{LoginThrottle, Permission, Role, UserId};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct UnlockFormData {
+    lockout_id: Uuid,
+}
+
+#[tracing::instrument(name = "Unlock logins", skip(form, pool, throttle), fields(user_id=%&*user_id))]
+pub async fn unlock_login(
+    form: web::Form<UnlockFormData>,
+    pool: web::Data<PgPool>,
+    throttle: web::Data<LoginThrottle>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    if throttle
+        .unlock(&pool, form.0.lockout_id, **user_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The lockout has been lifted.").send();
+    } else {
+        FlashMessage::error("This lockout is no longer active.").send();
+    }
+    Ok(see_other("/admin/lockouts"))
+}
@@ -1,9 +1,19 @@
+use crate::authentication::{revoke_session, SessionId, UserId};
 use crate::session_state::TypedSession;
-use crate::utils::see_other;
-use actix_web::HttpResponse;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn logout(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn logout(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    revoke_session(&pool, **user_id, **session_id)
+        .await
+        .map_err(e500)?;
     session.log_out();
     FlashMessage::info("You have successfully logged out.").send();
     Ok(see_other("/login"))
@@ -1,9 +1,29 @@
+mod api_tokens;
 mod dashboard;
+mod email_domains;
+mod imports;
+mod lists;
+mod lockouts;
 mod logout;
 mod newsletter;
 mod password;
+mod sequences;
+mod sessions;
+mod subscribers;
+mod two_factor;
+mod users;
 
+pub use api_tokens::*;
 pub use dashboard::*;
+pub use email_domains::*;
+pub use imports::*;
+pub use lists::*;
+pub use lockouts::*;
 pub use logout::*;
 pub use newsletter::*;
 pub use password::*;
+pub use sequences::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
@@ -1,14 +1,18 @@
+use crate::authentication::{Permission, Role};
 use actix_web::http::header::ContentType;
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 
 use crate::authentication::UserId;
+use crate::domain::{DeliveryFrequency, ListSlug, Locale, Segment};
+use crate::routes::get_list_ids_by_slugs;
 use crate::utils::{e400, e500, error_chain_fmt, see_other};
 use actix_web::http::header::HeaderValue;
 use actix_web::http::{header, StatusCode};
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 // use base64::Engine;
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use std::collections::HashMap;
 use std::fmt::Formatter;
 
 #[derive(thiserror::Error)]
@@ -50,6 +54,62 @@ pub struct FormData {
     html_content: String,
     text_content: String,
     idempotency_key: String,
+    /// Comma-separated identifiers of the mailing lists to deliver to,
+    /// the default list if omitted.
+    lists: Option<String>,
+    /// Optional filter over the subscribers of those lists, see [`Segment`].
+    segment: Option<String>,
+    /// Set by the "Preview recipients" button: count who would receive
+    /// the issue instead of publishing it.
+    dry_run: Option<String>,
+    /// Optional translations, e.g. `title_fr`, `html_content_fr` and
+    /// `text_content_fr` for French subscribers.
+    #[serde(flatten)]
+    variants: HashMap<String, String>,
+}
+
+/// The fields of a translated issue.
+const VARIANT_FIELDS: [&str; 3] = ["title", "html_content", "text_content"];
+
+impl FormData {
+    /// A submitted field of the variant in `locale`, `None` if left empty.
+    fn variant_field(&self, field: &str, locale: Locale) -> Option<&str> {
+        self.variants
+            .get(&format!("{field}_{locale}"))
+            .map(String::as_str)
+            .filter(|value| !value.trim().is_empty())
+    }
+}
+
+/// An issue translated for the subscribers of a non-default locale.
+pub struct IssueVariant<'a> {
+    pub locale: Locale,
+    pub title: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+/// Translations left entirely empty are skipped, partial ones are rejected.
+fn parse_variants(form: &FormData) -> Result<Vec<IssueVariant<'_>>, String> {
+    let mut variants = Vec::new();
+    for locale in Locale::all().filter(|l| !l.is_default()) {
+        let fields = VARIANT_FIELDS.map(|field| form.variant_field(field, locale));
+        match fields {
+            [None, None, None] => {}
+            [Some(title), Some(html_content), Some(text_content)] => variants.push(IssueVariant {
+                locale,
+                title,
+                html_content,
+                text_content,
+            }),
+            _ => {
+                return Err(format!(
+                    "The {locale} variant needs a title, an HTML and a text content."
+                ))
+            }
+        }
+    }
+    Ok(variants)
 }
 
 #[tracing::instrument(
@@ -59,19 +119,45 @@ fields(user_id=%&*user_id)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::PublishIssues)?;
     let user_id = user_id.into_inner();
-    let FormData {
-        title,
-        html_content,
-        text_content,
-        idempotency_key,
-    } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let form = form.into_inner();
+    let idempotency_key: IdempotencyKey = form.idempotency_key.clone().try_into().map_err(e400)?;
+    let lists = match &form.lists {
+        Some(lists) => ListSlug::parse_many(lists).map_err(e400)?,
+        None => vec![ListSlug::default()],
+    };
+    let segment = match form.segment.as_deref().map(str::trim) {
+        Some(segment) if !segment.is_empty() => Some(Segment::parse(segment).map_err(e400)?),
+        _ => None,
+    };
+    let variants = parse_variants(&form).map_err(e400)?;
+    let list_ids = get_list_ids_by_slugs(&pool, &lists)
+        .await
+        .context("Failed to look up the mailing lists")
+        .map_err(e500)?
+        .map_err(|slug| e400(format!("{slug} is not a known mailing list.")))?;
 
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+    if form.dry_run.is_some() {
+        let n_recipients = count_recipients(&pool, &list_ids, segment.as_ref())
+            .await
+            .context("Failed to count the recipients of the newsletter issue")
+            .map_err(e500)?;
+        let message = format!(
+            "<p><i>This issue would currently be delivered to {n_recipients} subscriber(s).</i></p>"
+        );
+        return newsletter_form(&pool, &message, Some(&form)).await;
+    }
+
+    // The key comes from the form we rendered, scoping it to this endpoint
+    // is enough.
+    let fingerprint = request_fingerprint(&request, &[]);
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id, &fingerprint)
         .await
         .map_err(e500)?
     {
@@ -80,14 +166,28 @@ pub async fn publish_newsletter(
             success_message().send();
             return Ok(saved_response);
         }
+        NextAction::RejectReusedKey => {
+            return Err(actix_web::error::ErrorUnprocessableEntity(
+                "This form was already used for another request.",
+            ))
+        }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &form.title,
+        &form.text_content,
+        &form.html_content,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    insert_issue_variants(&mut transaction, issue_id, &variants)
         .await
-        .context("Failed to store newsletter issue details")
+        .context("Failed to store the translations of the newsletter issue")
         .map_err(e500)?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    publish_issue(&mut transaction, issue_id, &list_ids, segment.as_ref())
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
@@ -107,18 +207,74 @@ fn success_message() -> FlashMessage {
     )
 }
 
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::idempotency::{
+    request_fingerprint, save_response, try_processing, IdempotencyKey, NextAction,
+};
 use std::fmt::Write;
 use uuid::Uuid;
 
 pub async fn newsletters(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::PublishIssues)?;
     let mut messages = String::new();
     for m in flash_messages.iter() {
         writeln!(messages, "<p><i>{}</i></p>", m.content()).unwrap();
     }
-    let idempotency_key = uuid::Uuid::new_v4();
+    newsletter_form(&pool, &messages, None).await
+}
+
+/// Render the publish form, pre-filled with `previous` if there is one.
+async fn newsletter_form(
+    pool: &PgPool,
+    messages: &str,
+    previous: Option<&FormData>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let available_lists = get_list_slugs(pool)
+        .await
+        .context("Failed to retrieve mailing lists")
+        .map_err(e500)?
+        .join(", ");
+    let default_list = ListSlug::default().to_string();
+    let escape = |value: Option<&str>| htmlescape::encode_minimal(value.unwrap_or_default());
+    let title = escape(previous.map(|f| f.title.as_str()));
+    let html_content = escape(previous.map(|f| f.html_content.as_str()));
+    let text_content = escape(previous.map(|f| f.text_content.as_str()));
+    let lists = escape(Some(
+        previous
+            .and_then(|f| f.lists.as_deref())
+            .unwrap_or(&default_list),
+    ));
+    let segment = escape(previous.and_then(|f| f.segment.as_deref()));
+    let mut variant_fields = String::new();
+    for locale in Locale::all().filter(|l| !l.is_default()) {
+        let [title, html_content, text_content] = VARIANT_FIELDS
+            .map(|field| escape(previous.and_then(|f| f.variant_field(field, locale))));
+        write!(
+            variant_fields,
+            r#"<fieldset>
+                <legend>{locale} variant (optional)</legend>
+                <label>Title
+                    <input type="text" name="title_{locale}" value="{title}">
+                </label>
+                <label>HTML content
+                    <textarea name="html_content_{locale}">{html_content}</textarea>
+                </label>
+                <label>Text content
+                    <textarea name="text_content_{locale}">{text_content}</textarea>
+                </label>
+            </fieldset>
+            "#
+        )
+        .unwrap();
+    }
+    // Previewing must not burn the idempotency key of the issue being written.
+    let idempotency_key = previous
+        .map(|f| f.idempotency_key.clone())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let idempotency_key = escape(Some(&idempotency_key));
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -136,28 +292,48 @@ pub async fn newsletters(
                         type="text"
                         placeholder="Enter newsletter title"
                         name="title"
+                        value="{title}"
                         required
                 >
             </label>
             <label>HTML content
                 <textarea
-                        // type="text"
                         placeholder="Enter newsletter content"
                         name="html_content"
                         required
-                >
+                >{html_content}</textarea>
             </label>
             <label>Text content
                 <textarea
-                        // type="text"
                         placeholder="Enter newsletter content"
                         name="text_content"
                         required
+                >{text_content}</textarea>
+            </label>
+            <p>Subscribers whose language has no variant receive the issue above.</p>
+            {variant_fields}<label>Mailing lists
+                <input
+                        type="text"
+                        placeholder="Comma-separated list identifiers"
+                        name="lists"
+                        value="{lists}"
+                        required
+                >
+            </label>
+            <p>Available lists: {available_lists}</p>
+            <label>Segment
+                <input
+                        type="text"
+                        placeholder="e.g. tag:rust AND NOT tag:beta"
+                        name="segment"
+                        value="{segment}"
                 >
             </label>
             <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-            <button type="submit">Login</button>
+            <button type="submit" name="dry_run" value="true">Preview recipients</button>
+            <button type="submit">Publish</button>
         </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
     </body>
 </html>
             "#
@@ -165,7 +341,16 @@ pub async fn newsletters(
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+async fn get_list_slugs(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let records = sqlx::query!(r#"SELECT slug FROM lists ORDER BY created_at"#)
+        .fetch_all(pool)
+        .await?;
+    Ok(records.into_iter().map(|r| r.slug).collect())
+}
+
+/// Store a new issue as a draft.
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
@@ -179,7 +364,7 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            created_at
         )
         VALUES ($1, $2, $3, $4, now())
         "#,
@@ -194,23 +379,136 @@ async fn insert_newsletter_issue(
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn insert_issue_variants(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    variants: &[IssueVariant<'_>],
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
+    for variant in variants {
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_variants (
+                newsletter_issue_id,
+                locale,
+                title,
+                text_content,
+                html_content
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
             newsletter_issue_id,
-            subscriber_email
+            variant.locale.as_ref(),
+            variant.title,
+            variant.text_content,
+            variant.html_content
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Append the `FROM ... WHERE ...` clause selecting the confirmed members
+/// of the given lists that match `segment` and have not paused their mail.
+/// Subscribers are aliased as `s`.
+fn push_recipients_filter(
+    query: &mut QueryBuilder<'_, Postgres>,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) {
+    query
+        .push(
+            r#"
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE m.status = 'confirmed'
+            AND (s.paused_until IS NULL OR s.paused_until <= now())
+            AND m.list_id = ANY("#,
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        .push_bind(list_ids.to_vec())
+        .push(")");
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        segment.push_sql(query);
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn count_recipients(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT COUNT(DISTINCT s.id)");
+    push_recipients_filter(&mut query, list_ids, segment);
+    let (n_recipients,): (i64,) = query.build_query_as().fetch_one(pool).await?;
+    Ok(n_recipients)
+}
+
+/// Queue the deliveries of a draft and record how many subscribers it is
+/// going out to.
+#[tracing::instrument(skip_all)]
+pub async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) -> Result<(), sqlx::Error> {
+    let recipients =
+        enqueue_delivery_tasks(transaction, newsletter_issue_id, list_ids, segment).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = now(), recipients = $2
+        WHERE newsletter_issue_id = $1
         "#,
-        newsletter_issue_id
+        newsletter_issue_id,
+        i32::try_from(recipients).unwrap_or(i32::MAX)
     )
     .execute(transaction)
     .await?;
     Ok(())
 }
+
+/// Queue one delivery per confirmed member of the given lists matching the
+/// segment. Subscribers on several of the lists only receive the issue once.
+/// Subscribers who asked for a digest get the issue queued for their next one.
+/// Returns the number of subscribers the issue was queued for.
+#[tracing::instrument(skip_all)]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) -> Result<u64, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT DISTINCT "#,
+    );
+    query.push_bind(newsletter_issue_id).push("::uuid, s.email");
+    push_recipients_filter(&mut query, list_ids, segment);
+    query
+        .push(" AND s.delivery_frequency = ")
+        .push_bind(DeliveryFrequency::EveryIssue.as_str());
+    let queued = query.build().execute(&mut *transaction).await?;
+
+    let mut query = QueryBuilder::new(
+        r#"
+        INSERT INTO digest_queue (
+            newsletter_issue_id,
+            subscriber_id
+        )
+        SELECT DISTINCT "#,
+    );
+    query.push_bind(newsletter_issue_id).push("::uuid, s.id");
+    push_recipients_filter(&mut query, list_ids, segment);
+    query
+        .push(" AND s.delivery_frequency = ")
+        .push_bind(DeliveryFrequency::Digest.as_str());
+    let digested = query.build().execute(transaction).await?;
+    Ok(queued.rows_affected() + digested.rows_affected())
+}
@@ -1,4 +1,7 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials, UserId};
+use crate::authentication::{
+    end_other_sessions, validate_credentials, AuthError, Credentials, SessionId, UserId,
+};
+use crate::configuration::AuthenticationSettings;
 use crate::domain::{ChangePasswordParam, Password};
 use crate::routes::get_username;
 use crate::utils::{e500, see_other};
@@ -51,8 +54,7 @@ impl ResponseError for ChangePasswordError {
     fn error_response(&self) -> HttpResponse<BoxBody> {
         match self {
             ChangePasswordError::ValidationError(err) => {
-                HttpResponse::build(StatusCode::BAD_REQUEST)
-                    .json(&serde_json::json!({"error": err}))
+                HttpResponse::build(StatusCode::BAD_REQUEST).json(serde_json::json!({"error": err}))
             }
             ChangePasswordError::UnexpectedError(_) => {
                 HttpResponse::build(StatusCode::BAD_REQUEST).finish()
@@ -61,11 +63,16 @@ impl ResponseError for ChangePasswordError {
     }
 }
 
-#[tracing::instrument(name = "Change password", skip(form, user_id, pool))]
+#[tracing::instrument(
+    name = "Change password",
+    skip(form, user_id, session_id, pool, settings)
+)]
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    settings: web::Data<AuthenticationSettings>,
     user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
 
@@ -90,7 +97,7 @@ pub async fn change_password(
         password: Secret::new(change_password_param.current_password.as_ref().to_owned()),
     };
 
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &pool, &settings.password_hashing).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -102,10 +109,15 @@ pub async fn change_password(
     crate::authentication::change_password(
         *user_id,
         Secret::new(change_password_param.new_password.as_ref().to_string()),
-        &pool,
+        &settings.password_hashing,
+        pool.get_ref(),
     )
     .await
     .map_err(e500)?;
+    // Whoever knew the old password is logged out everywhere but here.
+    end_other_sessions(pool.get_ref(), *user_id, **session_id)
+        .await
+        .map_err(e500)?;
     FlashMessage::info("Your password has been changed.").send();
     Ok(see_other("/admin/dashboard"))
 }
@@ -0,0 +1,160 @@
+use crate::authentication::{Permission, Role};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct SequenceOverview {
+    sequence_id: Uuid,
+    name: String,
+    slug: String,
+    active: i64,
+    completed: i64,
+    stopped: i64,
+}
+
+struct StepOverview {
+    sequence_id: Uuid,
+    position: i32,
+    delay_days: i32,
+    title: String,
+}
+
+pub async fn sequences_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ViewStats)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let sequences = get_sequences_overview(&pool).await.map_err(e500)?;
+    let steps = get_steps_overview(&pool).await.map_err(e500)?;
+
+    let mut sequences_html = String::new();
+    for sequence in sequences {
+        let mut steps_html = String::new();
+        for step in steps
+            .iter()
+            .filter(|s| s.sequence_id == sequence.sequence_id)
+        {
+            writeln!(
+                steps_html,
+                "<tr><td>{}</td><td>Day {}</td><td>{}</td></tr>",
+                step.position,
+                step.delay_days,
+                encode_minimal(&step.title)
+            )
+            .unwrap();
+        }
+        writeln!(
+            sequences_html,
+            r#"<h2>{name} ({slug})</h2>
+        <p>{active} active, {completed} completed, {stopped} stopped</p>
+        <table>
+            <tr><th>Step</th><th>Sent on</th><th>Title</th></tr>
+            {steps_html}
+        </table>
+        <form action="/admin/sequences/steps" method="post">
+            <input type="hidden" name="sequence_id" value="{sequence_id}">
+            <label>Days after confirmation
+                <input type="number" name="delay_days" min="0" value="0" required>
+            </label>
+            <label>Title
+                <input type="text" name="title" required>
+            </label>
+            <label>Plain text content
+                <textarea name="text_content" rows="10" cols="50" required></textarea>
+            </label>
+            <label>HTML content
+                <textarea name="html_content" rows="10" cols="50" required></textarea>
+            </label>
+            <button type="submit">Add step</button>
+        </form>"#,
+            name = encode_minimal(&sequence.name),
+            slug = encode_minimal(&sequence.slug),
+            active = sequence.active,
+            completed = sequence.completed,
+            stopped = sequence.stopped,
+            sequence_id = sequence.sequence_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Automation sequences</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Sequences start when a subscriber confirms their subscription to the list,
+            and stop if they leave it.</p>
+        {sequences_html}
+        <h2>New sequence</h2>
+        <form action="/admin/sequences" method="post">
+            <label>Name
+                <input type="text" placeholder="e.g. Welcome" name="name" required>
+            </label>
+            <label>List
+                <input type="text" name="list" value="newsletter" required>
+            </label>
+            <button type="submit">Create sequence</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get automation sequences overview", skip(pool))]
+async fn get_sequences_overview(pool: &PgPool) -> Result<Vec<SequenceOverview>, anyhow::Error> {
+    let sequences = sqlx::query_as!(
+        SequenceOverview,
+        r#"
+        SELECT
+            s.sequence_id,
+            s.name,
+            l.slug,
+            COUNT(p.subscriber_id) FILTER (WHERE p.status = 'active') AS "active!",
+            COUNT(p.subscriber_id) FILTER (WHERE p.status = 'completed') AS "completed!",
+            COUNT(p.subscriber_id) FILTER (WHERE p.status = 'stopped') AS "stopped!"
+        FROM automation_sequences s
+        JOIN lists l ON l.list_id = s.list_id
+        LEFT JOIN automation_progress p ON p.sequence_id = s.sequence_id
+        GROUP BY s.sequence_id, l.slug
+        ORDER BY s.created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the automation sequences.")?;
+    Ok(sequences)
+}
+
+#[tracing::instrument(name = "Get automation steps overview", skip(pool))]
+async fn get_steps_overview(pool: &PgPool) -> Result<Vec<StepOverview>, anyhow::Error> {
+    let steps = sqlx::query_as!(
+        StepOverview,
+        r#"
+        SELECT sequence_id, position, delay_days, title
+        FROM automation_steps
+        ORDER BY sequence_id, position
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the automation steps.")?;
+    Ok(steps)
+}
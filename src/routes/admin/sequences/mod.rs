@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::sequences_form;
+pub use post::{add_sequence_step, create_sequence};
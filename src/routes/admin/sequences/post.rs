@@ -0,0 +1,169 @@
+use crate::authentication::{Permission, Role};
+use crate::automation::parse_delay_days;
+use crate::domain::ListSlug;
+use crate::routes::get_list_id_by_slug;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde_derive::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct SequenceFormData {
+    name: String,
+    list: String,
+}
+
+#[tracing::instrument(name = "Create an automation sequence", skip(form, pool))]
+pub async fn create_sequence(
+    form: web::Form<SequenceFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageLists)?;
+    let SequenceFormData { name, list } = form.0;
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The sequence name cannot be empty.").send();
+        return Ok(see_other("/admin/sequences"));
+    }
+    let list = match ListSlug::parse(list) {
+        Ok(list) => list,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/sequences"));
+        }
+    };
+    let Some(list_id) = get_list_id_by_slug(&pool, &list).await.map_err(e500)? else {
+        FlashMessage::error(format!("{list} is not a known mailing list.")).send();
+        return Ok(see_other("/admin/sequences"));
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO automation_sequences (sequence_id, list_id, name, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        Uuid::new_v4(),
+        list_id,
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    FlashMessage::info(format!("The {name} sequence has been created.")).send();
+    Ok(see_other("/admin/sequences"))
+}
+
+#[derive(Deserialize)]
+pub struct StepFormData {
+    sequence_id: Uuid,
+    delay_days: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Append a step to a sequence.
+/// Subscribers who are still going through it will receive it too.
+#[tracing::instrument(name = "Add an automation step", skip(form, pool))]
+pub async fn add_sequence_step(
+    form: web::Form<StepFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageLists)?;
+    let StepFormData {
+        sequence_id,
+        delay_days,
+        title,
+        text_content,
+        html_content,
+    } = form.0;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(sequence) = get_sequence_tail(&mut transaction, sequence_id)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("This sequence does not exist.").send();
+        return Ok(see_other("/admin/sequences"));
+    };
+    let delay_days = match parse_delay_days(&delay_days, sequence.last_delay_days) {
+        Ok(delay_days) => delay_days,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/sequences"));
+        }
+    };
+    if [&title, &text_content, &html_content]
+        .iter()
+        .any(|field| field.trim().is_empty())
+    {
+        FlashMessage::error("The title and the contents of a step cannot be empty.").send();
+        return Ok(see_other("/admin/sequences"));
+    }
+
+    let position = sequence.last_position.unwrap_or(0) + 1;
+    sqlx::query!(
+        r#"
+        INSERT INTO automation_steps (
+            sequence_id, position, delay_days, title, text_content, html_content
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        sequence_id,
+        position,
+        delay_days,
+        title.trim(),
+        text_content,
+        html_content
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(format!(
+        "Step {position} has been added to the {} sequence.",
+        sequence.name
+    ))
+    .send();
+    Ok(see_other("/admin/sequences"))
+}
+
+struct SequenceTail {
+    name: String,
+    last_position: Option<i32>,
+    last_delay_days: Option<i32>,
+}
+
+/// The sequence along with its last step, if any.
+/// The sequence stays locked until the transaction ends, so that steps
+/// added concurrently cannot take the same position.
+#[tracing::instrument(name = "Get the last step of a sequence", skip(transaction))]
+async fn get_sequence_tail(
+    transaction: &mut Transaction<'_, Postgres>,
+    sequence_id: Uuid,
+) -> Result<Option<SequenceTail>, anyhow::Error> {
+    sqlx::query_as!(
+        SequenceTail,
+        r#"
+        SELECT s.name, last.position AS "last_position?", last.delay_days AS "last_delay_days?"
+        FROM automation_sequences s
+        LEFT JOIN LATERAL (
+            SELECT position, delay_days
+            FROM automation_steps
+            WHERE sequence_id = s.sequence_id
+            ORDER BY position DESC
+            LIMIT 1
+        ) last ON true
+        WHERE s.sequence_id = $1
+        FOR UPDATE OF s
+        "#,
+        sequence_id
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to retrieve the automation sequence.")
+}
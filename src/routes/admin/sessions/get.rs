@@ -0,0 +1,73 @@
+use crate::authentication::{active_sessions, SessionId, UserId};
+use crate::configuration::SessionSettings;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// The sessions the user is logged in to, each of which can be revoked.
+pub async fn sessions_list(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let mut sessions_html = String::new();
+    for s in active_sessions(&pool, **user_id, &settings)
+        .await
+        .map_err(e500)?
+    {
+        let action_html = if s.session_id == **session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/revoke" method="post">
+                    <input type="hidden" name="session_id" value="{}">
+                    <button type="submit">Revoke</button>
+                </form>"#,
+                s.session_id
+            )
+        };
+        writeln!(
+            sessions_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{action_html}</td></tr>",
+            s.created_at.format("%Y-%m-%d %H:%M UTC"),
+            s.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            encode_minimal(s.ip_address.as_deref().unwrap_or_default()),
+            encode_minimal(s.user_agent.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Active sessions</title>
+    </head>
+    <body>
+        {msg_html}
+        <table>
+            <tr><th>Logged in at</th><th>Last activity</th><th>IP address</th><th>Browser</th><th></th></tr>
+            {sessions_html}
+        </table>
+        <form action="/admin/sessions/revoke-all" method="post">
+            <button type="submit">Log out everywhere</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
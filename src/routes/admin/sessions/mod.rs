@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::sessions_list;
+pub use post::{log_out_everywhere, revoke_user_session};
@@ -0,0 +1,44 @@
+use crate::authentication::{end_all_sessions, revoke_session, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct RevokeFormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(name = "Revoke a session", skip(form, pool), fields(user_id=%&*user_id))]
+pub async fn revoke_user_session(
+    form: web::Form<RevokeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if revoke_session(&pool, **user_id, form.0.session_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("This session is no longer active.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Log out everywhere", skip(session, pool), fields(user_id=%&*user_id))]
+pub async fn log_out_everywhere(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    end_all_sessions(pool.get_ref(), **user_id)
+        .await
+        .map_err(e500)?;
+    session.log_out();
+    FlashMessage::info("You have been logged out of all your sessions.").send();
+    Ok(see_other("/login"))
+}
@@ -0,0 +1,247 @@
+use crate::authentication::{Permission, Role};
+use crate::utils::{csv_quote, e400};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+/// How many rows may be waiting to be written to the response.
+const EXPORT_BUFFER_SIZE: usize = 64;
+
+const SUBSCRIBER_STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    fn parse(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::JsonLines),
+            other => Err(format!("{other} is not a supported export format.")),
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::JsonLines => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportParams {
+    format: Option<String>,
+    status: Option<String>,
+}
+
+struct ExportRow {
+    id: uuid::Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: chrono::DateTime<chrono::Utc>,
+    confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    tags: Vec<String>,
+    consent_source: Option<String>,
+    consent_text_version: Option<String>,
+    consent_signup_ip: Option<String>,
+    consent_signup_user_agent: Option<String>,
+    consent_given_at: Option<chrono::DateTime<chrono::Utc>>,
+    consent_confirmation_ip: Option<String>,
+    consent_confirmation_user_agent: Option<String>,
+    consent_confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ExportRow {
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            self.id,
+            csv_quote(&self.email),
+            csv_quote(&self.name),
+            self.status,
+            self.subscribed_at.to_rfc3339(),
+            self.confirmed_at
+                .map(|c| c.to_rfc3339())
+                .unwrap_or_default(),
+            csv_quote(&self.tags.join(",")),
+            csv_quote(self.consent_source.as_deref().unwrap_or_default()),
+            csv_quote(self.consent_text_version.as_deref().unwrap_or_default()),
+            csv_quote(self.consent_signup_ip.as_deref().unwrap_or_default()),
+            csv_quote(
+                self.consent_signup_user_agent
+                    .as_deref()
+                    .unwrap_or_default()
+            ),
+            self.consent_given_at
+                .map(|c| c.to_rfc3339())
+                .unwrap_or_default(),
+            csv_quote(self.consent_confirmation_ip.as_deref().unwrap_or_default()),
+            csv_quote(
+                self.consent_confirmation_user_agent
+                    .as_deref()
+                    .unwrap_or_default()
+            ),
+            self.consent_confirmed_at
+                .map(|c| c.to_rfc3339())
+                .unwrap_or_default()
+        )
+    }
+
+    fn to_json_line(&self) -> String {
+        let mut line = serde_json::json!({
+            "id": self.id,
+            "email": self.email,
+            "name": self.name,
+            "status": self.status,
+            "subscribed_at": self.subscribed_at.to_rfc3339(),
+            "confirmed_at": self.confirmed_at.map(|c| c.to_rfc3339()),
+            "tags": self.tags,
+            "consent": self.consent_given_at.map(|given_at| serde_json::json!({
+                "source": self.consent_source,
+                "consent_text_version": self.consent_text_version,
+                "given_at": given_at.to_rfc3339(),
+                "confirmed_at": self.consent_confirmed_at.map(|c| c.to_rfc3339()),
+                "signup": {
+                    "ip": self.consent_signup_ip,
+                    "user_agent": self.consent_signup_user_agent,
+                    "at": given_at.to_rfc3339(),
+                },
+                "confirmation": self.consent_confirmed_at.map(|confirmed_at| serde_json::json!({
+                    "ip": self.consent_confirmation_ip,
+                    "user_agent": self.consent_confirmation_user_agent,
+                    "at": confirmed_at.to_rfc3339(),
+                })),
+            })),
+        })
+        .to_string();
+        line.push('\n');
+        line
+    }
+}
+
+/// Stream every subscriber (optionally only the ones with the given status)
+/// as CSV or JSON Lines, reading them from a database cursor as the client
+/// consumes the response.
+///
+/// Each subscriber comes with their latest consent record, with the IP
+/// address and user agent of the signup and of the confirmation kept apart.
+#[tracing::instrument(name = "Export subscribers", skip(params, pool))]
+pub async fn export_subscribers(
+    params: web::Query<ExportParams>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let params = params.into_inner();
+    let format = ExportFormat::parse(params.format.as_deref().unwrap_or("csv")).map_err(e400)?;
+    if let Some(status) = &params.status {
+        if !SUBSCRIBER_STATUSES.contains(&status.as_str()) {
+            return Err(e400(format!("{status} is not a valid subscriber status.")));
+        }
+    }
+
+    // The cursor borrows the pool, so it is drained by its own task and the
+    // rows are handed over through a bounded channel: we never fetch much
+    // further ahead than the client reads.
+    let (sender, receiver) = mpsc::channel::<Result<Bytes, sqlx::Error>>(EXPORT_BUFFER_SIZE);
+    let pool = pool.into_inner();
+    tokio::spawn(async move {
+        if let ExportFormat::Csv = format {
+            let header = "id,email,name,status,subscribed_at,confirmed_at,tags,\
+                consent_source,consent_text_version,\
+                consent_signup_ip,consent_signup_user_agent,consent_given_at,\
+                consent_confirmation_ip,consent_confirmation_user_agent,consent_confirmed_at\n";
+            if sender.send(Ok(Bytes::from(header))).await.is_err() {
+                return;
+            }
+        }
+        let mut rows = sqlx::query_as!(
+            ExportRow,
+            r#"
+            SELECT
+                s.id,
+                s.email,
+                s.name,
+                s.status,
+                s.subscribed_at,
+                (
+                    SELECT min(m.confirmed_at)
+                    FROM list_memberships m
+                    WHERE m.subscriber_id = s.id
+                ) AS confirmed_at,
+                s.tags,
+                c.source AS "consent_source?",
+                c.consent_text_version AS "consent_text_version?",
+                c.signup_ip AS "consent_signup_ip?",
+                c.signup_user_agent AS "consent_signup_user_agent?",
+                c.signed_up_at AS "consent_given_at?",
+                c.confirmation_ip AS "consent_confirmation_ip?",
+                c.confirmation_user_agent AS "consent_confirmation_user_agent?",
+                c.confirmed_at AS "consent_confirmed_at?"
+            FROM subscriptions s
+            LEFT JOIN LATERAL (
+                SELECT *
+                FROM consent_records r
+                WHERE r.subscriber_id = s.id
+                ORDER BY r.signed_up_at DESC
+                LIMIT 1
+            ) c ON true
+            WHERE $1::text IS NULL OR s.status = $1
+            ORDER BY s.subscribed_at, s.id
+            "#,
+            params.status
+        )
+        .fetch(pool.as_ref());
+        loop {
+            let chunk = match rows.try_next().await {
+                Ok(Some(row)) => Ok(Bytes::from(match format {
+                    ExportFormat::Csv => row.to_csv(),
+                    ExportFormat::JsonLines => row.to_json_line(),
+                })),
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to read the subscribers to export.",
+                    );
+                    Err(e)
+                }
+            };
+            let failed = chunk.is_err();
+            // The client went away, no point in reading any further.
+            if sender.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers.{}",
+                format.extension()
+            ))],
+        })
+        .streaming(body))
+}
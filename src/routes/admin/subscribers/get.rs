@@ -0,0 +1,361 @@
+use crate::authentication::{AccessDenied, Permission, Role};
+use crate::domain::SubscriberAttributes;
+use crate::personal_data::personal_data_archive;
+use crate::routes::archive_response;
+use crate::utils::{e500, error_chain_fmt};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::{Formatter, Write};
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct Pagination {
+    page: Option<i64>,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    tags: Vec<String>,
+}
+
+pub async fn subscribers(
+    query: web::Query<Pagination>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let rows = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, tags
+        FROM subscriptions
+        ORDER BY subscribed_at DESC, id
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for row in rows.iter().take(PAGE_SIZE as usize) {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            row.id,
+            encode_minimal(&row.email),
+            encode_minimal(&row.name),
+            encode_minimal(&row.status),
+            encode_minimal(&row.tags.join(", "))
+        )
+        .unwrap();
+    }
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?page={}">&lt;- Previous</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if rows.len() as i64 > PAGE_SIZE {
+        write!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?page={}">Next -&gt;</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscribers</title>
+    </head>
+    <body>
+        {msg_html}
+        <table>
+            <tr><th>Email</th><th>Name</th><th>Status</th><th>Tags</th></tr>
+            {rows_html}
+        </table>
+        <p>{pagination_html}</p>
+        <p>
+            Export:
+            <a href="/admin/subscribers/export?format=csv">CSV</a>
+            <a href="/admin/subscribers/export?format=jsonl">JSON Lines</a>
+        </p>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberDetailsError {
+    #[error("There is no subscriber with id {0}.")]
+    NotFound(Uuid),
+
+    #[error(transparent)]
+    AccessDenied(#[from] AccessDenied),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberDetailsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberDetailsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberDetailsError::NotFound(_) => StatusCode::NOT_FOUND,
+            SubscriberDetailsError::AccessDenied(e) => e.status_code(),
+            SubscriberDetailsError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscriberDetailsError::AccessDenied(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+        }
+    }
+}
+
+struct SubscriberDetails {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: chrono::DateTime<chrono::Utc>,
+    tags: Vec<String>,
+    attributes: serde_json::Value,
+}
+
+struct MembershipRow {
+    slug: String,
+    status: String,
+}
+
+struct ConsentRow {
+    slug: String,
+    source: String,
+    consent_text_version: String,
+    signup_ip: Option<String>,
+    signup_user_agent: Option<String>,
+    signed_up_at: chrono::DateTime<chrono::Utc>,
+    confirmation_ip: Option<String>,
+    confirmation_user_agent: Option<String>,
+    confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, SubscriberDetailsError> {
+    role.require(Permission::ManageSubscribers)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT email, name, status, subscribed_at, tags, attributes
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(SubscriberDetailsError::NotFound(subscriber_id))?;
+    let memberships = sqlx::query_as!(
+        MembershipRow,
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the list memberships of the subscriber.")?;
+    let consents = sqlx::query_as!(
+        ConsentRow,
+        r#"
+        SELECT
+            l.slug, c.source, c.consent_text_version, c.signup_ip, c.signup_user_agent,
+            c.signed_up_at, c.confirmation_ip, c.confirmation_user_agent, c.confirmed_at
+        FROM consent_records c
+        JOIN lists l ON l.list_id = c.list_id
+        WHERE c.subscriber_id = $1
+        ORDER BY c.signed_up_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the consent records of the subscriber.")?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut memberships_html = String::new();
+    for m in memberships {
+        writeln!(
+            memberships_html,
+            "<li>{} ({})</li>",
+            encode_minimal(&m.slug),
+            encode_minimal(&m.status)
+        )
+        .unwrap();
+    }
+    let mut consents_html = String::new();
+    for c in consents {
+        let optional = |value: Option<String>| encode_minimal(&value.unwrap_or_default());
+        writeln!(
+            consents_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+            <td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&c.slug),
+            encode_minimal(&c.source),
+            encode_minimal(&c.consent_text_version),
+            c.signed_up_at.to_rfc3339(),
+            optional(c.signup_ip),
+            optional(c.signup_user_agent),
+            c.confirmed_at.map(|c| c.to_rfc3339()).unwrap_or_default(),
+            optional(c.confirmation_ip),
+            optional(c.confirmation_user_agent)
+        )
+        .unwrap();
+    }
+    let email = encode_minimal(&subscriber.email);
+    let name = encode_minimal(&subscriber.name);
+    let status = encode_minimal(&subscriber.status);
+    let subscribed_at = subscriber.subscribed_at.to_rfc3339();
+    let tags = encode_minimal(&subscriber.tags.join(", "));
+    let attributes = SubscriberAttributes::try_from(subscriber.attributes)
+        .map(|a| a.to_lines())
+        .unwrap_or_default();
+    let attributes = encode_minimal(&attributes);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscriber details</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Email: {email}</p>
+        <p>Name: {name}</p>
+        <p>Status: {status}</p>
+        <p>Subscribed at: {subscribed_at}</p>
+        <p>Mailing lists:</p>
+        <ul>
+            {memberships_html}
+        </ul>
+        <p>Consent records:</p>
+        <table>
+            <tr>
+                <th>List</th><th>Source</th><th>Consent text</th>
+                <th>Signed up at</th><th>Signup IP</th><th>Signup user agent</th>
+                <th>Confirmed at</th><th>Confirmation IP</th><th>Confirmation user agent</th>
+            </tr>
+            {consents_html}
+        </table>
+        <form action="/admin/subscribers/{subscriber_id}" method="post">
+            <label>Tags
+                <input
+                        type="text"
+                        placeholder="Comma-separated tags"
+                        name="tags"
+                        value="{tags}"
+                >
+            </label>
+            <label>Attributes
+                <textarea
+                        placeholder="One key=value pair per line"
+                        name="attributes"
+                >{attributes}</textarea>
+            </label>
+            <button type="submit">Save</button>
+        </form>
+        <p><a href="/admin/subscribers/{subscriber_id}/archive">Download personal data archive</a></p>
+        <form action="/admin/subscribers/{subscriber_id}/erase" method="post">
+            <button type="submit">Erase personal data</button>
+        </form>
+        <p><a href="/admin/subscribers">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+/// Everything we hold about a subscriber, for subject access requests
+/// received outside of the self-service flow.
+pub async fn subscriber_archive(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, SubscriberDetailsError> {
+    role.require(Permission::ManageSubscribers)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let email = get_subscriber_email(&pool, subscriber_id).await?;
+    let archive = personal_data_archive(&pool, &email)
+        .await?
+        .ok_or(SubscriberDetailsError::NotFound(subscriber_id))?;
+    Ok(archive_response(&archive))
+}
+
+#[tracing::instrument(name = "Get subscriber email", skip(pool))]
+pub async fn get_subscriber_email(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<String, SubscriberDetailsError> {
+    let record = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    record
+        .map(|r| r.email)
+        .ok_or(SubscriberDetailsError::NotFound(subscriber_id))
+}
@@ -0,0 +1,7 @@
+mod export;
+mod get;
+mod post;
+
+pub use export::export_subscribers;
+pub use get::{subscriber_archive, subscriber_details, subscribers};
+pub use post::{erase_subscriber, update_subscriber};
@@ -0,0 +1,71 @@
+use super::get::{get_subscriber_email, SubscriberDetailsError};
+use crate::authentication::{Permission, Role};
+use crate::domain::{SubscriberAttributes, SubscriberTag};
+use crate::personal_data::erase_personal_data;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct FormData {
+    tags: String,
+    attributes: String,
+}
+
+/// Replace the tags and attributes of a subscriber with the submitted ones.
+#[tracing::instrument(name = "Update subscriber tags and attributes", skip(form, pool))]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let location = format!("/admin/subscribers/{subscriber_id}");
+    let parsed = SubscriberTag::parse_many(&form.tags).and_then(|tags| {
+        SubscriberAttributes::parse_lines(&form.attributes).map(|attributes| (tags, attributes))
+    });
+    let (tags, attributes) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET tags = $2, attributes = $3
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        &tags[..],
+        attributes.into_json()
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been updated.").send();
+    Ok(see_other(&location))
+}
+
+/// Delete everything we hold about a subscriber and suppress their address.
+#[tracing::instrument(name = "Erase subscriber personal data", skip(pool))]
+pub async fn erase_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, SubscriberDetailsError> {
+    role.require(Permission::ManageSubscribers)?;
+    let email = get_subscriber_email(&pool, subscriber_id.into_inner()).await?;
+    erase_personal_data(&pool, &email).await?;
+    FlashMessage::info("The personal data of the subscriber has been erased.").send();
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,100 @@
+use crate::authentication::{
+    generate_totp_secret, get_totp_secret, provisioning_qr_code, unused_recovery_codes, UserId,
+};
+use crate::configuration::AuthenticationSettings;
+use crate::routes::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// Set up an authenticator app, or turn two-factor authentication off.
+pub async fn two_factor_settings(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    settings: web::Data<AuthenticationSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let body_html = if get_totp_secret(&pool, *user_id)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        let recovery_codes = unused_recovery_codes(&pool, *user_id).await.map_err(e500)?;
+        let disable_html = if settings.require_two_factor {
+            "<p>It is required for all users and cannot be turned off.</p>".to_string()
+        } else {
+            r#"<form action="/admin/two-factor/disable" method="post">
+            <label>Authentication code
+                <input type="text" name="code" autocomplete="one-time-code" required>
+            </label>
+            <button type="submit">Turn off two-factor authentication</button>
+        </form>"#
+                .to_string()
+        };
+        format!(
+            r#"<p>Two-factor authentication is on. You have {recovery_codes} unused recovery codes.</p>
+        {disable_html}"#
+        )
+    } else {
+        // The same secret is shown until the user confirms it with a code.
+        let secret = match session.get_totp_enrollment_secret().map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = generate_totp_secret();
+                session
+                    .insert_totp_enrollment_secret(&secret)
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let qr_code = provisioning_qr_code(&secret, &username).map_err(e500)?;
+        let required_html = if settings.require_two_factor {
+            "<p>Two-factor authentication is required for all users, set it up to continue.</p>"
+        } else {
+            ""
+        };
+        format!(
+            r#"{required_html}
+        <p>Scan this QR code with your authenticator app:</p>
+        {qr_code}
+        <p>Or enter this key by hand: <code id="totp-secret">{}</code></p>
+        <form action="/admin/two-factor" method="post">
+            <label>Code from the app
+                <input type="text" name="code" autocomplete="one-time-code" required>
+            </label>
+            <button type="submit">Turn on two-factor authentication</button>
+        </form>"#,
+            secret.expose_secret()
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Two-factor authentication</title>
+    </head>
+    <body>
+        {msg_html}
+        {body_html}
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::two_factor_settings;
+pub use post::{disable_two_factor, enable_two_factor};
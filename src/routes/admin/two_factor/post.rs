@@ -0,0 +1,109 @@
+use crate::authentication::{
+    remove_totp_secret, store_totp_secret, totp_step, unix_time, verify_second_factor, UserId,
+};
+use crate::configuration::AuthenticationSettings;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(Deserialize)]
+pub struct CodeFormData {
+    code: Secret<String>,
+}
+
+/// Turn on two-factor authentication once the user proved their app is
+/// set up, and show their recovery codes. They are never shown again.
+#[tracing::instrument(
+    name = "Turn on two-factor authentication",
+    skip(form, pool, session, settings),
+    fields(user_id=%&*user_id)
+)]
+pub async fn enable_two_factor(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    settings: web::Data<AuthenticationSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(secret) = session.get_totp_enrollment_secret().map_err(e500)? else {
+        FlashMessage::error("Your setup has expired, please scan the new QR code.").send();
+        return Ok(see_other("/admin/two-factor"));
+    };
+    let Some(step) = totp_step(&secret, form.0.code.expose_secret(), unix_time()).map_err(e500)?
+    else {
+        FlashMessage::error("The code is invalid, please try again.").send();
+        return Ok(see_other("/admin/two-factor"));
+    };
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let recovery_codes = store_totp_secret(
+        &mut transaction,
+        **user_id,
+        &secret,
+        step,
+        &settings.password_hashing,
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    session.remove_totp_enrollment_secret();
+
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{code}</code></li>").unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Recovery codes</title>
+    </head>
+    <body>
+        <p>Two-factor authentication is on.</p>
+        <p>Keep these recovery codes somewhere safe. Each of them lets you log in once
+        if you lose access to your authenticator app. They will not be shown again.</p>
+        <ul>
+        {codes_html}
+        </ul>
+        <p><a href="/admin/dashboard">Continue to the dashboard</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Turn off two-factor authentication",
+    skip(form, pool, settings),
+    fields(user_id=%&*user_id)
+)]
+pub async fn disable_two_factor(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<AuthenticationSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if settings.require_two_factor {
+        FlashMessage::error("Two-factor authentication is required for all users.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    // A stolen session is not enough to turn it off.
+    if !verify_second_factor(&pool, **user_id, form.0.code)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The code is invalid, please try again.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    remove_totp_secret(&pool, **user_id).await.map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been turned off.").send();
+    Ok(see_other("/admin/two-factor"))
+}
@@ -0,0 +1,176 @@
+use crate::authentication::{Permission, Role};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn users_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    // Messages may echo back an email address typed in by an admin.
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let mut users_html = String::new();
+    for user in get_users(&pool).await.map_err(e500)? {
+        let (action, label) = match user.status.as_str() {
+            "active" => ("disable", "Disable"),
+            _ => ("enable", "Enable"),
+        };
+        let role_options = role_options(&user.role);
+        writeln!(
+            users_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>
+                <form action="/admin/users/role" method="post">
+                    <input type="hidden" name="user_id" value="{}">
+                    <select name="role">{role_options}</select>
+                    <button type="submit">Change role</button>
+                </form>
+                <form action="/admin/users/{action}" method="post">
+                    <input type="hidden" name="user_id" value="{}">
+                    <button type="submit">{label}</button>
+                </form>
+                <form action="/admin/users/delete" method="post">
+                    <input type="hidden" name="user_id" value="{}">
+                    <button type="submit">Delete</button>
+                </form>
+            </td></tr>"#,
+            encode_minimal(&user.username),
+            encode_minimal(user.email.as_deref().unwrap_or_default()),
+            user.role,
+            user.status,
+            user.created_at.format("%Y-%m-%d"),
+            user.user_id,
+            user.user_id,
+            user.user_id,
+        )
+        .unwrap();
+    }
+
+    let mut invitations_html = String::new();
+    for invitation in get_pending_invitations(&pool).await.map_err(e500)? {
+        writeln!(
+            invitations_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&invitation.email),
+            invitation.role,
+            encode_minimal(invitation.invited_by.as_deref().unwrap_or_default()),
+            invitation.expires_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+
+    let invitation_role_options = role_options(Role::Editor.as_str());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Users</title>
+    </head>
+    <body>
+        {msg_html}
+        <table>
+            <tr><th>Username</th><th>Email</th><th>Role</th><th>Status</th><th>Created</th><th>Actions</th></tr>
+            {users_html}
+        </table>
+        <h2>Pending invitations</h2>
+        <table>
+            <tr><th>Email</th><th>Role</th><th>Invited by</th><th>Expires</th></tr>
+            {invitations_html}
+        </table>
+        <form action="/admin/users/invitations" method="post">
+            <label>Email
+                <input
+                        type="email"
+                        placeholder="Enter the email address of your colleague"
+                        name="email"
+                        required
+                >
+            </label>
+            <label>Role
+                <select name="role">{invitation_role_options}</select>
+            </label>
+            <button type="submit">Send invitation</button>
+        </form>
+        <p><a href="/admin/lockouts">Login lockouts</a></p>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+</html>"#,
+        )))
+}
+
+/// The `<option>`s of a role picker, with `selected` preselected.
+fn role_options(selected: &str) -> String {
+    let mut html = String::new();
+    for role in Role::ALL {
+        let selected = if role.as_str() == selected {
+            " selected"
+        } else {
+            ""
+        };
+        write!(html, r#"<option value="{role}"{selected}>{role}</option>"#).unwrap();
+    }
+    html
+}
+
+struct UserOverview {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    status: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserOverview>, anyhow::Error> {
+    sqlx::query_as!(
+        UserOverview,
+        r#"
+        SELECT user_id, username, email, role, status, created_at
+        FROM users
+        ORDER BY created_at, username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the users.")
+}
+
+struct PendingInvitation {
+    email: String,
+    role: String,
+    invited_by: Option<String>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<PendingInvitation>, anyhow::Error> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT i.email, i.role, u.username AS "invited_by?", i.expires_at
+        FROM user_invitations i
+        LEFT JOIN users u ON u.user_id = i.invited_by
+        WHERE i.accepted_at IS NULL AND i.expires_at > now()
+        ORDER BY i.created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending invitations.")
+}
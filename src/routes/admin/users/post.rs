@@ -0,0 +1,313 @@
+use crate::authentication::{invitation_tag, Permission, Role, UserId, INVITATION_VALIDITY_DAYS};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde_derive::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct InvitationFormData {
+    email: String,
+    /// The role of the new user, editor if omitted.
+    role: Option<String>,
+}
+
+/// Mail a colleague a link to create their own admin account.
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, pool, email_client, base_url, hmac_secret),
+    fields(user_id=%&*user_id)
+)]
+pub async fn invite_user(
+    form: web::Form<InvitationFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let InvitationFormData { email, role } = form.0;
+    let parsed = SubscriberEmail::parse(email).and_then(|email| {
+        let role = role.as_deref().map_or(Ok(Role::Editor), Role::parse)?;
+        Ok((email, role))
+    });
+    let (email, invited_role) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let existing_user = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up the users.")
+    .map_err(e500)?;
+    if existing_user.is_some() {
+        FlashMessage::error(format!("{email} already has an account.")).send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let invitation_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (
+            invitation_id, email, role, invited_by, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, now(), now() + make_interval(days => $5))
+        "#,
+        invitation_id,
+        email.as_ref(),
+        invited_role.as_str(),
+        **user_id,
+        INVITATION_VALIDITY_DAYS as i32
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the invitation.")
+    .map_err(e500)?;
+    let tag = invitation_tag(&hmac_secret.0, invitation_id);
+    send_invitation_email(&email_client, &email, &base_url.0, invitation_id, &tag)
+        .await
+        .context("Failed to send the invitation email.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("An invitation has been sent to {email}.")).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(
+    name = "Send an invitation email",
+    skip(email_client, email, base_url, tag)
+)]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    invitation_id: Uuid,
+    tag: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!(
+        "{}/invitations/accept?invitation_id={}&tag={}",
+        base_url, invitation_id, tag
+    );
+    let plain_body = format!(
+        "You have been invited to help run our newsletter.\n\
+        Visit {} to create your account, the link is valid for {} days.",
+        link, INVITATION_VALIDITY_DAYS
+    );
+    let html_body = format!(
+        "You have been invited to help run our newsletter.<br />\
+        Click <a href=\"{}\">here</a> to create your account, the link is valid for {} days.",
+        link, INVITATION_VALIDITY_DAYS
+    );
+    email_client
+        .send_email(
+            email,
+            "You are invited to run our newsletter",
+            &html_body,
+            &plain_body,
+        )
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct UserFormData {
+    user_id: Uuid,
+}
+
+/// Block a user from logging in, without losing track of their account.
+#[tracing::instrument(name = "Disable a user", skip(form, pool))]
+pub async fn disable_user(
+    form: web::Form<UserFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let username = match lock_user_for_removal(&mut transaction, form.0.user_id)
+        .await
+        .map_err(e500)?
+    {
+        Ok(username) => username,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    sqlx::query!(
+        r#"UPDATE users SET status = 'disabled' WHERE user_id = $1"#,
+        form.0.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(format!("{username} has been disabled.")).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Enable a user", skip(form, pool))]
+pub async fn enable_user(
+    form: web::Form<UserFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let user = sqlx::query!(
+        r#"UPDATE users SET status = 'active' WHERE user_id = $1 RETURNING username"#,
+        form.0.user_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    match user {
+        Some(user) => FlashMessage::info(format!("{} has been enabled.", user.username)).send(),
+        None => FlashMessage::error("This user does not exist.").send(),
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Delete a user", skip(form, pool))]
+pub async fn delete_user(
+    form: web::Form<UserFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let user_id = form.0.user_id;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let username = match lock_user_for_removal(&mut transaction, user_id)
+        .await
+        .map_err(e500)?
+    {
+        Ok(username) => username,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    // Saved responses are only replayed to the user who sent the request.
+    sqlx::query!(r#"DELETE FROM idempotency WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .map_err(e500)?;
+    sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(format!("{username} has been deleted.")).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(Deserialize)]
+pub struct RoleFormData {
+    user_id: Uuid,
+    role: String,
+}
+
+#[tracing::instrument(name = "Change the role of a user", skip(form, pool))]
+pub async fn change_user_role(
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let new_role = match Role::parse(&form.0.role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let user_id = form.0.user_id;
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Demoting an owner is a removal as far as the other owners are concerned.
+    let username = match lock_user_for_removal(&mut transaction, user_id)
+        .await
+        .map_err(e500)?
+    {
+        Ok(username) => username,
+        Err(e) if new_role != Role::Owner => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+        Err(_) => match get_username(&mut transaction, user_id)
+            .await
+            .map_err(e500)?
+        {
+            Some(username) => username,
+            None => {
+                FlashMessage::error("This user does not exist.").send();
+                return Ok(see_other("/admin/users"));
+            }
+        },
+    };
+    sqlx::query!(
+        r#"UPDATE users SET role = $2 WHERE user_id = $1"#,
+        user_id,
+        new_role.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(format!("{username} is now {new_role}.")).send();
+    Ok(see_other("/admin/users"))
+}
+
+async fn get_username(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let user = sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id)
+        .fetch_optional(transaction)
+        .await?;
+    Ok(user.map(|u| u.username))
+}
+
+/// Lock the active owners and return the username of `user_id`, or a
+/// message for the admin if it does not exist or is the last active owner.
+/// The lock stops two owners from removing each other at the same time.
+async fn lock_user_for_removal(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Result<String, String>, sqlx::Error> {
+    let active_owners = sqlx::query!(
+        r#"
+        SELECT user_id FROM users
+        WHERE status = 'active' AND role = 'owner'
+        ORDER BY user_id
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let user = sqlx::query!(
+        r#"SELECT username FROM users WHERE user_id = $1 FOR UPDATE"#,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(user) = user else {
+        return Ok(Err("This user does not exist.".into()));
+    };
+    if active_owners.len() == 1 && active_owners[0].user_id == user_id {
+        return Ok(Err(format!(
+            "{} is the last active owner and cannot be removed.",
+            user.username
+        )));
+    }
+    Ok(Ok(user.username))
+}
@@ -0,0 +1,514 @@
+use super::{ApiRequestError, Pagination};
+use crate::authentication::{ApiClient, ApiScope};
+use crate::domain::{ListSlug, Locale, Segment};
+use crate::idempotency::{
+    request_fingerprint, save_response, try_processing, IdempotencyKey, NextAction,
+};
+use crate::routes::{
+    get_list_ids_by_slugs, insert_issue_variants, insert_newsletter_issue, publish_issue,
+    IssueVariant,
+};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Deserialize, Serialize)]
+pub struct IssueContent {
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+
+/// A draft, with its optional translations keyed by locale, e.g. `"fr"`.
+#[derive(Deserialize, Serialize)]
+pub struct NewIssue {
+    #[serde(flatten)]
+    content: IssueContent,
+    #[serde(default)]
+    variants: HashMap<String, IssueContent>,
+}
+
+/// Fields left out are not changed. `variants`, if given, replaces all of them.
+#[derive(Deserialize)]
+pub struct IssueUpdate {
+    title: Option<String>,
+    html_content: Option<String>,
+    text_content: Option<String>,
+    variants: Option<HashMap<String, IssueContent>>,
+}
+
+/// Who to deliver the issue to: the members of `lists` (the default list if
+/// omitted) matching the optional `segment`.
+#[derive(Deserialize, Serialize)]
+pub struct PublishRequest {
+    lists: Option<Vec<String>>,
+    segment: Option<String>,
+}
+
+fn require_content(field: &str, value: &str) -> Result<(), ApiRequestError> {
+    if value.trim().is_empty() {
+        return Err(ApiRequestError::ValidationError(format!(
+            "The {field} cannot be empty."
+        )));
+    }
+    Ok(())
+}
+
+impl IssueContent {
+    fn validate(&self) -> Result<(), ApiRequestError> {
+        require_content("title", &self.title)?;
+        require_content("html_content", &self.html_content)?;
+        require_content("text_content", &self.text_content)
+    }
+}
+
+fn parse_variants(
+    variants: &HashMap<String, IssueContent>,
+) -> Result<Vec<IssueVariant<'_>>, ApiRequestError> {
+    let mut parsed: Vec<IssueVariant<'_>> = Vec::with_capacity(variants.len());
+    for (locale, content) in variants {
+        let locale = Locale::parse(locale).map_err(ApiRequestError::ValidationError)?;
+        if locale.is_default() {
+            return Err(ApiRequestError::ValidationError(format!(
+                "{locale} is the language of the issue itself, not a variant."
+            )));
+        }
+        if parsed.iter().any(|v| v.locale == locale) {
+            return Err(ApiRequestError::ValidationError(format!(
+                "The {locale} variant is given more than once."
+            )));
+        }
+        content.validate()?;
+        parsed.push(IssueVariant {
+            locale,
+            title: &content.title,
+            html_content: &content.html_content,
+            text_content: &content.text_content,
+        });
+    }
+    Ok(parsed)
+}
+
+fn parse_lists(lists: Option<Vec<String>>) -> Result<Vec<ListSlug>, ApiRequestError> {
+    let Some(lists) = lists else {
+        return Ok(vec![ListSlug::default()]);
+    };
+    let mut slugs: Vec<ListSlug> = Vec::new();
+    for slug in lists {
+        let slug = ListSlug::parse(slug).map_err(ApiRequestError::ValidationError)?;
+        if !slugs.contains(&slug) {
+            slugs.push(slug);
+        }
+    }
+    if slugs.is_empty() {
+        return Err(ApiRequestError::ValidationError(
+            "At least one mailing list must be selected.".into(),
+        ));
+    }
+    Ok(slugs)
+}
+
+/// Creating and publishing issues must be safe to retry, so both require an
+/// `Idempotency-Key` header.
+fn idempotency_key(request: &HttpRequest) -> Result<IdempotencyKey, ApiRequestError> {
+    let value = request
+        .headers()
+        .get("Idempotency-Key")
+        .ok_or_else(|| {
+            ApiRequestError::ValidationError("An Idempotency-Key header is required.".into())
+        })?
+        .to_str()
+        .map_err(|_| {
+            ApiRequestError::ValidationError(
+                "The Idempotency-Key header must be printable ASCII.".into(),
+            )
+        })?;
+    IdempotencyKey::try_from(value.to_owned())
+        .map_err(|e| ApiRequestError::ValidationError(e.to_string()))
+}
+
+/// The fingerprint of a request and its JSON body. The body is compared in
+/// a canonical form: `serde_json` sorts the keys of objects.
+fn json_fingerprint(
+    request: &HttpRequest,
+    body: &impl serde::Serialize,
+) -> Result<String, ApiRequestError> {
+    let payload = serde_json::to_value(body)
+        .and_then(|value| serde_json::to_vec(&value))
+        .context("Failed to serialize the request body.")?;
+    Ok(request_fingerprint(request, &payload))
+}
+
+fn reused_key() -> ApiRequestError {
+    ApiRequestError::UnprocessableEntity(
+        "The Idempotency-Key was already used for another request.".into(),
+    )
+}
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    published_at: Option<chrono::DateTime<chrono::Utc>>,
+    recipients: Option<i32>,
+    n_delivered: i32,
+    n_failed: i32,
+    pending: i64,
+}
+
+impl IssueSummary {
+    fn to_json(&self) -> serde_json::Value {
+        // `sent` and `failed` count the attempts made so far, `pending`
+        // the ones still queued.
+        let delivery = self.published_at.map(|_| {
+            serde_json::json!({
+                "recipients": self.recipients,
+                "pending": self.pending,
+                "sent": self.n_delivered,
+                "failed": self.n_failed,
+            })
+        });
+        serde_json::json!({
+            "id": self.newsletter_issue_id,
+            "title": self.title,
+            "status": if self.published_at.is_some() { "published" } else { "draft" },
+            "created_at": self.created_at.to_rfc3339(),
+            "published_at": self.published_at.map(|t| t.to_rfc3339()),
+            "delivery": delivery,
+        })
+    }
+}
+
+/// The issue with its contents, its translations and its delivery stats.
+#[tracing::instrument(skip(connection))]
+async fn fetch_issue(
+    connection: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<serde_json::Value>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issue_id, title, html_content, text_content, created_at,
+            published_at, recipients, n_delivered, n_failed,
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id) +
+            (SELECT COUNT(*) FROM digest_queue d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id) AS "pending!"
+        FROM newsletter_issues i
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+    let Some(r) = r else {
+        return Ok(None);
+    };
+    let variants = sqlx::query!(
+        r#"
+        SELECT locale, title, html_content, text_content
+        FROM newsletter_issue_variants
+        WHERE newsletter_issue_id = $1
+        ORDER BY locale
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(connection)
+    .await?;
+
+    let mut issue = IssueSummary {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        created_at: r.created_at,
+        published_at: r.published_at,
+        recipients: r.recipients,
+        n_delivered: r.n_delivered,
+        n_failed: r.n_failed,
+        pending: r.pending,
+    }
+    .to_json();
+    issue["html_content"] = r.html_content.into();
+    issue["text_content"] = r.text_content.into();
+    issue["variants"] = variants
+        .into_iter()
+        .map(|v| {
+            let content = serde_json::json!({
+                "title": v.title,
+                "html_content": v.html_content,
+                "text_content": v.text_content,
+            });
+            (v.locale, content)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+    Ok(Some(issue))
+}
+
+fn not_found(newsletter_issue_id: Uuid) -> ApiRequestError {
+    ApiRequestError::NotFound(format!("There is no issue with id {newsletter_issue_id}."))
+}
+
+fn already_published() -> ApiRequestError {
+    ApiRequestError::Conflict("This issue has already been published.".into())
+}
+
+/// Lock a draft for the rest of the transaction.
+async fn lock_draft(
+    connection: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<(), ApiRequestError> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT published_at FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(connection)
+    .await
+    .context("Failed to retrieve the issue.")?
+    .ok_or_else(|| not_found(newsletter_issue_id))?;
+    match issue.published_at {
+        Some(_) => Err(already_published()),
+        None => Ok(()),
+    }
+}
+
+/// `{"issues": [...], "next_page": 2}`, newest first, without their contents.
+#[tracing::instrument(name = "List issues through the API", skip(pool, client))]
+pub async fn list_issues_json(
+    query: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::IssuesPublish)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let rows = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            newsletter_issue_id, title, created_at, published_at, recipients,
+            n_delivered, n_failed,
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id) +
+            (SELECT COUNT(*) FROM digest_queue d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id) AS "pending!"
+        FROM newsletter_issues i
+        ORDER BY created_at DESC, newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve issues.")?;
+    let next_page = (rows.len() as i64 > PAGE_SIZE).then_some(page + 1);
+    let issues: Vec<_> = rows
+        .iter()
+        .take(PAGE_SIZE as usize)
+        .map(IssueSummary::to_json)
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "issues": issues,
+        "next_page": next_page,
+    })))
+}
+
+#[tracing::instrument(name = "Fetch an issue through the API", skip(pool, client))]
+pub async fn get_issue_json(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::IssuesPublish)?;
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a database connection.")?;
+    let issue = fetch_issue(&mut connection, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the issue.")?
+        .ok_or_else(|| not_found(newsletter_issue_id))?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+/// Store a draft, answering `201 Created` with the issue.
+#[tracing::instrument(
+    name = "Create an issue through the API",
+    skip_all,
+    fields(user_id=%client.user_id)
+)]
+pub async fn create_issue_json(
+    request: HttpRequest,
+    body: web::Json<NewIssue>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::IssuesPublish)?;
+    let idempotency_key = idempotency_key(&request)?;
+    let body = body.into_inner();
+    let fingerprint = json_fingerprint(&request, &body)?;
+    body.content.validate()?;
+    let variants = parse_variants(&body.variants)?;
+
+    let mut transaction =
+        match try_processing(&pool, &idempotency_key, client.user_id, &fingerprint).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            NextAction::RejectReusedKey => return Err(reused_key()),
+        };
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &body.content.title,
+        &body.content.text_content,
+        &body.content.html_content,
+    )
+    .await
+    .context("Failed to store the issue.")?;
+    insert_issue_variants(&mut transaction, issue_id, &variants)
+        .await
+        .context("Failed to store the translations of the issue.")?;
+    let issue = fetch_issue(&mut transaction, issue_id)
+        .await
+        .context("Failed to retrieve the new issue.")?
+        .context("The new issue was not stored.")?;
+
+    let response = HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/api/v1/issues/{issue_id}")))
+        .json(issue);
+    let response = save_response(transaction, &idempotency_key, client.user_id, response).await?;
+    Ok(response)
+}
+
+/// Change a draft. Published issues cannot be changed anymore.
+#[tracing::instrument(name = "Update an issue through the API", skip(body, pool, client))]
+pub async fn update_issue_json(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<IssueUpdate>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::IssuesPublish)?;
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let body = body.into_inner();
+    for (field, value) in [
+        ("title", &body.title),
+        ("html_content", &body.html_content),
+        ("text_content", &body.text_content),
+    ] {
+        if let Some(value) = value {
+            require_content(field, value)?;
+        }
+    }
+    let variants = body.variants.as_ref().map(parse_variants).transpose()?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a database connection.")?;
+    lock_draft(&mut transaction, newsletter_issue_id).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = COALESCE($2, title),
+            html_content = COALESCE($3, html_content),
+            text_content = COALESCE($4, text_content)
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.html_content,
+        body.text_content
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the issue.")?;
+    if let Some(variants) = variants {
+        sqlx::query!(
+            r#"DELETE FROM newsletter_issue_variants WHERE newsletter_issue_id = $1"#,
+            newsletter_issue_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to remove the translations of the issue.")?;
+        insert_issue_variants(&mut transaction, newsletter_issue_id, &variants)
+            .await
+            .context("Failed to store the translations of the issue.")?;
+    }
+    let issue = fetch_issue(&mut transaction, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the updated issue.")?
+        .ok_or_else(|| not_found(newsletter_issue_id))?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the update of the issue.")?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+/// Queue a draft for delivery, answering with the published issue.
+#[tracing::instrument(
+    name = "Publish an issue through the API",
+    skip_all,
+    fields(user_id=%client.user_id, newsletter_issue_id=%newsletter_issue_id)
+)]
+pub async fn publish_issue_json(
+    newsletter_issue_id: web::Path<Uuid>,
+    request: HttpRequest,
+    body: web::Json<PublishRequest>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::IssuesPublish)?;
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let idempotency_key = idempotency_key(&request)?;
+    let body = body.into_inner();
+    let fingerprint = json_fingerprint(&request, &body)?;
+    let lists = parse_lists(body.lists)?;
+    let segment = match body.segment.as_deref().map(str::trim) {
+        Some(segment) if !segment.is_empty() => {
+            Some(Segment::parse(segment).map_err(ApiRequestError::ValidationError)?)
+        }
+        _ => None,
+    };
+    let list_ids = get_list_ids_by_slugs(&pool, &lists)
+        .await
+        .context("Failed to look up the mailing lists.")?
+        .map_err(|slug| {
+            ApiRequestError::ValidationError(format!("{slug} is not a known mailing list."))
+        })?;
+
+    let mut transaction =
+        match try_processing(&pool, &idempotency_key, client.user_id, &fingerprint).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            NextAction::RejectReusedKey => return Err(reused_key()),
+        };
+    lock_draft(&mut transaction, newsletter_issue_id).await?;
+    publish_issue(
+        &mut transaction,
+        newsletter_issue_id,
+        &list_ids,
+        segment.as_ref(),
+    )
+    .await
+    .context("Failed to enqueue delivery tasks.")?;
+    let issue = fetch_issue(&mut transaction, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the published issue.")?
+        .ok_or_else(|| not_found(newsletter_issue_id))?;
+
+    let response = HttpResponse::Ok().json(issue);
+    let response = save_response(transaction, &idempotency_key, client.user_id, response).await?;
+    Ok(response)
+}
@@ -0,0 +1,58 @@
+//! JSON endpoints for our frontend and mobile app, served under `/api/v1`.
+mod issues;
+mod subscribers;
+mod subscriptions;
+
+pub use issues::*;
+pub use subscribers::*;
+pub use subscriptions::*;
+
+use crate::configuration::CorsSettings;
+use actix_cors::Cors;
+use actix_web::error::InternalError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpResponse};
+use serde_derive::Serialize;
+
+/// An entry of the `errors` array of a JSON error response.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    /// The request field the error is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            field: None,
+            message: message.into(),
+        }
+    }
+}
+
+/// `{"errors": [{"field": "email", "message": "..."}]}`
+pub fn json_error_response(status: StatusCode, errors: Vec<ApiError>) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "errors": errors }))
+}
+
+/// Report malformed JSON bodies in the same shape as the other errors.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|e, _| {
+        let response =
+            json_error_response(StatusCode::BAD_REQUEST, vec![ApiError::new(e.to_string())]);
+        InternalError::from_response(e, response).into()
+    })
+}
+
+/// Only the configured origins may call the API from a browser.
+pub fn cors(settings: &CorsSettings) -> Cors {
+    settings
+        .allowed_origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods(["GET", "POST"])
+        .allowed_header(header::CONTENT_TYPE)
+        .max_age(settings.max_age_seconds)
+}
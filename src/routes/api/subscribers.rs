@@ -0,0 +1,225 @@
+use super::{json_error_response, ApiError};
+use crate::authentication::{ApiClient, ApiScope, MissingScope};
+use crate::domain::{SubscriberAttributes, SubscriberTag};
+use crate::personal_data::erase_personal_data;
+use crate::utils::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use serde_derive::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Formatter;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 100;
+
+/// The errors of the endpoints authenticated with an API token.
+#[derive(thiserror::Error)]
+pub enum ApiRequestError {
+    #[error("{0}")]
+    ValidationError(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    UnprocessableEntity(String),
+
+    #[error(transparent)]
+    MissingScope(#[from] MissingScope),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiRequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiRequestError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiRequestError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiRequestError::Conflict(_) => StatusCode::CONFLICT,
+            ApiRequestError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiRequestError::MissingScope(_) => StatusCode::FORBIDDEN,
+            ApiRequestError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            // The details end up in our logs, not in the response.
+            ApiRequestError::UnexpectedError(_) => {
+                "Something went wrong on our side, please try again later.".to_string()
+            }
+            e => e.to_string(),
+        };
+        json_error_response(self.status_code(), vec![ApiError::new(message)])
+    }
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    tags: Vec<String>,
+    attributes: serde_json::Value,
+    subscribed_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SubscriberRow {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "email": self.email,
+            "name": self.name,
+            "status": self.status,
+            "tags": self.tags,
+            "attributes": self.attributes,
+            "subscribed_at": self.subscribed_at.to_rfc3339(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Pagination {
+    pub page: Option<i64>,
+}
+
+/// `{"subscribers": [...], "next_page": 2}`, newest first.
+#[tracing::instrument(name = "List subscribers through the API", skip(pool, client))]
+pub async fn list_subscribers_json(
+    query: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::SubscribersRead)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let rows = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, tags, attributes, subscribed_at
+        FROM subscriptions
+        ORDER BY subscribed_at DESC, id
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    let next_page = (rows.len() as i64 > PAGE_SIZE).then_some(page + 1);
+    let subscribers: Vec<_> = rows
+        .iter()
+        .take(PAGE_SIZE as usize)
+        .map(SubscriberRow::to_json)
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "subscribers": subscribers,
+        "next_page": next_page,
+    })))
+}
+
+/// Fields left out are not changed.
+#[derive(Deserialize)]
+pub struct SubscriberUpdate {
+    tags: Option<Vec<String>>,
+    attributes: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Attributes go through the same validation as the ones typed in the admin
+/// pages, so their values must be strings, numbers or booleans.
+fn parse_attributes(
+    attributes: serde_json::Map<String, serde_json::Value>,
+) -> Result<SubscriberAttributes, String> {
+    let mut pairs = Vec::new();
+    for (key, value) in attributes {
+        let value = match value {
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Number(_) | serde_json::Value::Bool(_) => value.to_string(),
+            other => return Err(format!("The value of {key} cannot be {other}.")),
+        };
+        pairs.push((key, value));
+    }
+    SubscriberAttributes::parse(pairs)
+}
+
+/// Replace the tags and/or the attributes of a subscriber.
+#[tracing::instrument(name = "Update a subscriber through the API", skip(body, pool, client))]
+pub async fn update_subscriber_json(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberUpdate>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::SubscribersWrite)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let body = body.into_inner();
+    let tags = body
+        .tags
+        .map(|tags| {
+            tags.into_iter()
+                .map(|t| SubscriberTag::parse(t).map(|t| t.as_ref().to_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map_err(ApiRequestError::ValidationError)?;
+    let attributes = body
+        .attributes
+        .map(|a| parse_attributes(a).map(SubscriberAttributes::into_json))
+        .transpose()
+        .map_err(ApiRequestError::ValidationError)?;
+
+    let row = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        UPDATE subscriptions
+        SET tags = COALESCE($2, tags), attributes = COALESCE($3, attributes)
+        WHERE id = $1
+        RETURNING id, email, name, status, tags, attributes, subscribed_at
+        "#,
+        subscriber_id,
+        tags.as_deref(),
+        attributes
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to update the subscriber.")?
+    .ok_or_else(|| not_found(subscriber_id))?;
+    Ok(HttpResponse::Ok().json(row.to_json()))
+}
+
+/// Delete everything we hold about a subscriber and suppress their address.
+#[tracing::instrument(name = "Erase a subscriber through the API", skip(pool, client))]
+pub async fn erase_subscriber_json(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, ApiRequestError> {
+    client.require(ApiScope::SubscribersWrite)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let email = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or_else(|| not_found(subscriber_id))?
+    .email;
+    erase_personal_data(&pool, &email).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn not_found(subscriber_id: Uuid) -> ApiRequestError {
+    ApiRequestError::NotFound(format!("There is no subscriber with id {subscriber_id}."))
+}
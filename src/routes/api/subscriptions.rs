@@ -0,0 +1,77 @@
+use super::{json_error_response, ApiError};
+use crate::configuration::SubscriptionProtectionSettings;
+use crate::domain::EmailNormalization;
+use crate::email_domains::EmailDomainPolicy;
+use crate::rate_limit::RateLimiter;
+use crate::routes::{register_subscription, RequestOrigin, SubscribeError, SubscribeParams};
+use crate::startup::HmacSecret;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use sqlx::PgPool;
+use std::fmt::Formatter;
+
+/// A [`SubscribeError`] rendered as JSON.
+pub struct SubscribeApiError(SubscribeError);
+
+impl std::fmt::Debug for SubscribeApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl std::fmt::Display for SubscribeApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl ResponseError for SubscribeApiError {
+    fn status_code(&self) -> StatusCode {
+        self.0.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let errors = match &self.0 {
+            SubscribeError::ValidationError(errors) => errors
+                .iter()
+                .map(|e| ApiError {
+                    field: Some(e.field.clone()),
+                    message: e.message.clone(),
+                })
+                .collect(),
+            SubscribeError::TooManyRequests => vec![ApiError::new(self.0.to_string())],
+            // The details end up in our logs, not in the response.
+            SubscribeError::UnexpectedError(_) => vec![ApiError::new(
+                "Something went wrong on our side, please try again later.",
+            )],
+        };
+        json_error_response(self.status_code(), errors)
+    }
+}
+
+/// `subscribe` for JSON clients: same parameters, JSON responses.
+#[allow(clippy::too_many_arguments)]
+pub async fn subscribe_json(
+    body: web::Json<SubscribeParams>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    normalization: web::Data<EmailNormalization>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    rate_limiter: web::Data<RateLimiter>,
+    protection: web::Data<SubscriptionProtectionSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, SubscribeApiError> {
+    register_subscription(
+        body.into_inner().with_request_locale(&request),
+        RequestOrigin::from_request(&request),
+        &pool,
+        &normalization,
+        &domain_policy,
+        &rate_limiter,
+        &protection,
+        &hmac_secret,
+    )
+    .await
+    .map_err(SubscribeApiError)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "pending_confirmation" })))
+}
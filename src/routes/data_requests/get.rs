@@ -0,0 +1,162 @@
+use crate::domain::SubscriberToken;
+use crate::personal_data::personal_data_archive;
+use crate::utils::error_chain_fmt;
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::{Formatter, Write};
+
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("{0}")]
+    ValidationError(String),
+
+    #[error("The link is not valid or has expired.")]
+    InvalidToken,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DataRequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DataRequestError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            DataRequestError::InvalidToken => StatusCode::UNAUTHORIZED,
+            DataRequestError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Params {
+    token: String,
+}
+
+/// Resolve a data request token to the email address it was mailed to.
+/// Tokens are valid for 24 hours.
+#[tracing::instrument(name = "Get email from data request token", skip(pool, token))]
+pub async fn get_email_from_data_request_token(
+    pool: &PgPool,
+    token: String,
+) -> Result<String, DataRequestError> {
+    let token = SubscriberToken::parse(token).map_err(DataRequestError::ValidationError)?;
+    let record = sqlx::query!(
+        r#"
+        SELECT email
+        FROM data_request_tokens
+        WHERE data_request_token = $1 AND created_at > now() - interval '24 hours'
+        "#,
+        token.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the data request.")?;
+    record
+        .map(|r| r.email)
+        .ok_or(DataRequestError::InvalidToken)
+}
+
+pub async fn data_request_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Your personal data</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Enter your email address to receive a link to download or erase the data we hold about you.</p>
+        <form action="/data-requests" method="post">
+            <label>Email
+                <input
+                        type="email"
+                        placeholder="Enter your email address"
+                        name="email"
+                        required
+                >
+            </label>
+            <button type="submit">Send me a link</button>
+        </form>
+    </body>
+</html>"#,
+        )))
+}
+
+/// Landing page of the link we mail out.
+pub async fn data_request_options(
+    params: web::Query<Params>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DataRequestError> {
+    let token = params.into_inner().token;
+    let email = get_email_from_data_request_token(&pool, token.clone()).await?;
+    let email = htmlescape::encode_minimal(&email);
+    let token = htmlescape::encode_minimal(&token);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Your personal data</title>
+    </head>
+    <body>
+        <p>Personal data held about {email}</p>
+        <p><a href="/data-requests/archive?token={token}">Download a copy of your data</a></p>
+        <form action="/data-requests/erase" method="post">
+            <input hidden type="text" name="token" value="{token}">
+            <p>Erasing your data unsubscribes you from all our mailing lists. This cannot be undone.</p>
+            <button type="submit">Erase my data</button>
+        </form>
+    </body>
+</html>"#,
+        )))
+}
+
+pub async fn data_request_archive(
+    params: web::Query<Params>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DataRequestError> {
+    let email = get_email_from_data_request_token(&pool, params.into_inner().token).await?;
+    let archive = personal_data_archive(&pool, &email)
+        .await?
+        .unwrap_or_else(|| serde_json::json!({ "email": email }));
+    Ok(archive_response(&archive))
+}
+
+/// Serve a personal data archive as a JSON attachment.
+pub fn archive_response(archive: &serde_json::Value) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("personal-data.json".into())],
+        })
+        .body(archive.to_string())
+}
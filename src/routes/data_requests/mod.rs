@@ -0,0 +1,10 @@
+mod get;
+mod post;
+
+pub use get::{
+    archive_response, data_request_archive, data_request_form, data_request_options,
+    DataRequestError,
+};
+pub use post::{
+    create_data_request_token, erase_requested_data, request_personal_data, send_data_request_email,
+};
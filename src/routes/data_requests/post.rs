@@ -0,0 +1,159 @@
+use super::get::get_email_from_data_request_token;
+use crate::configuration::SubscriptionProtectionSettings;
+use crate::domain::{EmailNormalization, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::personal_data::erase_personal_data;
+use crate::rate_limit::RateLimiter;
+use crate::routes::{generate_subscription_token, DataRequestError, RequestOrigin};
+use crate::utils::see_other;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde_derive::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+/// Mail a link to the data we hold about an address to that address.
+/// The response is the same whether we know the address or not: the email
+/// is sent by the background worker, and requests are rate limited per
+/// address and per IP address so that they cannot be used to flood inboxes.
+#[tracing::instrument(
+    name = "Request access to personal data",
+    skip(form, request, pool, normalization, rate_limiter, protection)
+)]
+pub async fn request_personal_data(
+    form: web::Form<RequestFormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    normalization: web::Data<EmailNormalization>,
+    rate_limiter: web::Data<RateLimiter>,
+    protection: web::Data<SubscriptionProtectionSettings>,
+) -> Result<HttpResponse, DataRequestError> {
+    let email = match SubscriberEmail::parse(form.into_inner().email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/data-requests"));
+        }
+    };
+    let canonical_email = email.canonical(&normalization);
+    let ip_allowed = match RequestOrigin::from_request(&request).ip {
+        Some(ip) => {
+            rate_limiter
+                .hit(
+                    &format!("data_request:ip:{ip}"),
+                    protection.max_data_requests_per_ip,
+                )
+                .await?
+        }
+        None => true,
+    };
+    let email_allowed = rate_limiter
+        .hit(
+            &format!("data_request:email:{canonical_email}"),
+            protection.max_data_requests_per_email,
+        )
+        .await?;
+    if ip_allowed && email_allowed {
+        enqueue_data_request(&pool, &canonical_email).await?;
+    } else {
+        tracing::warn!("Ignoring a rate limited data request.");
+    }
+
+    FlashMessage::info(
+        "If we hold data about this address, \
+        we have sent it a link to download or erase it.",
+    )
+    .send();
+    Ok(see_other("/data-requests"))
+}
+
+/// Queue a data request email for the subscriber with this canonical
+/// address, if there is one. The token is only created when it is sent.
+#[tracing::instrument(name = "Enqueue a data request email", skip(pool))]
+async fn enqueue_data_request(pool: &PgPool, canonical_email: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO data_request_email_outbox (outbox_id, subscriber_id, enqueued_at)
+        SELECT $1, id, now()
+        FROM subscriptions
+        WHERE canonical_email = $2
+        "#,
+        Uuid::new_v4(),
+        canonical_email
+    )
+    .execute(pool)
+    .await
+    .context("Failed to enqueue the data request email.")?;
+    Ok(())
+}
+
+/// Store a new data request token for an address as we hold it, which may
+/// be spelled differently from the one that was typed in, and return it.
+#[tracing::instrument(name = "Create a data request token", skip(transaction))]
+pub async fn create_data_request_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<String, sqlx::Error> {
+    let token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO data_request_tokens (data_request_token, email, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        token,
+        email
+    )
+    .execute(transaction)
+    .await?;
+    Ok(token)
+}
+
+#[tracing::instrument(
+    name = "Send a data request email",
+    skip(email_client, email, base_url, token)
+)]
+pub async fn send_data_request_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!("{}/data-requests/confirm?token={}", base_url, token);
+    let plain_body = format!(
+        "We received a request to access or erase the data we hold about this address.\n\
+        Visit {} to proceed, the link is valid for 24 hours.\n\
+        If you did not ask for this, you can ignore this email.",
+        link
+    );
+    let html_body = format!(
+        "We received a request to access or erase the data we hold about this address.<br />\
+        Click <a href=\"{}\">here</a> to proceed, the link is valid for 24 hours.<br />\
+        If you did not ask for this, you can ignore this email.",
+        link
+    );
+    email_client
+        .send_email(email, "Your personal data", &html_body, &plain_body)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct EraseFormData {
+    token: String,
+}
+
+#[tracing::instrument(name = "Erase personal data on request", skip(form, pool))]
+pub async fn erase_requested_data(
+    form: web::Form<EraseFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DataRequestError> {
+    let email = get_email_from_data_request_token(&pool, form.into_inner().token).await?;
+    erase_personal_data(&pool, &email).await?;
+    FlashMessage::info("Your personal data has been erased.").send();
+    Ok(see_other("/data-requests"))
+}
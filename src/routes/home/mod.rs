@@ -1,8 +1,12 @@
+use crate::routes::form_started_at;
+use crate::startup::HmacSecret;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 
-pub async fn home() -> HttpResponse {
+pub async fn home(hmac_secret: web::Data<HmacSecret>) -> HttpResponse {
+    // Lets `subscribe` tell how long it took to fill in the form.
+    let form_started_at = form_started_at(&hmac_secret.0, chrono::Utc::now().timestamp());
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(include_str!("home.html").replace("{form_started_at}", &form_started_at))
 }
@@ -0,0 +1,258 @@
+use crate::authentication::{create_user, verify_invitation_tag, Role};
+use crate::configuration::AuthenticationSettings;
+use crate::domain::Password;
+use crate::startup::HmacSecret;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const MAX_USERNAME_LENGTH: usize = 64;
+
+#[derive(serde::Deserialize)]
+pub struct InvitationParams {
+    invitation_id: String,
+    tag: String,
+}
+
+impl InvitationParams {
+    /// The invitation id, if the link was signed by us.
+    fn verified_id(&self, secret: &Secret<String>) -> Option<Uuid> {
+        let invitation_id = Uuid::parse_str(&self.invitation_id).ok()?;
+        verify_invitation_tag(secret, invitation_id, &self.tag).then_some(invitation_id)
+    }
+
+    fn accept_url(&self) -> String {
+        format!(
+            "/invitations/accept?invitation_id={}&tag={}",
+            urlencoding::encode(&self.invitation_id),
+            urlencoding::encode(&self.tag)
+        )
+    }
+}
+
+/// Landing page of the link mailed to an invited user.
+pub async fn invitation_form(
+    params: web::Query<InvitationParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = params.into_inner();
+    let email = match params.verified_id(&hmac_secret.0) {
+        Some(invitation_id) => get_pending_invitation_email(&pool, invitation_id)
+            .await
+            .map_err(e500)?,
+        None => None,
+    };
+    let Some(email) = email else {
+        return Ok(invalid_invitation_page());
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let email = encode_minimal(&email);
+    let invitation_id = encode_minimal(&params.invitation_id);
+    let tag = encode_minimal(&params.tag);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Create your account</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Create the account of {email} to help run our newsletter.</p>
+        <form action="/invitations/accept" method="post">
+            <input type="hidden" name="invitation_id" value="{invitation_id}">
+            <input type="hidden" name="tag" value="{tag}">
+            <label>Username
+                <input type="text" placeholder="Choose a username" name="username" required>
+            </label>
+            <label>Password
+                <input type="password" placeholder="Choose a password" name="password" required>
+            </label>
+            <label>Confirm password
+                <input
+                        type="password"
+                        placeholder="Type the password again"
+                        name="password_check"
+                        required
+                >
+            </label>
+            <button type="submit">Create account</button>
+        </form>
+    </body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInvitationFormData {
+    #[serde(flatten)]
+    invitation: InvitationParams,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(form, pool, hmac_secret, settings),
+    fields(username = %form.username)
+)]
+pub async fn accept_invitation(
+    form: web::Form<AcceptInvitationFormData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    settings: web::Data<AuthenticationSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let Some(invitation_id) = form.invitation.verified_id(&hmac_secret.0) else {
+        return Ok(invalid_invitation_page());
+    };
+    let accept_url = form.invitation.accept_url();
+    let username = match parse_username(&form.username) {
+        Ok(username) => username,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&accept_url));
+        }
+    };
+    let password = match Password::parse(form.password.expose_secret().to_owned()) {
+        Ok(password) => password,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&accept_url));
+        }
+    };
+    if form.password_check.expose_secret() != password.as_ref() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&accept_url));
+    }
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let username_is_taken =
+        sqlx::query!(r#"SELECT user_id FROM users WHERE username = $1"#, username)
+            .fetch_optional(&mut transaction)
+            .await
+            .map_err(e500)?
+            .is_some();
+    if username_is_taken {
+        FlashMessage::error(format!("The username {username} is already taken.")).send();
+        return Ok(see_other(&accept_url));
+    }
+    // Claiming the invitation in the transaction makes it usable only once.
+    let invitation = sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET accepted_at = now()
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        RETURNING email, role
+        "#,
+        invitation_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(e500)?;
+    let Some(invitation) = invitation else {
+        return Ok(invalid_invitation_page());
+    };
+    let role = Role::parse(&invitation.role).map_err(e500)?;
+    let created = create_user(
+        &mut transaction,
+        username,
+        &invitation.email,
+        role,
+        Secret::new(password.as_ref().to_owned()),
+        &settings.password_hashing,
+    )
+    .await;
+    // Someone else may have taken the username or the address in the
+    // meantime: dropping the transaction leaves the invitation unused.
+    if let Err(e) = created {
+        let message = match violated_unique_constraint(&e) {
+            Some("users_username_key") => format!("The username {username} is already taken."),
+            Some("users_email_key") => format!("{} already has an account.", invitation.email),
+            _ => return Err(e500(e)),
+        };
+        FlashMessage::error(message).send();
+        return Ok(see_other(&accept_url));
+    }
+    transaction.commit().await.map_err(e500)?;
+
+    FlashMessage::info("Your account has been created, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+/// The name of the unique constraint `e` ran into, if any.
+fn violated_unique_constraint(e: &anyhow::Error) -> Option<&str> {
+    let e = e.downcast_ref::<sqlx::Error>()?.as_database_error()?;
+    if e.code().as_deref() == Some("23505") {
+        e.constraint()
+    } else {
+        None
+    }
+}
+
+fn parse_username(s: &str) -> Result<&str, String> {
+    let username = s.trim();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        Err(format!(
+            "The username must be between 1 and {MAX_USERNAME_LENGTH} characters long."
+        ))
+    } else {
+        Ok(username)
+    }
+}
+
+#[tracing::instrument(name = "Get pending invitation", skip(pool))]
+async fn get_pending_invitation_email(
+    pool: &PgPool,
+    invitation_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT email
+        FROM user_invitations
+        WHERE invitation_id = $1 AND accepted_at IS NULL AND expires_at > now()
+        "#,
+        invitation_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the invitation.")?;
+    Ok(record.map(|r| r.email))
+}
+
+fn invalid_invitation_page() -> HttpResponse {
+    HttpResponse::build(StatusCode::BAD_REQUEST)
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Invalid invitation</title>
+    </head>
+    <body>
+        <p>This invitation is not valid, has already been used or has expired.
+            Please ask an administrator for a new one.</p>
+    </body>
+</html>"#,
+        )
+}
@@ -37,8 +37,13 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
                     name="password"
                 >
             </label>
+            <label>
+                <input type="checkbox" name="remember_me">
+                Remember me
+            </label>
             <button type="submit">Login</button>
         </form>
+        <p><a href="/password-reset">Forgot your password?</a></p>
     </body>
 </html>
             "#
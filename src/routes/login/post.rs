@@ -1,10 +1,15 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{
+    get_totp_secret, start_session, validate_credentials, AuthError, Credentials, LoginThrottle,
+    RememberedSession,
+};
+use crate::configuration::{AuthenticationSettings, SessionSettings};
+use crate::routes::RequestOrigin;
 use crate::session_state::TypedSession;
 use crate::utils::error_chain_fmt;
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use sqlx::PgPool;
@@ -14,37 +19,92 @@ use std::fmt::Formatter;
 pub struct FormData {
     username: String,
     password: Secret<String>,
+    /// Set when the "Remember me" box is ticked.
+    remember_me: Option<String>,
 }
 
 #[tracing::instrument(
     name = "Login a user",
-    skip(form, pool, session),
+    skip(form, pool, session, request, throttle, settings, session_settings),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    request: HttpRequest,
+    throttle: web::Data<LoginThrottle>,
+    settings: web::Data<AuthenticationSettings>,
+    session_settings: web::Data<SessionSettings>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let remember_me = form.0.remember_me.is_some();
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &pool).await {
+    let username = credentials.username.clone();
+    let origin = RequestOrigin::from_request(&request);
+    let ip = origin.ip.clone();
+    tracing::Span::current().record("username", &tracing::field::display(&username));
+    // Locked out attempts are not checked at all, so they cannot guess a password.
+    if throttle
+        .is_locked_out(&pool, &username, ip.as_deref())
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+    {
+        return Err(login_redirect(LoginError::TooManyAttempts));
+    }
+    match validate_credentials(credentials, &pool, &settings.password_hashing).await {
         Ok(user_id) => {
+            throttle
+                .record_success(&username)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            let two_factor_enabled = get_totp_secret(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+                .is_some();
             session.renew();
-            session
-                .insert(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            // The user is only logged in once they also entered a valid code.
+            let (result, location) = if two_factor_enabled {
+                (
+                    session.insert_pending_user_id(user_id, remember_me),
+                    "/login/two-factor",
+                )
+            } else {
+                let session_id = start_session(
+                    &pool,
+                    user_id,
+                    origin.ip.as_deref(),
+                    origin.user_agent.as_deref(),
+                    remember_me,
+                    &session_settings,
+                )
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                if remember_me {
+                    request
+                        .extensions_mut()
+                        .insert(RememberedSession(session_settings.lifetime(true)));
+                }
+                (session.insert(user_id, session_id), "/admin/dashboard")
+            };
+            result.map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
             Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
+                .insert_header((LOCATION, location))
                 .finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    let delay = throttle
+                        .record_failure(&pool, &username, ip.as_deref())
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    tokio::time::sleep(delay).await;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
 
@@ -68,6 +128,9 @@ pub enum LoginError {
 
     #[error("Authentication failed")]
     UnexpectedError(#[from] anyhow::Error),
+
+    #[error("Too many failed login attempts, please try again later.")]
+    TooManyAttempts,
 }
 
 impl std::fmt::Debug for LoginError {
@@ -0,0 +1,123 @@
+use crate::authentication::{
+    start_session, verify_second_factor, LoginThrottle, RememberedSession,
+};
+use crate::configuration::SessionSettings;
+use crate::routes::{get_username, RequestOrigin};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// The second step of the login of users with two-factor authentication.
+pub async fn login_two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Two-factor authentication</title>
+    </head>
+    <body>
+        {error_html}
+        <form action="/login/two-factor" method="post">
+            <label>Authentication code
+                <input
+                        type="text"
+                        placeholder="Enter the code from your app or a recovery code"
+                        name="code"
+                        autocomplete="one-time-code"
+                        required
+                >
+            </label>
+            <button type="submit">Verify</button>
+        </form>
+    </body>
+</html>"#
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Verify the second factor of a login",
+    skip(form, pool, session, request, throttle, session_settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+    throttle: web::Data<LoginThrottle>,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_pending_user_id().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    // Codes are guessed against the same counters as passwords.
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let origin = RequestOrigin::from_request(&request);
+    let ip = origin.ip.clone();
+    if throttle
+        .is_locked_out(&pool, &username, ip.as_deref())
+        .await
+        .map_err(e500)?
+    {
+        session.log_out();
+        FlashMessage::error("Too many failed login attempts, please try again later.").send();
+        return Ok(see_other("/login"));
+    }
+    if !verify_second_factor(&pool, user_id, form.0.code)
+        .await
+        .map_err(e500)?
+    {
+        let delay = throttle
+            .record_failure(&pool, &username, ip.as_deref())
+            .await
+            .map_err(e500)?;
+        tokio::time::sleep(delay).await;
+        FlashMessage::error("The authentication code is invalid.").send();
+        return Ok(see_other("/login/two-factor"));
+    }
+    throttle.record_success(&username).await.map_err(e500)?;
+    let remember_me = session.get_pending_remember_me().map_err(e500)?;
+    let session_id = start_session(
+        &pool,
+        user_id,
+        origin.ip.as_deref(),
+        origin.user_agent.as_deref(),
+        remember_me,
+        &session_settings,
+    )
+    .await
+    .map_err(e500)?;
+    if remember_me {
+        request
+            .extensions_mut()
+            .insert(RememberedSession(session_settings.lifetime(true)));
+    }
+    session.renew();
+    session.insert(user_id, session_id).map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
@@ -1,13 +1,25 @@
 mod admin;
+mod api;
+mod data_requests;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
+mod password_reset;
+mod preferences;
+mod subscribe_page;
 mod subscriptions;
 mod subscriptions_confirm;
 
 pub use admin::*;
+pub use api::*;
+pub use data_requests::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
+pub use password_reset::*;
+pub use preferences::*;
+pub use subscribe_page::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
@@ -0,0 +1,275 @@
+use crate::authentication::{
+    enqueue_password_reset, get_password_reset_username, reset_password, LoginThrottle,
+    PASSWORD_RESET_VALIDITY_MINUTES,
+};
+use crate::configuration::AuthenticationSettings;
+use crate::domain::{Password, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::rate_limit::RateLimiter;
+use crate::routes::RequestOrigin;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// Ask for a link to reset a forgotten password.
+pub async fn password_reset_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Forgot your password?</title>
+    </head>
+    <body>
+        {msg_html}
+        <form action="/password-reset" method="post">
+            <label>Email
+                <input
+                        type="email"
+                        placeholder="Enter the email address of your account"
+                        name="email"
+                        required
+                >
+            </label>
+            <button type="submit">Send me a reset link</button>
+        </form>
+        <p><a href="/login">&lt;- Back to login</a></p>
+    </body>
+</html>"#,
+        ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordResetRequestFormData {
+    email: String,
+}
+
+/// Mail a reset link to the user with this address. The answer is the same
+/// whether there is one or not, or the request was rate limited, so it
+/// cannot be used to find accounts. The email is sent by the background worker.
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, request, pool, rate_limiter, authentication)
+)]
+pub async fn request_password_reset(
+    form: web::Form<PasswordResetRequestFormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    rate_limiter: web::Data<RateLimiter>,
+    authentication: web::Data<AuthenticationSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/password-reset"));
+        }
+    };
+    let ip_allowed = match RequestOrigin::from_request(&request).ip {
+        Some(ip) => rate_limiter
+            .hit(
+                &format!("password_reset:ip:{ip}"),
+                authentication.max_password_resets_per_ip,
+            )
+            .await
+            .map_err(e500)?,
+        None => true,
+    };
+    let email_allowed = rate_limiter
+        .hit(
+            &format!("password_reset:email:{}", email.as_ref().to_lowercase()),
+            authentication.max_password_resets_per_email,
+        )
+        .await
+        .map_err(e500)?;
+    if ip_allowed && email_allowed {
+        enqueue_password_reset(&pool, email.as_ref())
+            .await
+            .map_err(e500)?;
+    } else {
+        tracing::warn!("Ignoring a rate limited password reset request.");
+    }
+    FlashMessage::info(format!(
+        "If an account uses {email}, we have sent it a link to reset its password."
+    ))
+    .send();
+    Ok(see_other("/password-reset"))
+}
+
+#[tracing::instrument(
+    name = "Send a password reset email",
+    skip(email_client, email, base_url, token)
+)]
+pub async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    username: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!("{}/password-reset/confirm?token={}", base_url, token);
+    let plain_body = format!(
+        "Someone asked to reset the password of {}.\n\
+        Visit {} to choose a new one, the link is valid for {} minutes.\n\
+        If it was not you, you can ignore this email.",
+        username, link, PASSWORD_RESET_VALIDITY_MINUTES
+    );
+    let html_body = format!(
+        "Someone asked to reset the password of {}.<br />\
+        Click <a href=\"{}\">here</a> to choose a new one, the link is valid for {} minutes.<br />\
+        If it was not you, you can ignore this email.",
+        encode_minimal(username),
+        link,
+        PASSWORD_RESET_VALIDITY_MINUTES
+    );
+    email_client
+        .send_email(email, "Reset your password", &html_body, &plain_body)
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordResetParams {
+    token: String,
+}
+
+impl PasswordResetParams {
+    fn reset_url(&self) -> String {
+        format!(
+            "/password-reset/confirm?token={}",
+            urlencoding::encode(&self.token)
+        )
+    }
+}
+
+/// Landing page of the link mailed to the user.
+pub async fn new_password_form(
+    params: web::Query<PasswordResetParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(username) = get_password_reset_username(&pool, &params.token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(invalid_link_page());
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let username = encode_minimal(&username);
+    let token = encode_minimal(&params.token);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Choose a new password</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Choose a new password for {username}.</p>
+        <form action="/password-reset/confirm" method="post">
+            <input type="hidden" name="token" value="{token}">
+            <label>New password
+                <input type="password" placeholder="Enter new password" name="password" required>
+            </label>
+            <label>Confirm new password
+                <input
+                        type="password"
+                        placeholder="Type the new password again"
+                        name="password_check"
+                        required
+                >
+            </label>
+            <button type="submit">Reset password</button>
+        </form>
+    </body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewPasswordFormData {
+    #[serde(flatten)]
+    params: PasswordResetParams,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Reset a forgotten password",
+    skip(form, pool, settings, throttle)
+)]
+pub async fn confirm_password_reset(
+    form: web::Form<NewPasswordFormData>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<AuthenticationSettings>,
+    throttle: web::Data<LoginThrottle>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let reset_url = form.params.reset_url();
+    let password = match Password::parse(form.password.expose_secret().to_owned()) {
+        Ok(password) => password,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&reset_url));
+        }
+    };
+    if form.password_check.expose_secret() != password.as_ref() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&reset_url));
+    }
+    let user_id = reset_password(
+        &pool,
+        &form.params.token,
+        Secret::new(password.as_ref().to_owned()),
+        &settings.password_hashing,
+    )
+    .await
+    .map_err(e500)?;
+    let Some(user_id) = user_id else {
+        return Ok(invalid_link_page());
+    };
+    // Whoever could read the reset email may log in again right away.
+    throttle.unlock_user(&pool, user_id).await.map_err(e500)?;
+
+    FlashMessage::info("Your password has been reset, you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+fn invalid_link_page() -> HttpResponse {
+    HttpResponse::build(StatusCode::BAD_REQUEST)
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Invalid link</title>
+    </head>
+    <body>
+        <p>This link is not valid, has already been used or has expired.</p>
+        <p><a href="/password-reset">Ask for a new one</a></p>
+    </body>
+</html>"#,
+        )
+}
@@ -0,0 +1,210 @@
+use crate::domain::SubscriberToken;
+use crate::utils::error_chain_fmt;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::{Formatter, Write};
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("{0}")]
+    ValidationError(String),
+
+    #[error("The preferences link is not valid.")]
+    UnknownToken,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PreferencesError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PreferencesError::UnknownToken => StatusCode::UNAUTHORIZED,
+            PreferencesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Params {
+    token: String,
+}
+
+/// Resolve the token of a preference center link to the subscriber it was issued to.
+#[tracing::instrument(name = "Get subscriber_id from preferences token", skip(pool, token))]
+pub async fn get_subscriber_id_by_preferences_token(
+    pool: &PgPool,
+    token: String,
+) -> Result<Uuid, PreferencesError> {
+    let token = SubscriberToken::parse(token).map_err(PreferencesError::ValidationError)?;
+    let record = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE preferences_token = $1"#,
+        token.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    record.map(|r| r.id).ok_or(PreferencesError::UnknownToken)
+}
+
+struct SubscriberPreferences {
+    email: String,
+    name: String,
+    status: String,
+    delivery_frequency: String,
+    paused_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+struct ListChoice {
+    slug: String,
+    name: String,
+    status: Option<String>,
+}
+
+pub async fn preferences_form(
+    params: web::Query<Params>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let token = params.into_inner().token;
+    let subscriber_id = get_subscriber_id_by_preferences_token(&pool, token.clone()).await?;
+    let subscriber = sqlx::query_as!(
+        SubscriberPreferences,
+        r#"
+        SELECT email, name, status, delivery_frequency, paused_until
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to retrieve the preferences of the subscriber.")?;
+    let lists = sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT l.slug, l.name, m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id AND m.subscriber_id = $1
+        ORDER BY l.created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the mailing lists.")?;
+
+    // Error messages may echo back what the subscriber typed in.
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut status_html = String::new();
+    if subscriber.status == "unsubscribed" {
+        writeln!(
+            status_html,
+            "<p>You are currently unsubscribed from all our mailing lists.</p>"
+        )
+        .unwrap();
+    }
+    if let Some(paused_until) = subscriber.paused_until.filter(|p| *p > chrono::Utc::now()) {
+        writeln!(
+            status_html,
+            "<p>Mail is paused until {}.</p>",
+            paused_until.format("%Y-%m-%d")
+        )
+        .unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in lists {
+        let checked = if list.status.as_deref() == Some("confirmed") {
+            " checked"
+        } else {
+            ""
+        };
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list_{}" value="on"{checked}> {}</label>"#,
+            encode_minimal(&list.slug),
+            encode_minimal(&list.name)
+        )
+        .unwrap();
+    }
+    let checked_if = |frequency: &str| {
+        if subscriber.delivery_frequency == frequency {
+            " checked"
+        } else {
+            ""
+        }
+    };
+    let every_issue = checked_if("every_issue");
+    let digest = checked_if("digest");
+    let email = encode_minimal(&subscriber.email);
+    let name = encode_minimal(&subscriber.name);
+    let token = encode_minimal(&token);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscription preferences</title>
+    </head>
+    <body>
+        {msg_html}
+        <p>Subscription preferences for {email}</p>
+        {status_html}
+        <form action="/preferences" method="post">
+            <input hidden type="text" name="token" value="{token}">
+            <label>Name
+                <input
+                        type="text"
+                        placeholder="Enter your name"
+                        name="name"
+                        value="{name}"
+                        required
+                >
+            </label>
+            <fieldset>
+                <legend>Mailing lists</legend>
+                {lists_html}
+            </fieldset>
+            <fieldset>
+                <legend>Frequency</legend>
+                <label><input type="radio" name="frequency" value="every_issue"{every_issue}> Every issue</label>
+                <label><input type="radio" name="frequency" value="digest"{digest}> Weekly digest</label>
+            </fieldset>
+            <label>Pause mail
+                <select name="pause_days">
+                    <option value="" selected>Keep the current setting</option>
+                    <option value="0">Resume now</option>
+                    <option value="7">For a week</option>
+                    <option value="30">For a month</option>
+                    <option value="90">For three months</option>
+                </select>
+            </label>
+            <button type="submit">Save preferences</button>
+        </form>
+        <form action="/preferences/unsubscribe" method="post">
+            <input hidden type="text" name="token" value="{token}">
+            <button type="submit">Unsubscribe from everything</button>
+        </form>
+    </body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{get_subscriber_id_by_preferences_token, preferences_form, PreferencesError};
+pub use post::{unsubscribe, update_preferences};
@@ -0,0 +1,276 @@
+use crate::automation::stop_inactive_sequences;
+use crate::domain::{DeliveryFrequency, ListSlug, SubscriberName};
+use crate::routes::{
+    get_list_ids_by_slugs, get_subscriber_id_by_preferences_token, PreferencesError,
+};
+use crate::utils::see_other;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde_derive::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Every checked mailing list is submitted as a `list_<slug>` field.
+const LIST_FIELD_PREFIX: &str = "list_";
+
+/// Mail can be paused for at most a year.
+const MAX_PAUSE_DAYS: i32 = 365;
+
+#[derive(Deserialize)]
+pub struct FormData {
+    token: String,
+    name: String,
+    frequency: String,
+    /// Empty to keep the current setting, `0` to resume delivery.
+    #[serde(default)]
+    pause_days: String,
+    #[serde(flatten)]
+    lists: HashMap<String, String>,
+}
+
+struct Preferences {
+    name: SubscriberName,
+    frequency: DeliveryFrequency,
+    pause_days: Option<i32>,
+    lists: Vec<ListSlug>,
+}
+
+fn parse_preferences(form: FormData) -> Result<Preferences, String> {
+    let name = SubscriberName::parse(form.name)?;
+    let frequency = DeliveryFrequency::parse(&form.frequency)?;
+    let pause_days = match form.pause_days.trim() {
+        "" => None,
+        days => match days.parse::<i32>() {
+            Ok(days) if (0..=MAX_PAUSE_DAYS).contains(&days) => Some(days),
+            _ => {
+                return Err(format!(
+                    "Mail can only be paused for up to {MAX_PAUSE_DAYS} days."
+                ))
+            }
+        },
+    };
+    let mut lists = Vec::new();
+    for key in form.lists.keys() {
+        if let Some(slug) = key.strip_prefix(LIST_FIELD_PREFIX) {
+            lists.push(ListSlug::parse(slug.to_string())?);
+        }
+    }
+    Ok(Preferences {
+        name,
+        frequency,
+        pause_days,
+        lists,
+    })
+}
+
+#[tracing::instrument(name = "Update subscriber preferences", skip(form, pool))]
+pub async fn update_preferences(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let form = form.into_inner();
+    let subscriber_id = get_subscriber_id_by_preferences_token(&pool, form.token.clone()).await?;
+    let location = format!("/preferences?token={}", form.token);
+    let preferences = match parse_preferences(form) {
+        Ok(preferences) => preferences,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let list_ids = match get_list_ids_by_slugs(&pool, &preferences.lists)
+        .await
+        .context("Failed to look up the mailing lists.")?
+    {
+        Ok(list_ids) => list_ids,
+        Err(slug) => {
+            FlashMessage::error(format!("{slug} is not a known mailing list.")).send();
+            return Ok(see_other(&location));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    update_subscriber_preferences(&mut transaction, subscriber_id, &preferences)
+        .await
+        .context("Failed to update the preferences of the subscriber.")?;
+    if preferences.pause_days.is_some_and(|days| days > 0) {
+        delete_queued_deliveries(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to drop the deliveries queued for the subscriber.")?;
+    }
+    update_list_memberships(&mut transaction, subscriber_id, &list_ids)
+        .await
+        .context("Failed to update the list memberships of the subscriber.")?;
+    stop_inactive_sequences(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to stop the automation sequences of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update the subscriber preferences.")?;
+
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&location))
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeFormData {
+    token: String,
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(form, pool))]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let form = form.into_inner();
+    let subscriber_id = get_subscriber_id_by_preferences_token(&pool, form.token.clone()).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    unsubscribe_from_everything(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")?;
+    delete_queued_deliveries(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to drop the deliveries queued for the subscriber.")?;
+    stop_inactive_sequences(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to stop the automation sequences of the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe the subscriber.")?;
+
+    FlashMessage::info("You have been unsubscribed from all our mailing lists.").send();
+    Ok(see_other(&format!("/preferences?token={}", form.token)))
+}
+
+#[tracing::instrument(skip(transaction, preferences))]
+async fn update_subscriber_preferences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    preferences: &Preferences,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            name = $2,
+            delivery_frequency = $3,
+            paused_until = CASE
+                WHEN $4::int IS NULL THEN paused_until
+                WHEN $4 = 0 THEN NULL
+                ELSE now() + make_interval(days => $4)
+            END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        preferences.name.as_ref(),
+        preferences.frequency.as_str(),
+        preferences.pause_days
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Confirm the memberships of the chosen lists and drop the other confirmed ones.
+/// Pending memberships are left alone, their confirmation link still works.
+#[tracing::instrument(skip(transaction))]
+async fn update_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND status = 'confirmed' AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if list_ids.is_empty() {
+        return Ok(());
+    }
+    // The preferences link was delivered to the subscriber's inbox,
+    // which is as good a proof of ownership as a confirmation link.
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+        SELECT list_id, $1, 'confirmed', now(), now()
+        FROM lists
+        WHERE list_id = ANY($2)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'confirmed', confirmed_at = COALESCE(list_memberships.confirmed_at, now())
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn unsubscribe_from_everything(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM digest_queue WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Drop the issues still waiting to be sent to the subscriber, so that
+/// an issue being sent right now does not reach them anymore.
+#[tracing::instrument(skip(transaction))]
+async fn delete_queued_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue q
+        USING subscriptions s
+        WHERE s.id = $1 AND q.subscriber_email = s.email
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use crate::configuration::SubscriptionProtectionSettings;
+use crate::domain::{EmailNormalization, ListSlug, Locale};
+use crate::email_domains::EmailDomainPolicy;
+use crate::i18n::{request_locale, t};
+use crate::rate_limit::RateLimiter;
+use crate::routes::{
+    form_started_at, register_subscription, RequestOrigin, SubscribeError, SubscribeParams,
+    CONSENT_TEXT_VERSION,
+};
+use crate::startup::HmacSecret;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const HOSTED_FORM_SOURCE: &str = "hosted_form";
+
+#[derive(serde::Deserialize)]
+pub struct SubscribePageParams {
+    /// The list to subscribe to, the default list if omitted.
+    list: Option<String>,
+    /// The language of the page, the browser's one if omitted.
+    locale: Option<String>,
+}
+
+struct HostedList {
+    slug: String,
+    name: String,
+    subscribed_redirect_url: Option<String>,
+}
+
+/// A signup form we host for every mailing list.
+pub async fn subscribe_page(
+    params: web::Query<SubscribePageParams>,
+    flash_messages: IncomingFlashMessages,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = params.into_inner();
+    let locale = params
+        .locale
+        .and_then(|l| Locale::parse(&l).ok())
+        .unwrap_or_else(|| request_locale(&request));
+    let list = match requested_list(params.list) {
+        Some(slug) => get_hosted_list(&pool, &slug).await.map_err(e500)?,
+        None => None,
+    };
+    let Some(list) = list else {
+        let title = encode_minimal(&t(locale, "subscribe_page.unknown_list.title", &[]));
+        let message = encode_minimal(&t(locale, "subscribe_page.unknown_list.message", &[]));
+        return Ok(HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="{locale}">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{title}</title>
+    </head>
+    <body>
+        <p>{message}</p>
+    </body>
+</html>"#,
+            )));
+    };
+
+    // Error messages may echo back what the visitor typed in.
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    // Catalog messages are plain text, the list name is inserted before escaping.
+    let text = |key: &str| encode_minimal(&t(locale, key, &[("list_name", &list.name)]));
+    let title = text("subscribe_page.title");
+    let name_label = text("subscribe_page.name");
+    let email_label = text("subscribe_page.email");
+    let consent = text("subscribe_page.consent");
+    let submit = text("subscribe_page.submit");
+    let slug = list.slug;
+    // Lets `subscribe` tell how long it took to fill in the form.
+    let form_started_at = form_started_at(&hmac_secret.0, chrono::Utc::now().timestamp());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{title}</title>
+    </head>
+    <body>
+        <h1>{title}</h1>
+        {msg_html}
+        <form action="/subscribe" method="post">
+            <label>{name_label}
+                <input type="text" name="name" required>
+            </label>
+            <label>{email_label}
+                <input type="email" name="email" required>
+            </label>
+            <!-- Left empty by humans, who do not see it. -->
+            <label style="display: none">Website
+                <input type="text" name="website" tabindex="-1" autocomplete="off">
+            </label>
+            <input type="hidden" name="list" value="{slug}">
+            <input type="hidden" name="form_started_at" value="{form_started_at}">
+            <input type="hidden" name="source" value="{HOSTED_FORM_SOURCE}">
+            <input type="hidden" name="consent_version" value="{CONSENT_TEXT_VERSION}">
+            <input type="hidden" name="locale" value="{locale}">
+            <p>{consent}</p>
+            <button type="submit">{submit}</button>
+        </form>
+    </body>
+</html>"#,
+        )))
+}
+
+/// `subscribe` for browsers: outcomes are shown on the hosted form,
+/// or the visitor is sent to the list's own page.
+#[allow(clippy::too_many_arguments)]
+pub async fn subscribe_from_page(
+    form: web::Form<SubscribeParams>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    normalization: web::Data<EmailNormalization>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    rate_limiter: web::Data<RateLimiter>,
+    protection: web::Data<SubscriptionProtectionSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let params = form.into_inner();
+    let list = requested_list(params.list.clone());
+    // Only a supported locale is echoed back, the others fail validation.
+    let submitted_locale = params.locale.as_deref().and_then(|l| Locale::parse(l).ok());
+    let mut query = Vec::new();
+    if let Some(list) = &list {
+        query.push(format!("list={list}"));
+    }
+    if let Some(locale) = submitted_locale {
+        query.push(format!("locale={locale}"));
+    }
+    let form_url = match query.is_empty() {
+        true => "/subscribe".to_string(),
+        false => format!("/subscribe?{}", query.join("&")),
+    };
+    let locale = submitted_locale.unwrap_or_else(|| request_locale(&request));
+
+    // Our forms always carry the field, so it missing is a bot's doing.
+    let result = if params.form_started_at.is_none() {
+        tracing::warn!("Ignoring a form submission without a form timestamp.");
+        Ok(())
+    } else {
+        register_subscription(
+            params.with_request_locale(&request),
+            RequestOrigin::from_request(&request),
+            &pool,
+            &normalization,
+            &domain_policy,
+            &rate_limiter,
+            &protection,
+            &hmac_secret,
+        )
+        .await
+    };
+    match result {
+        Ok(()) => {}
+        Err(SubscribeError::ValidationError(errors)) => {
+            for e in errors {
+                FlashMessage::error(e.message).send();
+            }
+            return Ok(see_other(&form_url));
+        }
+        Err(e @ SubscribeError::TooManyRequests) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&form_url));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+
+    // `register_subscription` succeeded, so the list exists.
+    let redirect_url = match &list {
+        Some(slug) => get_hosted_list(&pool, slug)
+            .await
+            .map_err(e500)?
+            .and_then(|list| list.subscribed_redirect_url),
+        None => None,
+    };
+    match redirect_url {
+        Some(redirect_url) => Ok(see_other(&redirect_url)),
+        None => {
+            FlashMessage::info(t(locale, "subscribe_page.thanks", &[])).send();
+            Ok(see_other(&form_url))
+        }
+    }
+}
+
+/// The list named in the request, `None` if that is not a valid identifier.
+fn requested_list(list: Option<String>) -> Option<ListSlug> {
+    match list {
+        Some(list) => ListSlug::parse(list).ok(),
+        None => Some(ListSlug::default()),
+    }
+}
+
+#[tracing::instrument(name = "Get hosted mailing list", skip(pool))]
+async fn get_hosted_list(
+    pool: &PgPool,
+    slug: &ListSlug,
+) -> Result<Option<HostedList>, anyhow::Error> {
+    sqlx::query_as!(
+        HostedList,
+        r#"SELECT slug, name, subscribed_redirect_url FROM lists WHERE slug = $1"#,
+        slug.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the mailing list.")
+}
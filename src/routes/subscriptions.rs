@@ -1,17 +1,31 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::configuration::SubscriptionProtectionSettings;
+use crate::domain::{
+    ConsentLabel, EmailNormalization, ListSlug, Locale, NewSubscriber, SubscriberAttributes,
+    SubscriberEmail, SubscriberName, SubscriberTag,
+};
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
+use crate::email_domains::EmailDomainPolicy;
+use crate::i18n::{request_locale, t};
+use crate::personal_data::is_suppressed;
+use crate::rate_limit::RateLimiter;
+use crate::routes::get_list_id_by_slug;
+use crate::startup::{HmacSecret, TrustedProxies};
 use crate::utils::error_chain_fmt;
+use actix_web::http::header::HeaderMap;
 use actix_web::http::StatusCode;
 use actix_web::ResponseError;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use anyhow::Context;
+use hmac::{Hmac, Mac};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
 use serde_derive::{Deserialize, Serialize};
 use sqlx::types::{chrono, uuid};
 use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use std::fmt::Formatter;
+use std::net::IpAddr;
 use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
@@ -19,22 +33,179 @@ use uuid::Uuid;
 pub struct SubscribeParams {
     pub name: String,
     pub email: String,
+    /// Identifier of the mailing list to join, the default list if omitted.
+    #[serde(default)]
+    pub list: Option<String>,
+    /// Comma-separated tags, e.g. `rust,beta`.
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// Identifier of the form or page the subscriber signed up from.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Version of the consent text shown next to the form,
+    /// [`CONSENT_TEXT_VERSION`] if omitted.
+    #[serde(default)]
+    pub consent_version: Option<String>,
+    /// Honeypot: hidden from humans, so it should always be left empty.
+    #[serde(default)]
+    pub website: Option<String>,
+    /// When the signup form was rendered, see [`form_started_at`].
+    #[serde(default)]
+    pub form_started_at: Option<String>,
+    /// Language of the emails and pages sent to the subscriber, e.g. `fr`.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Any other field prefixed with `attr_` becomes a custom attribute,
+    /// e.g. `attr_signup_source=conference`.
+    #[serde(flatten)]
+    pub extra: HashMap<String, String>,
+}
+
+const ATTRIBUTE_FIELD_PREFIX: &str = "attr_";
+
+/// The version of the consent text shown by our own signup form.
+pub const CONSENT_TEXT_VERSION: &str = "2023-12";
+const DEFAULT_CONSENT_SOURCE: &str = "subscribe_form";
+
+/// Where a request came from, kept as proof of consent.
+#[derive(Debug, Default)]
+pub struct RequestOrigin {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl RequestOrigin {
+    /// The IP is the peer address, unless the peer is one of the
+    /// [`TrustedProxies`], whose forwarding headers are then followed.
+    pub fn from_request(request: &HttpRequest) -> Self {
+        let trusted = request
+            .app_data::<web::Data<TrustedProxies>>()
+            .map(|proxies| proxies.0.as_slice())
+            .unwrap_or_default();
+        let ip = request
+            .peer_addr()
+            .map(|peer| client_ip(peer.ip(), trusted, request.headers()).to_string());
+        let user_agent = request
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string);
+        Self { ip, user_agent }
+    }
+}
+
+/// Walk `X-Forwarded-For` from the closest hop while it was added by a
+/// trusted proxy: entries further left can be set by the client itself.
+fn client_ip(peer: IpAddr, trusted: &[IpAddr], headers: &HeaderMap) -> IpAddr {
+    let mut client = peer;
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .collect();
+    for hop in hops.into_iter().rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+impl SubscribeParams {
+    /// Fall back to the language of the browser when no locale was submitted.
+    pub fn with_request_locale(mut self, request: &HttpRequest) -> Self {
+        if self.locale.is_none() {
+            self.locale = Some(request_locale(request).to_string());
+        }
+        self
+    }
 }
 
 impl TryFrom<SubscribeParams> for NewSubscriber {
     type Error = String;
 
     fn try_from(value: SubscribeParams) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(NewSubscriber { name, email })
+        parse_subscriber(value)
     }
 }
 
 pub fn parse_subscriber(form: SubscribeParams) -> Result<NewSubscriber, String> {
-    let name = SubscriberName::parse(form.name)?;
-    let email = SubscriberEmail::parse(form.email)?;
-    Ok(NewSubscriber { name, email })
+    parse_subscriber_fields(form).map_err(|errors| join_messages(&errors))
+}
+
+/// A submitted field that failed validation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: String) -> Self {
+        Self {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+/// Keep the error of an invalid field aside so that every field gets checked.
+fn check_field<T>(
+    errors: &mut Vec<FieldError>,
+    field: &str,
+    result: Result<T, String>,
+) -> Option<T> {
+    result
+        .map_err(|e| errors.push(FieldError::new(field, e)))
+        .ok()
+}
+
+fn join_messages(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Like [`parse_subscriber`], reporting every invalid field instead of the first one.
+pub fn parse_subscriber_fields(form: SubscribeParams) -> Result<NewSubscriber, Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let name = check_field(&mut errors, "name", SubscriberName::parse(form.name));
+    let email = check_field(&mut errors, "email", SubscriberEmail::parse(form.email));
+    let tags = check_field(
+        &mut errors,
+        "tags",
+        SubscriberTag::parse_many(form.tags.as_deref().unwrap_or_default()),
+    );
+    let attributes = check_field(
+        &mut errors,
+        "attributes",
+        SubscriberAttributes::parse(form.extra.iter().filter_map(|(key, value)| {
+            key.strip_prefix(ATTRIBUTE_FIELD_PREFIX)
+                .map(|key| (key, value))
+        })),
+    );
+    let locale = match &form.locale {
+        Some(locale) => check_field(&mut errors, "locale", Locale::parse(locale)),
+        None => Some(Locale::default()),
+    };
+    match (name, email, tags, attributes, locale) {
+        (Some(name), Some(email), Some(tags), Some(attributes), Some(locale)) => {
+            Ok(NewSubscriber {
+                name,
+                email,
+                tags,
+                attributes,
+                locale,
+            })
+        }
+        _ => Err(errors),
+    }
 }
 
 pub struct StoreTokenError(sqlx::Error);
@@ -60,8 +231,11 @@ impl std::error::Error for StoreTokenError {
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    #[error("{}", join_messages(.0))]
+    ValidationError(Vec<FieldError>),
+
+    #[error("Too many subscription requests, please try again later.")]
+    TooManyRequests,
 
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
@@ -86,17 +260,25 @@ impl std::fmt::Debug for SubscribeError {
         error_chain_fmt(self, f)
     }
 }
+impl SubscribeError {
+    fn invalid(field: &str) -> impl FnOnce(String) -> SubscribeError + '_ {
+        move |message| SubscribeError::ValidationError(vec![FieldError::new(field, message)])
+    }
+}
+
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
 /// Generate a random 25-characters-long case-sensitive subscription token.
-fn generate_subscription_token() -> String {
+/// Also used for the token giving access to the preference center.
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -104,68 +286,302 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
+pub async fn subscribe(
+    form: web::Form<SubscribeParams>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    normalization: web::Data<EmailNormalization>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    rate_limiter: web::Data<RateLimiter>,
+    protection: web::Data<SubscriptionProtectionSettings>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, SubscribeError> {
+    register_subscription(
+        form.into_inner().with_request_locale(&request),
+        RequestOrigin::from_request(&request),
+        &pool,
+        &normalization,
+        &domain_policy,
+        &rate_limiter,
+        &protection,
+        &hmac_secret,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Everything `subscribe` does, whichever format the request came in.
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber.",
-    skip(form, pool, email_client, base_url),
+    skip(
+        params,
+        origin,
+        pool,
+        normalization,
+        domain_policy,
+        rate_limiter,
+        protection,
+        hmac_secret
+    ),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name,
+        subscriber_email = %params.email,
+        subscriber_name = %params.name,
+        list = ?params.list,
     )
 )]
-pub async fn subscribe(
-    form: web::Form<SubscribeParams>,
-    pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+pub async fn register_subscription(
+    mut params: SubscribeParams,
+    origin: RequestOrigin,
+    pool: &PgPool,
+    normalization: &EmailNormalization,
+    domain_policy: &EmailDomainPolicy,
+    rate_limiter: &RateLimiter,
+    protection: &SubscriptionProtectionSettings,
+    hmac_secret: &HmacSecret,
+) -> Result<(), SubscribeError> {
+    // Bots are not told that they were spotted.
+    if looks_automated(&params, protection, hmac_secret) {
+        tracing::warn!("Ignoring a subscription request that looks automated.");
+        return Ok(());
+    }
+    if let Some(ip) = &origin.ip {
+        if !rate_limiter
+            .hit(
+                &format!("subscribe:ip:{ip}"),
+                protection.max_requests_per_ip,
+            )
+            .await?
+        {
+            return Err(SubscribeError::TooManyRequests);
+        }
+    }
+    let mut errors = Vec::new();
+    let list = match params.list.take() {
+        Some(list) => check_field(&mut errors, "list", ListSlug::parse(list)),
+        None => Some(ListSlug::default()),
+    };
+    let source = params
+        .source
+        .take()
+        .unwrap_or_else(|| DEFAULT_CONSENT_SOURCE.into());
+    let source = check_field(&mut errors, "source", ConsentLabel::parse(source));
+    let consent_version = params
+        .consent_version
+        .take()
+        .unwrap_or_else(|| CONSENT_TEXT_VERSION.into());
+    let consent_version = check_field(
+        &mut errors,
+        "consent_version",
+        ConsentLabel::parse(consent_version),
+    );
+    let new_subscriber = parse_subscriber_fields(params)
+        .map_err(|e| errors.extend(e))
+        .ok();
+    let (Some(list), Some(source), Some(consent_version), Some(new_subscriber)) =
+        (list, source, consent_version, new_subscriber)
+    else {
+        return Err(SubscribeError::ValidationError(errors));
+    };
+    domain_policy
+        .check(&new_subscriber.email)
+        .map_err(SubscribeError::invalid("email"))?;
+    // Protects the owner of the address from being sent a confirmation
+    // email every time someone submits it.
+    let email_key = format!(
+        "subscribe:email:{}",
+        new_subscriber.email.canonical(normalization)
+    );
+    if !rate_limiter
+        .hit(&email_key, protection.max_requests_per_email)
+        .await?
+    {
+        return Err(SubscribeError::TooManyRequests);
+    }
+    // Erased addresses are answered like any other, so the check does not
+    // reveal who asked to be forgotten.
+    if is_suppressed(pool, new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Ignoring a subscription request for a suppressed address.");
+        return Ok(());
+    }
+
+    let list_id = get_list_id_by_slug(pool, &list)
+        .await
+        .context("Failed to look up the mailing list.")?
+        .ok_or_else(|| {
+            SubscribeError::invalid("list")(format!("{list} is not a known mailing list."))
+        })?;
 
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = match get_subscriber_id_by_email(&pool, &new_subscriber).await {
-        Ok(subscriber_id) => subscriber_id,
-        Err(_) => {
-            tracing::info!("No duplicate subscriber found!, Creating new...");
-            insert_subscriber(&new_subscriber, &mut transaction)
-                .await
-                .context("Failed to insert new subscriber in the database.")?
-        }
-    };
+    let (subscriber_id, is_returning) =
+        match get_subscriber_id_by_email(pool, &new_subscriber, normalization).await {
+            Ok(subscriber_id) => (subscriber_id, true),
+            Err(_) => {
+                tracing::info!("No duplicate subscriber found!, Creating new...");
+                let subscriber_id =
+                    insert_subscriber(&new_subscriber, normalization, &mut transaction)
+                        .await
+                        .context("Failed to insert new subscriber in the database.")?;
+                (subscriber_id, false)
+            }
+        };
 
     tracing::info!("Proceed with adding subscriber");
-    let subscription_token = generate_subscription_token();
-
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    insert_list_membership(&mut transaction, list_id, subscriber_id)
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to add the subscriber to the mailing list.")?;
+    insert_consent_record(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        &source,
+        &consent_version,
+        &origin,
+    )
+    .await
+    .context("Failed to record the consent of the subscriber.")?;
+
+    let recent_token = get_recent_subscription_token(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        protection.token_reuse_cooldown_seconds,
+    )
+    .await
+    .context("Failed to look up a recent confirmation token.")?;
+    let subscription_token = match recent_token {
+        Some(subscription_token) => subscription_token,
+        None => {
+            let subscription_token = generate_subscription_token();
+            store_token(
+                &mut transaction,
+                subscriber_id,
+                list_id,
+                &subscription_token,
+            )
+            .await
+            .context("Failed to store the confirmation token for a new subscriber.")?;
+            subscription_token
+        }
+    };
+    // Anyone can submit an address, so changes to an existing subscriber
+    // wait for its owner to confirm them.
+    if is_returning {
+        stage_subscriber_segmentation(&mut transaction, &subscription_token, &new_subscriber)
+            .await
+            .context("Failed to store the tags and attributes to apply on confirmation.")?;
+    }
+
+    enqueue_confirmation_email(
+        &mut transaction,
+        subscriber_id,
+        &new_subscriber.email,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to enqueue the confirmation email.")?;
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
+    Ok(())
+}
 
-    Ok(HttpResponse::Ok().finish())
+/// Returns `true` if the honeypot field was filled in or the form was
+/// submitted faster than a human could have filled it.
+/// A `form_started_at` that was not signed by us counts as automated.
+fn looks_automated(
+    params: &SubscribeParams,
+    protection: &SubscriptionProtectionSettings,
+    hmac_secret: &HmacSecret,
+) -> bool {
+    if params.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        return true;
+    }
+    let Some(started_at) = &params.form_started_at else {
+        return false;
+    };
+    match verify_form_started_at(&hmac_secret.0, started_at) {
+        Some(started_at) => {
+            chrono::Utc::now().timestamp() - started_at < protection.min_fill_time_seconds
+        }
+        None => true,
+    }
+}
+
+fn form_mac(secret: &Secret<String>, started_at: i64) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("form_started_at:{started_at}").as_bytes());
+    mac
+}
+
+/// The value of the hidden `form_started_at` field of a form rendered
+/// at `started_at` (a Unix timestamp): the timestamp and an HMAC tag of it,
+/// so that bots cannot backdate it.
+pub fn form_started_at(secret: &Secret<String>, started_at: i64) -> String {
+    let tag = hex::encode(form_mac(secret, started_at).finalize().into_bytes());
+    format!("{started_at}.{tag}")
+}
+
+/// The timestamp of a `form_started_at` value, if its tag is valid.
+fn verify_form_started_at(secret: &Secret<String>, value: &str) -> Option<i64> {
+    let (started_at, tag) = value.split_once('.')?;
+    let started_at: i64 = started_at.parse().ok()?;
+    let tag = hex::decode(tag).ok()?;
+    form_mac(secret, started_at)
+        .verify_slice(&tag)
+        .ok()
+        .map(|_| started_at)
+}
+
+/// Write a confirmation email to the outbox, it is sent by the background
+/// worker once `transaction` is committed.
+#[tracing::instrument(
+    name = "Enqueue a confirmation email",
+    skip(transaction, subscriber_email, subscription_token)
+)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscriber_email: &SubscriberEmail,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_outbox (
+            outbox_id, subscriber_id, subscriber_email, subscription_token, enqueued_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        subscriber_email.as_ref(),
+        subscription_token
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url)
+    skip(email_client, subscriber_email, base_url)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+    subscriber_email: &SubscriberEmail,
+    locale: Locale,
     base_url: &str,
     subscription_token: &str,
 ) -> Result<(), reqwest::Error> {
@@ -173,62 +589,171 @@ pub async fn send_confirmation_email(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-                Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    let args = [("confirmation_link", confirmation_link.as_str())];
+    let plain_body = t(locale, "confirmation_email.text", &args);
+    let html_body = t(locale, "confirmation_email.html", &args);
 
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .send_email(
+            subscriber_email,
+            &t(locale, "confirmation_email.subject", &[]),
+            &html_body,
+            &plain_body,
+        )
         .await
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, transaction)
+    skip(new_subscriber, normalization, transaction)
 )]
 pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
+    normalization: &EmailNormalization,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
+    let tags: Vec<String> = new_subscriber
+        .tags
+        .iter()
+        .map(|t| t.as_ref().to_owned())
+        .collect();
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id, email, canonical_email, name, subscribed_at, status, tags, attributes,
+            preferences_token, locale
+        )
+        VALUES ($1, $2, $3, $4, $5, 'pending_confirmation', $6, $7, $8, $9)
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.canonical(normalization),
         new_subscriber.name.as_ref(),
-        chrono::Utc::now()
+        chrono::Utc::now(),
+        &tags[..],
+        new_subscriber.attributes.clone().into_json(),
+        generate_subscription_token(),
+        new_subscriber.locale.as_ref()
     )
     .execute(transaction)
     .await?;
     Ok(subscriber_id)
 }
 
+/// Keep the tags and attributes submitted by a returning subscriber with
+/// its confirmation token, they are merged by `confirm`.
+#[tracing::instrument(
+    name = "Stage subscriber tags and attributes",
+    skip(transaction, subscription_token, new_subscriber)
+)]
+pub async fn stage_subscriber_segmentation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+    new_subscriber: &NewSubscriber,
+) -> Result<(), sqlx::Error> {
+    if new_subscriber.tags.is_empty() && new_subscriber.attributes.is_empty() {
+        return Ok(());
+    }
+    let tags: Vec<String> = new_subscriber
+        .tags
+        .iter()
+        .map(|t| t.as_ref().to_owned())
+        .collect();
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET
+            pending_tags = ARRAY(SELECT DISTINCT unnest(pending_tags || $2::text[])),
+            pending_attributes = pending_attributes || $3
+        WHERE subscription_token = $1
+        "#,
+        subscription_token,
+        &tags[..],
+        new_subscriber.attributes.clone().into_json()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Add subscriber to a mailing list",
+    skip(transaction, list_id, subscriber_id)
+)]
+pub async fn insert_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id) DO NOTHING
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Keep track of who opted in, from where and after being shown which text.
+/// The record is completed when the subscriber confirms.
+#[tracing::instrument(
+    name = "Record subscriber consent",
+    skip(transaction, subscriber_id, list_id, origin)
+)]
+pub async fn insert_consent_record(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    source: &ConsentLabel,
+    consent_version: &ConsentLabel,
+    origin: &RequestOrigin,
+) -> Result<Uuid, sqlx::Error> {
+    let consent_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            consent_id, subscriber_id, list_id, source, consent_text_version,
+            signup_ip, signup_user_agent, signed_up_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        "#,
+        consent_id,
+        subscriber_id,
+        list_id,
+        source.as_ref(),
+        consent_version.as_ref(),
+        origin.ip,
+        origin.user_agent
+    )
+    .execute(transaction)
+    .await?;
+    Ok(consent_id)
+}
+
 #[tracing::instrument(
     name = "Store subscription token in the database",
-    skip(transaction, subscriber_id, subscription_token)
+    skip(transaction, subscriber_id, list_id, subscription_token)
 )]
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<Uuid, StoreTokenError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id, created_at)
+        VALUES ($1, $2, $3, now())
         "#,
         subscription_token,
         subscriber_id,
+        list_id,
     )
     .execute(transaction)
     .await
@@ -240,17 +765,51 @@ pub async fn store_token(
     Ok(subscriber_id)
 }
 
+/// A confirmation token issued for the same list less than
+/// `cooldown_seconds` ago that has not been used yet.
+#[tracing::instrument(
+    name = "Get recent subscription token",
+    skip(transaction, subscriber_id, list_id)
+)]
+pub async fn get_recent_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    cooldown_seconds: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"
+        SELECT subscription_token
+        FROM subscription_tokens
+        WHERE
+            subscriber_id = $1
+            AND list_id = $2
+            AND NOT expired
+            AND created_at > now() - make_interval(secs => $3)
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        list_id,
+        cooldown_seconds as f64
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(record.map(|r| r.subscription_token))
+}
+
 #[tracing::instrument(
     name = "Get subscriber id by email from the database",
-    skip(pool, new_subscriber,)
+    skip(pool, new_subscriber, normalization)
 )]
 pub async fn get_subscriber_id_by_email(
     pool: &PgPool,
     new_subscriber: &NewSubscriber,
+    normalization: &EmailNormalization,
 ) -> Result<Uuid, sqlx::Error> {
     let record = sqlx::query!(
-        r#"SELECT id FROM subscriptions WHERE email = $1"#,
-        new_subscriber.email.as_ref()
+        r#"SELECT id FROM subscriptions WHERE canonical_email = $1"#,
+        new_subscriber.email.canonical(normalization)
     )
     .fetch_one(pool)
     .await
@@ -283,3 +842,60 @@ pub fn is_valid_name(s: &str) -> bool {
     let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
     !(is_empty_or_whitespace || is_too_long || contains_forbidden_characters)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, form_started_at, verify_form_started_at};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use secrecy::Secret;
+    use std::net::IpAddr;
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_headers_from_untrusted_peers_are_ignored() {
+        let headers = forwarded_for("1.2.3.4");
+        assert_eq!(client_ip(ip("10.0.0.1"), &[], &headers), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn the_closest_untrusted_hop_is_the_client() {
+        let headers = forwarded_for("6.6.6.6, 1.2.3.4, 10.0.0.2");
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        assert_eq!(client_ip(ip("10.0.0.1"), &trusted, &headers), ip("1.2.3.4"));
+    }
+
+    #[test]
+    fn an_unparseable_hop_stops_the_walk() {
+        let headers = forwarded_for("1.2.3.4, garbage");
+        let trusted = [ip("10.0.0.1")];
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &trusted, &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn form_timestamps_must_carry_a_valid_tag() {
+        let secret = Secret::new("a-very-long-secret".to_string());
+        let value = form_started_at(&secret, 1_700_000_000);
+
+        assert_eq!(verify_form_started_at(&secret, &value), Some(1_700_000_000));
+        let other_secret = Secret::new("another-secret".to_string());
+        assert_eq!(verify_form_started_at(&other_secret, &value), None);
+        let backdated = value.replacen("1700000000", "1600000000", 1);
+        assert_eq!(verify_form_started_at(&secret, &backdated), None);
+        assert_eq!(verify_form_started_at(&secret, "1700000000"), None);
+    }
+}
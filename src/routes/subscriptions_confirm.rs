@@ -1,19 +1,22 @@
-use crate::domain::SubscriberToken;
-use crate::utils::error_chain_fmt;
+use crate::automation::enroll_in_sequences;
+use crate::domain::{Locale, SubscriberToken};
+use crate::i18n::{request_locale, t};
+use crate::routes::RequestOrigin;
+use crate::utils::{error_chain_fmt, see_other};
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use htmlescape::encode_minimal;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use std::fmt::Formatter;
 use uuid::Uuid;
 
-// pub struct
+/// Confirmation links that were not clicked within this many days stop working.
+const CONFIRMATION_LINK_VALIDITY_DAYS: i64 = 7;
 
 #[derive(thiserror::Error)]
 pub enum ConfirmError {
-    #[error("{0}")]
-    ValidationError(String),
-
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -28,7 +31,6 @@ impl ResponseError for ConfirmError {
     fn status_code(&self) -> StatusCode {
         match self {
             ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            ConfirmError::ValidationError(_) => StatusCode::BAD_REQUEST,
         }
     }
 }
@@ -44,74 +46,303 @@ impl TryFrom<String> for SubscriberToken {
         SubscriberToken::parse(value)
     }
 }
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(params, pool))]
+
+/// What clicking on a confirmation link did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfirmationOutcome {
+    Confirmed,
+    AlreadyConfirmed,
+    Expired,
+    Invalid,
+}
+
+struct TokenRecord {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    expired: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+    slug: String,
+    name: String,
+    confirmed_redirect_url: Option<String>,
+    membership_status: Option<String>,
+    locale: String,
+}
+
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(params, request, pool))]
 pub async fn confirm(
     params: web::Query<Params>,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, ConfirmError> {
-    let subscription_token: SubscriberToken = params
-        .subscription_token
-        .clone()
-        .try_into()
-        .map_err(ConfirmError::ValidationError)?;
-
-    let id = get_subscriber_id_from_token(&pool, subscription_token.as_ref())
+    let Ok(subscription_token) = SubscriberToken::try_from(params.subscription_token.clone())
+    else {
+        return Ok(confirmation_page(
+            ConfirmationOutcome::Invalid,
+            None,
+            request_locale(&request),
+        ));
+    };
+    // Every step happens in one transaction, so that a failure halfway can
+    // be retried, and the token row stays locked so concurrent clicks queue up.
+    let mut transaction = pool
+        .begin()
         .await
-        .unwrap()
-        .context("Failed to retrieve subscriber")?;
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(record) = get_subscription_token(&mut transaction, subscription_token.as_ref())
+        .await
+        .context("Failed to retrieve the subscription token")?
+    else {
+        return Ok(confirmation_page(
+            ConfirmationOutcome::Invalid,
+            None,
+            request_locale(&request),
+        ));
+    };
 
-    confirm_subscriber(&pool, id)
+    let link_is_too_old = record.created_at
+        < chrono::Utc::now() - chrono::Duration::days(CONFIRMATION_LINK_VALIDITY_DAYS);
+    let link_is_valid = !record.expired && !link_is_too_old;
+    if link_is_valid {
+        apply_pending_segmentation(&mut transaction, subscription_token.as_ref())
+            .await
+            .context("Failed to apply the tags and attributes of the subscriber")?;
+    }
+    let outcome = if record.membership_status.as_deref() == Some("confirmed") {
+        ConfirmationOutcome::AlreadyConfirmed
+    } else if !link_is_valid {
+        ConfirmationOutcome::Expired
+    } else {
+        let (id, list_id) = (record.subscriber_id, record.list_id);
+        confirm_subscriber(&mut transaction, id)
+            .await
+            .context("Failed to Confirm subscriber")?;
+        confirm_list_membership(&mut transaction, list_id, id)
+            .await
+            .context("Failed to confirm the mailing list membership")?;
+        confirm_consent_record(
+            &mut transaction,
+            list_id,
+            id,
+            &RequestOrigin::from_request(&request),
+        )
         .await
-        .context("Failed to Confirm subscriber")?;
-    expire_subscription_token(&pool, subscription_token.as_ref())
+        .context("Failed to record the confirmation of the subscriber's consent")?;
+        expire_subscription_token(&mut transaction, subscription_token.as_ref())
+            .await
+            .context("Invalid subscription token")?;
+        enroll_in_sequences(&mut transaction, list_id, id)
+            .await
+            .context("Failed to start the automation sequences of the list")?;
+        ConfirmationOutcome::Confirmed
+    };
+    transaction
+        .commit()
         .await
-        .context("Invalid subscription token")?;
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+
+    match (&outcome, &record.confirmed_redirect_url) {
+        (
+            ConfirmationOutcome::Confirmed | ConfirmationOutcome::AlreadyConfirmed,
+            Some(redirect_url),
+        ) => Ok(see_other(redirect_url)),
+        _ => {
+            // Unknown locales fall back to the browser's language.
+            let locale = Locale::parse(&record.locale).unwrap_or_else(|_| request_locale(&request));
+            Ok(confirmation_page(outcome, Some(&record), locale))
+        }
+    }
+}
 
-    Ok(HttpResponse::Ok().finish())
+fn confirmation_page(
+    outcome: ConfirmationOutcome,
+    record: Option<&TokenRecord>,
+    locale: Locale,
+) -> HttpResponse {
+    let list_name = record
+        .map(|r| encode_minimal(&r.name))
+        .unwrap_or_else(|| encode_minimal(&t(locale, "confirmation_page.default_list_name", &[])));
+    let subscribe_url = match record {
+        Some(r) => format!("/subscribe?list={}", r.slug),
+        None => "/subscribe".into(),
+    };
+    let (status, key) = match outcome {
+        ConfirmationOutcome::Confirmed => (StatusCode::OK, "confirmed"),
+        ConfirmationOutcome::AlreadyConfirmed => (StatusCode::OK, "already_confirmed"),
+        ConfirmationOutcome::Expired => (StatusCode::GONE, "expired"),
+        ConfirmationOutcome::Invalid => (StatusCode::BAD_REQUEST, "invalid"),
+    };
+    let title = encode_minimal(&t(locale, &format!("confirmation_page.{key}.title"), &[]));
+    // The messages may contain a link, the list name is escaped above.
+    let message = t(
+        locale,
+        &format!("confirmation_page.{key}.message"),
+        &[("list_name", &list_name), ("subscribe_url", &subscribe_url)],
+    );
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{title}</title>
+    </head>
+    <body>
+        <h1>{title}</h1>
+        <p>{message}</p>
+    </body>
+</html>"#,
+        ))
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(pool, subscription_token))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+/// The token along with its list and the state of the membership it confirms.
+/// The token stays locked until the transaction ends.
+#[tracing::instrument(name = "Get subscription token", skip(transaction, subscription_token))]
+async fn get_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<Option<TokenRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenRecord,
+        r#"
+        SELECT
+            t.subscriber_id,
+            t.list_id,
+            t.expired,
+            t.created_at,
+            l.slug,
+            l.name,
+            l.confirmed_redirect_url,
+            m.status AS "membership_status?",
+            s.locale
+        FROM subscription_tokens t
+        JOIN lists l ON l.list_id = t.list_id
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        LEFT JOIN list_memberships m
+            ON m.list_id = t.list_id AND m.subscriber_id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        FOR UPDATE OF t
+        "#,
         subscription_token
     )
-    .fetch_optional(pool)
+    .fetch_optional(transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool, subscriber_id))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(transaction, subscriber_id)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
         subscriber_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Mark list membership as confirmed",
+    skip(transaction, list_id, subscriber_id)
+)]
+pub async fn confirm_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed', confirmed_at = COALESCE(confirmed_at, now())
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Complete the pending consent records of a subscriber for a list.
+#[tracing::instrument(
+    name = "Record consent confirmation",
+    skip(executor, list_id, subscriber_id, origin)
+)]
+pub async fn confirm_consent_record<'c>(
+    executor: impl PgExecutor<'c>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    origin: &RequestOrigin,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE consent_records
+        SET confirmation_ip = $3, confirmation_user_agent = $4, confirmed_at = now()
+        WHERE list_id = $1 AND subscriber_id = $2 AND confirmed_at IS NULL
+        "#,
+        list_id,
+        subscriber_id,
+        origin.ip,
+        origin.user_agent
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Merge the tags and attributes submitted along with a token into the
+/// subscriber. Existing tags are never removed.
+#[tracing::instrument(
+    name = "Apply pending segmentation",
+    skip(transaction, subscription_token)
+)]
+async fn apply_pending_segmentation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH pending AS (
+            UPDATE subscription_tokens t
+            SET pending_tags = '{}', pending_attributes = '{}'
+            FROM subscription_tokens old
+            WHERE t.subscription_token = $1
+                AND old.subscription_token = t.subscription_token
+            RETURNING t.subscriber_id, old.pending_tags, old.pending_attributes
+        )
+        UPDATE subscriptions s
+        SET
+            tags = ARRAY(SELECT DISTINCT unnest(s.tags || p.pending_tags)),
+            attributes = s.attributes || p.pending_attributes
+        FROM pending p
+        WHERE s.id = p.subscriber_id
+        "#,
+        subscription_token
+    )
+    .execute(transaction)
     .await?;
     Ok(())
 }
 
 #[tracing::instrument(
     name = "Mark subscription_token as expired",
-    skip(pool, subscription_token)
+    skip(transaction, subscription_token)
 )]
 pub async fn expire_subscription_token(
-    pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"UPDATE subscription_tokens SET expired = true WHERE subscription_token = $1"#,
         subscription_token
     )
-    .fetch_optional(pool)
+    .fetch_optional(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
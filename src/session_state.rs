@@ -1,18 +1,31 @@
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
+use secrecy::{ExposeSecret, Secret};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
+/// The name of the session cookie.
+pub const SESSION_COOKIE_NAME: &str = "id";
+
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    /// Set between a valid password and a valid second factor.
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const PENDING_REMEMBER_ME_KEY: &'static str = "pending_remember_me";
+    const TOTP_ENROLLMENT_SECRET_KEY: &'static str = "totp_enrollment_secret";
 
     pub fn renew(&self) {
         self.0.renew();
     }
-    pub fn insert(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+    /// Log in a user, in a session tracked with `start_session`.
+    pub fn insert(&self, user_id: Uuid, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+        self.0.remove(Self::PENDING_REMEMBER_ME_KEY);
+        self.0.insert(Self::SESSION_ID_KEY, session_id)?;
         self.0.insert(Self::USER_ID_KEY, user_id)
     }
 
@@ -20,6 +33,53 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// Half log in a user: they are not logged in until `insert` is called.
+    pub fn insert_pending_user_id(
+        &self,
+        user_id: Uuid,
+        remember_me: bool,
+    ) -> Result<(), SessionInsertError> {
+        self.0.remove(Self::USER_ID_KEY);
+        self.0.insert(Self::PENDING_REMEMBER_ME_KEY, remember_me)?;
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+
+    /// Whether the half logged in user asked to be remembered.
+    pub fn get_pending_remember_me(&self) -> Result<bool, SessionGetError> {
+        Ok(self
+            .0
+            .get(Self::PENDING_REMEMBER_ME_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// The secret shown to the user while they set up their authenticator app.
+    pub fn insert_totp_enrollment_secret(
+        &self,
+        secret: &Secret<String>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::TOTP_ENROLLMENT_SECRET_KEY, secret.expose_secret())
+    }
+
+    pub fn get_totp_enrollment_secret(&self) -> Result<Option<Secret<String>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::TOTP_ENROLLMENT_SECRET_KEY)?
+            .map(Secret::new))
+    }
+
+    pub fn remove_totp_enrollment_secret(&self) {
+        self.0.remove(Self::TOTP_ENROLLMENT_SECRET_KEY);
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
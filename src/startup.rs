@@ -1,11 +1,36 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{
+    persist_remembered_sessions, reject_anonymous_users, reject_invalid_api_tokens, LoginThrottle,
+};
+use crate::canonical_emails::ensure_canonical_emails;
+use crate::configuration::{
+    AuthenticationSettings, CorsSettings, DatabaseSettings, SessionSettings, Settings,
+    SubscriptionProtectionSettings,
+};
+use crate::domain::EmailNormalization;
 use crate::email_client::EmailClient;
+use crate::email_domains::EmailDomainPolicy;
+use crate::rate_limit::RateLimiter;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, home, login, login_form,
-    logout, newsletters, publish_newsletter,
+    accept_invitation, add_api_token, add_email_domain_rule, add_sequence_step, admin_dashboard,
+    api_tokens_form, change_password, change_password_form, change_user_role, confirm,
+    confirm_password_reset, cors, create_issue_json, create_list, create_sequence,
+    data_request_archive, data_request_form, data_request_options, delete_email_domain_rule,
+    delete_user, disable_two_factor, disable_user, email_domains_form, enable_two_factor,
+    enable_user, erase_requested_data, erase_subscriber, erase_subscriber_json, export_subscribers,
+    get_issue_json, home, import_form, import_rejected_rows, import_report,
+    import_subscribers_upload, invitation_form, invite_user, json_config, list_issues_json,
+    list_subscribers_json, lists_form, lockouts_log, log_out_everywhere, login, login_form,
+    login_two_factor, login_two_factor_form, logout, new_password_form, newsletters,
+    password_reset_form, preferences_form, publish_issue_json, publish_newsletter,
+    reload_email_domains, request_password_reset, request_personal_data, revoke_token,
+    revoke_user_session, sequences_form, sessions_list, subscribe_from_page, subscribe_json,
+    subscribe_page, subscriber_archive, subscriber_details, subscribers, two_factor_settings,
+    unlock_login, unsubscribe, update_issue_json, update_list_redirects, update_preferences,
+    update_subscriber, update_subscriber_json, users_form,
 };
+use crate::session_state::SESSION_COOKIE_NAME;
 use crate::{health_check, subscribe};
+use actix_session::config::BrowserSession;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
@@ -15,10 +40,11 @@ use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
+use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
 use tracing_actix_web::TracingLogger;
 pub struct Application {
     port: u16,
@@ -27,8 +53,17 @@ pub struct Application {
 
 impl Application {
     pub async fn build(settings: Settings) -> Result<Self, anyhow::Error> {
+        // Fail on startup rather than on the first login.
+        settings
+            .authentication
+            .password_hashing
+            .params()
+            .context("Invalid password hashing parameters")?;
         let connection_pool = get_connection_pool(&settings.database);
+        ensure_canonical_emails(&connection_pool, &settings.email_normalization).await?;
         let email_client = settings.email_client.client();
+        let email_domain_policy =
+            EmailDomainPolicy::load(&settings.email_domains, &connection_pool).await?;
         let address = format!(
             "{}:{}",
             settings.application.host, settings.application.port
@@ -41,7 +76,14 @@ impl Application {
             email_client,
             settings.application.base_url,
             settings.application.hmac_secret,
+            settings.application.trusted_proxies,
             settings.redis_uri,
+            settings.email_normalization,
+            email_domain_policy,
+            settings.subscription_protection,
+            settings.cors,
+            settings.authentication,
+            settings.session,
         )
         .await?;
         Ok(Self { port, server })
@@ -58,52 +100,217 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    trusted_proxies: Vec<IpAddr>,
     redis_uri: Secret<String>,
+    email_normalization: EmailNormalization,
+    email_domain_policy: EmailDomainPolicy,
+    subscription_protection: SubscriptionProtectionSettings,
+    cors_settings: CorsSettings,
+    authentication: AuthenticationSettings,
+    session_settings: SessionSettings,
 ) -> Result<Server, anyhow::Error> {
     let port = listener.local_addr().unwrap().port();
     tracing::info!("starting server at http://localhost:{}", port);
     let connection = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let trusted_proxies = web::Data::new(TrustedProxies(trusted_proxies));
+    let email_normalization = web::Data::new(email_normalization);
+    let email_domain_policy = web::Data::new(email_domain_policy);
+    let rate_limiter = web::Data::new(
+        RateLimiter::build(
+            subscription_protection.rate_limit_backend,
+            subscription_protection.rate_limit_window(),
+            &redis_uri,
+        )
+        .await?,
+    );
+    let subscription_protection = web::Data::new(subscription_protection);
+    let login_throttle =
+        web::Data::new(LoginThrottle::build(authentication.clone(), &redis_uri).await?);
+    let authentication = web::Data::new(authentication);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store =
         CookieMessageStore::builder(Key::from(hmac_secret.expose_secret().as_bytes())).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    // Sessions expire from `user_sessions`, the state is kept as long as the
+    // longest of them may last.
+    let session_lifecycle =
+        BrowserSession::default().state_ttl(actix_web::cookie::time::Duration::seconds(
+            session_settings.lifetime(true).as_secs() as i64,
+        ));
+    let cookie_secure = session_settings.cookie_secure;
+    let cookie_same_site = session_settings.cookie_same_site.into();
+    let session_settings = web::Data::new(session_settings);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .cookie_name(SESSION_COOKIE_NAME.to_string())
+                    .cookie_http_only(true)
+                    .cookie_secure(cookie_secure)
+                    .cookie_same_site(cookie_same_site)
+                    .session_lifecycle(session_lifecycle.clone())
+                    .build(),
+            )
+            .wrap(from_fn(persist_remembered_sessions))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscribe", web::get().to(subscribe_page))
+            .route("/subscribe", web::post().to(subscribe_from_page))
+            .service(
+                web::scope("/api/v1")
+                    .wrap(cors(&cors_settings))
+                    .app_data(json_config())
+                    .route("/subscriptions", web::post().to(subscribe_json))
+                    .service(
+                        web::scope("")
+                            .wrap(from_fn(reject_invalid_api_tokens))
+                            .route("/subscribers", web::get().to(list_subscribers_json))
+                            .route(
+                                "/subscribers/{subscriber_id}",
+                                web::patch().to(update_subscriber_json),
+                            )
+                            .route(
+                                "/subscribers/{subscriber_id}",
+                                web::delete().to(erase_subscriber_json),
+                            )
+                            .route("/issues", web::get().to(list_issues_json))
+                            .route("/issues", web::post().to(create_issue_json))
+                            .route(
+                                "/issues/{newsletter_issue_id}",
+                                web::get().to(get_issue_json),
+                            )
+                            .route(
+                                "/issues/{newsletter_issue_id}",
+                                web::patch().to(update_issue_json),
+                            )
+                            .route(
+                                "/issues/{newsletter_issue_id}/publish",
+                                web::post().to(publish_issue_json),
+                            ),
+                    ),
+            )
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(update_preferences))
+            .route("/preferences/unsubscribe", web::post().to(unsubscribe))
+            .route("/data-requests", web::get().to(data_request_form))
+            .route("/data-requests", web::post().to(request_personal_data))
+            .route(
+                "/data-requests/confirm",
+                web::get().to(data_request_options),
+            )
+            .route(
+                "/data-requests/archive",
+                web::get().to(data_request_archive),
+            )
+            .route("/data-requests/erase", web::post().to(erase_requested_data))
             // .route("/newsletters", web::post().to(publish_newsletter))
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/two-factor", web::get().to(login_two_factor_form))
+            .route("/login/two-factor", web::post().to(login_two_factor))
+            .route("/password-reset", web::get().to(password_reset_form))
+            .route("/password-reset", web::post().to(request_password_reset))
+            .route("/password-reset/confirm", web::get().to(new_password_form))
+            .route(
+                "/password-reset/confirm",
+                web::post().to(confirm_password_reset),
+            )
+            .route("/invitations/accept", web::get().to(invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route("/two-factor", web::get().to(two_factor_settings))
+                    .route("/two-factor", web::post().to(enable_two_factor))
+                    .route("/two-factor/disable", web::post().to(disable_two_factor))
+                    .route("/sessions", web::get().to(sessions_list))
+                    .route("/sessions/revoke", web::post().to(revoke_user_session))
+                    .route("/sessions/revoke-all", web::post().to(log_out_everywhere))
+                    .route("/api-tokens", web::get().to(api_tokens_form))
+                    .route("/api-tokens", web::post().to(add_api_token))
+                    .route("/api-tokens/revoke", web::post().to(revoke_token))
                     .route("/logout", web::post().to(logout))
                     .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/newsletters", web::get().to(newsletters)),
+                    .route("/newsletters", web::get().to(newsletters))
+                    .route("/lists", web::get().to(lists_form))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/lists/redirects", web::post().to(update_list_redirects))
+                    .route("/sequences", web::get().to(sequences_form))
+                    .route("/sequences", web::post().to(create_sequence))
+                    .route("/sequences/steps", web::post().to(add_sequence_step))
+                    .route("/email-domains", web::get().to(email_domains_form))
+                    .route("/email-domains", web::post().to(add_email_domain_rule))
+                    .route(
+                        "/email-domains/delete",
+                        web::post().to(delete_email_domain_rule),
+                    )
+                    .route(
+                        "/email-domains/reload",
+                        web::post().to(reload_email_domains),
+                    )
+                    .route("/users", web::get().to(users_form))
+                    .route("/users/invitations", web::post().to(invite_user))
+                    .route("/users/disable", web::post().to(disable_user))
+                    .route("/users/enable", web::post().to(enable_user))
+                    .route("/users/delete", web::post().to(delete_user))
+                    .route("/users/role", web::post().to(change_user_role))
+                    .route("/lockouts", web::get().to(lockouts_log))
+                    .route("/lockouts/unlock", web::post().to(unlock_login))
+                    .route("/subscribers", web::get().to(subscribers))
+                    // Registered before `/subscribers/{subscriber_id}`, which would shadow it.
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/imports", web::get().to(import_form))
+                    .route("/imports", web::post().to(import_subscribers_upload))
+                    .route("/imports/{import_id}", web::get().to(import_report))
+                    .route(
+                        "/imports/{import_id}/rejected.csv",
+                        web::get().to(import_rejected_rows),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::post().to(update_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/archive",
+                        web::get().to(subscriber_archive),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/erase",
+                        web::post().to(erase_subscriber),
+                    ),
             )
             .app_data(connection.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(email_normalization.clone())
+            .app_data(email_domain_policy.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(subscription_protection.clone())
+            .app_data(authentication.clone())
+            .app_data(session_settings.clone())
+            .app_data(login_throttle.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
@@ -114,6 +321,9 @@ pub async fn run(
 #[derive(Clone, Debug)]
 pub struct HmacSecret(pub Secret<String>);
 
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
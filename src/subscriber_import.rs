@@ -0,0 +1,406 @@
+use crate::domain::{
+    ConsentLabel, EmailNormalization, Locale, NewSubscriber, SubscriberAttributes, SubscriberEmail,
+    SubscriberName, SubscriberTag,
+};
+use crate::personal_data::is_suppressed;
+use crate::routes::{
+    confirm_consent_record, enqueue_confirmation_email, generate_subscription_token,
+    insert_consent_record, insert_list_membership, insert_subscriber, store_token, RequestOrigin,
+};
+use crate::utils::{csv_quote, error_chain_fmt};
+use anyhow::Context;
+use csv_async::{AsyncReaderBuilder, StringRecord, Trim};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashSet;
+use std::fmt::Formatter;
+use tokio::io::AsyncRead;
+use uuid::Uuid;
+
+/// The consent source of imported subscribers.
+const IMPORT_CONSENT_SOURCE: &str = "csv_import";
+/// They were shown a consent text we know nothing about.
+const IMPORT_CONSENT_TEXT_VERSION: &str = "external";
+/// Columns named `attr_<key>` hold the custom attribute `<key>`.
+const ATTRIBUTE_COLUMN_PREFIX: &str = "attr_";
+
+/// What happens to the subscribers we import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// They already opted in elsewhere: store them as confirmed right away.
+    Confirmed,
+    /// Store them as pending and send each of them a confirmation email.
+    SendConfirmation,
+}
+
+impl ImportMode {
+    pub fn parse(s: &str) -> Result<ImportMode, String> {
+        match s {
+            "confirmed" => Ok(Self::Confirmed),
+            "send_confirmation" => Ok(Self::SendConfirmation),
+            other => Err(format!("{other} is not a valid import mode.")),
+        }
+    }
+}
+
+/// A row of the CSV that could not be imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRow {
+    pub line: i64,
+    pub email: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: i64,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl ImportReport {
+    /// Render the rejected rows as a `line,email,reason` CSV.
+    pub fn rejected_rows_csv(&self) -> String {
+        let mut csv = String::from("line,email,reason\n");
+        for row in &self.rejected {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                row.line,
+                csv_quote(&row.email),
+                csv_quote(&row.reason)
+            ));
+        }
+        csv
+    }
+
+    fn reject(&mut self, line: i64, email: &str, reason: impl Into<String>) {
+        self.rejected.push(RejectedRow {
+            line,
+            email: email.to_string(),
+            reason: reason.into(),
+        });
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    ValidationError(String),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Import the subscribers listed in `csv` into the mailing list `list_id`.
+///
+/// The CSV must have a header row with (at least) an `email` and a `name`
+/// column. An optional `tags` column holds comma-separated tags, and
+/// `attr_<key>` columns hold custom attributes; empty cells are skipped.
+/// Rows are read and stored one at a time: invalid rows and
+/// addresses we already know about (or were asked to forget) are reported
+/// instead of aborting the import. Addresses are compared in their canonical
+/// form, see [`SubscriberEmail::canonical`].
+#[tracing::instrument(name = "Import subscribers", skip(pool, normalization, csv))]
+pub async fn import_subscribers<R>(
+    pool: &PgPool,
+    normalization: &EmailNormalization,
+    list_id: Uuid,
+    mode: ImportMode,
+    csv: R,
+) -> Result<ImportReport, ImportError>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut reader = AsyncReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .create_reader(csv);
+    let headers = reader
+        .headers()
+        .await
+        .map_err(|e| ImportError::ValidationError(format!("Failed to read the CSV header: {e}")))?;
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ImportError::ValidationError(format!("The CSV has no `{name}` column.")))
+    };
+    let email_column = column("email")?;
+    let name_column = column("name")?;
+    let tags_column = column("tags").ok();
+    let attribute_columns: Vec<(usize, String)> = headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| {
+            h.strip_prefix(ATTRIBUTE_COLUMN_PREFIX)
+                .map(|key| (i, key.to_string()))
+        })
+        .collect();
+
+    let mut report = ImportReport::default();
+    let mut seen_emails = HashSet::new();
+    let mut record = StringRecord::new();
+    loop {
+        match reader.read_record(&mut record).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => {
+                return Err(anyhow::anyhow!(e).context("Failed to read the CSV.").into())
+            }
+            Err(e) => {
+                let line = e.position().map(|p| p.line() as i64).unwrap_or_default();
+                report.reject(line, "", e.to_string());
+                continue;
+            }
+        }
+        let line = record
+            .position()
+            .map(|p| p.line() as i64)
+            .unwrap_or_default();
+        let email = record.get(email_column).unwrap_or_default();
+        let name = record.get(name_column).unwrap_or_default();
+        let tags = tags_column.and_then(|i| record.get(i)).unwrap_or_default();
+        let attributes = attribute_columns.iter().filter_map(|(i, key)| {
+            record
+                .get(*i)
+                .filter(|value| !value.is_empty())
+                .map(|value| (key.as_str(), value))
+        });
+
+        let new_subscriber = match parse_row(name, email, tags, attributes) {
+            Ok(new_subscriber) => new_subscriber,
+            Err(e) => {
+                report.reject(line, email, e);
+                continue;
+            }
+        };
+        let canonical_email = new_subscriber.email.canonical(normalization);
+        if !seen_emails.insert(canonical_email.clone()) {
+            report.reject(line, email, "The email appears earlier in the file.");
+            continue;
+        }
+        if is_known_email(pool, &canonical_email)
+            .await
+            .context("Failed to look up an existing subscriber.")?
+        {
+            report.reject(line, email, "The email is already subscribed.");
+            continue;
+        }
+        if is_suppressed(pool, email)
+            .await
+            .context("Failed to check the suppression list.")?
+        {
+            report.reject(line, email, "The email was erased at its owner's request.");
+            continue;
+        }
+
+        store_subscriber(pool, &new_subscriber, normalization, list_id, mode)
+            .await
+            .context("Failed to store an imported subscriber.")?;
+        report.imported += 1;
+    }
+    Ok(report)
+}
+
+fn parse_row<'a>(
+    name: &str,
+    email: &str,
+    tags: &str,
+    attributes: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Result<NewSubscriber, String> {
+    Ok(NewSubscriber {
+        name: SubscriberName::parse(name.to_string())?,
+        email: SubscriberEmail::parse(email.to_string())?,
+        tags: SubscriberTag::parse_many(tags)?,
+        attributes: SubscriberAttributes::parse(attributes)?,
+        locale: Locale::default(),
+    })
+}
+
+async fn is_known_email(pool: &PgPool, canonical_email: &str) -> Result<bool, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE canonical_email = $1"#,
+        canonical_email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(record.is_some())
+}
+
+/// Confirmation emails go through the outbox, like the ones of `subscribe`.
+async fn store_subscriber(
+    pool: &PgPool,
+    new_subscriber: &NewSubscriber,
+    normalization: &EmailNormalization,
+    list_id: Uuid,
+    mode: ImportMode,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscriber_id = insert_subscriber(new_subscriber, normalization, &mut transaction).await?;
+    let source = ConsentLabel::parse(IMPORT_CONSENT_SOURCE.into()).map_err(anyhow::Error::msg)?;
+    let consent_version =
+        ConsentLabel::parse(IMPORT_CONSENT_TEXT_VERSION.into()).map_err(anyhow::Error::msg)?;
+    insert_consent_record(
+        &mut transaction,
+        subscriber_id,
+        list_id,
+        &source,
+        &consent_version,
+        &RequestOrigin::default(),
+    )
+    .await?;
+    match mode {
+        ImportMode::Confirmed => {
+            confirm_imported_subscriber(&mut transaction, subscriber_id, list_id).await?;
+            confirm_consent_record(
+                &mut transaction,
+                list_id,
+                subscriber_id,
+                &RequestOrigin::default(),
+            )
+            .await?;
+        }
+        ImportMode::SendConfirmation => {
+            insert_list_membership(&mut transaction, list_id, subscriber_id).await?;
+            let subscription_token = generate_subscription_token();
+            store_token(
+                &mut transaction,
+                subscriber_id,
+                list_id,
+                &subscription_token,
+            )
+            .await?;
+            enqueue_confirmation_email(
+                &mut transaction,
+                subscriber_id,
+                &new_subscriber.email,
+                &subscription_token,
+            )
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn confirm_imported_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+        VALUES ($1, $2, 'confirmed', now(), now())
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Keep the outcome of an import around so that the rejected rows can be downloaded.
+#[tracing::instrument(skip_all)]
+pub async fn save_import_report(pool: &PgPool, report: &ImportReport) -> Result<Uuid, sqlx::Error> {
+    let import_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (import_id, imported_rows, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        import_id,
+        report.imported
+    )
+    .execute(&mut transaction)
+    .await?;
+    let lines: Vec<i64> = report.rejected.iter().map(|r| r.line).collect();
+    let emails: Vec<String> = report.rejected.iter().map(|r| r.email.clone()).collect();
+    let reasons: Vec<String> = report.rejected.iter().map(|r| r.reason.clone()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_import_rejections (import_id, line, email, reason)
+        SELECT $1, * FROM UNNEST($2::bigint[], $3::text[], $4::text[])
+        "#,
+        import_id,
+        &lines[..],
+        &emails[..],
+        &reasons[..]
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(import_id)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_import_report(
+    pool: &PgPool,
+    import_id: Uuid,
+) -> Result<Option<ImportReport>, sqlx::Error> {
+    let import = sqlx::query!(
+        r#"SELECT imported_rows FROM subscriber_imports WHERE import_id = $1"#,
+        import_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(import) = import else {
+        return Ok(None);
+    };
+    let rejected = sqlx::query_as!(
+        RejectedRow,
+        r#"
+        SELECT line, email, reason
+        FROM subscriber_import_rejections
+        WHERE import_id = $1
+        ORDER BY line
+        "#,
+        import_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(ImportReport {
+        imported: import.imported_rows,
+        rejected,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportMode, ImportReport, RejectedRow};
+    use claims::assert_err;
+
+    #[test]
+    fn unknown_import_modes_are_rejected() {
+        assert_eq!(ImportMode::parse("confirmed"), Ok(ImportMode::Confirmed));
+        assert_err!(ImportMode::parse("pending"));
+    }
+
+    #[test]
+    fn rejected_rows_are_quoted_in_the_report() {
+        let report = ImportReport {
+            imported: 0,
+            rejected: vec![RejectedRow {
+                line: 2,
+                email: "a\"b@example.com".into(),
+                reason: "Invalid, really".into(),
+            }],
+        };
+        assert_eq!(
+            report.rejected_rows_csv(),
+            "line,email,reason\n2,\"a\"\"b@example.com\",\"Invalid, really\"\n"
+        );
+    }
+}
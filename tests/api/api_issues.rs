@@ -0,0 +1,330 @@
+use crate::api_tokens::{api_request, create_token, error_message};
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber_with, when_sending_an_email};
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+fn draft() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+    })
+}
+
+async fn post_issue(
+    app: &TestApp,
+    token: &str,
+    path: &str,
+    body: &serde_json::Value,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    api_request(app, Method::POST, path, Some(token))
+        .header("Idempotency-Key", idempotency_key)
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Create a draft and return its id.
+async fn create_draft(app: &TestApp, token: &str) -> String {
+    let key = Uuid::new_v4().to_string();
+    let response = post_issue(app, token, "/issues", &draft(), &key).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["id"].as_str().unwrap().to_string()
+}
+
+async fn publish(app: &TestApp, token: &str, issue_id: &str, key: &str) -> reqwest::Response {
+    let path = format!("/issues/{issue_id}/publish");
+    post_issue(app, token, &path, &serde_json::json!({}), key).await
+}
+
+async fn get_issue(app: &TestApp, token: &str, issue_id: &str) -> serde_json::Value {
+    let response = api_request(
+        app,
+        Method::GET,
+        &format!("/issues/{issue_id}"),
+        Some(token),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+async fn logged_in_app_with_token() -> (TestApp, String) {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["issues:publish"]).await;
+    (app, token)
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_updated_and_fetched() {
+    let (app, token) = logged_in_app_with_token().await;
+    let mut body = draft();
+    body["variants"] = serde_json::json!({
+        "fr": { "title": "Titre", "html_content": "<p>Corps</p>", "text_content": "Corps" }
+    });
+
+    let response = post_issue(&app, &token, "/issues", &body, "create-draft").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["id"].as_str().unwrap();
+    assert_eq!(issue["status"], "draft");
+    assert!(issue["delivery"].is_null());
+    assert_eq!(issue["variants"]["fr"]["title"], "Titre");
+
+    let response = api_request(
+        &app,
+        Method::PATCH,
+        &format!("/issues/{issue_id}"),
+        Some(&token),
+    )
+    .json(&serde_json::json!({ "title": "A better title" }))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let issue = get_issue(&app, &token, issue_id).await;
+    assert_eq!(issue["title"], "A better title");
+    assert_eq!(issue["text_content"], "Newsletter body as plain text");
+    assert_eq!(issue["variants"]["fr"]["text_content"], "Corps");
+
+    let response = api_request(&app, Method::GET, "/issues", Some(&token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["issues"][0]["id"], issue_id);
+    assert_eq!(body["issues"][0]["status"], "draft");
+    assert!(body["next_page"].is_null());
+}
+
+#[tokio::test]
+async fn creating_an_issue_requires_an_idempotency_key() {
+    let (app, token) = logged_in_app_with_token().await;
+
+    let response = api_request(&app, Method::POST, "/issues", Some(&token))
+        .json(&draft())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        error_message(&body),
+        "An Idempotency-Key header is required."
+    );
+}
+
+#[tokio::test]
+async fn creating_an_issue_is_idempotent() {
+    let (app, token) = logged_in_app_with_token().await;
+
+    let first = post_issue(&app, &token, "/issues", &draft(), "same-key").await;
+    let second = post_issue(&app, &token, "/issues", &draft(), "same-key").await;
+
+    assert_eq!(second.status().as_u16(), 201);
+    let first: serde_json::Value = first.json().await.unwrap();
+    let second: serde_json::Value = second.json().await.unwrap();
+    assert_eq!(first["id"], second["id"]);
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_another_request_is_a_422() {
+    let (app, token) = logged_in_app_with_token().await;
+    let issue_id = create_draft(&app, &token).await;
+    let response = post_issue(&app, &token, "/issues", &draft(), "same-key").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let mut other_draft = draft();
+    other_draft["title"] = "Another title".into();
+    let response = post_issue(&app, &token, "/issues", &other_draft, "same-key").await;
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        error_message(&body),
+        "The Idempotency-Key was already used for another request."
+    );
+
+    // Nor can a key be replayed against another endpoint.
+    let response = publish(&app, &token, &issue_id, "same-key").await;
+    assert_eq!(response.status().as_u16(), 422);
+    let issue = get_issue(&app, &token, &issue_id).await;
+    assert_eq!(issue["status"], "draft");
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_and_reports_its_progress() {
+    let (app, token) = logged_in_app_with_token().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": "tay@gmail.com" })).await;
+    let issue_id = create_draft(&app, &token).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = publish(&app, &token, &issue_id, "publish").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "published");
+    assert_eq!(
+        issue["delivery"],
+        serde_json::json!({ "recipients": 1, "pending": 1, "sent": 0, "failed": 0 })
+    );
+
+    app.dispatch_all_pending_workers().await;
+    let issue = get_issue(&app, &token, &issue_id).await;
+    assert_eq!(
+        issue["delivery"],
+        serde_json::json!({ "recipients": 1, "pending": 0, "sent": 1, "failed": 0 })
+    );
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_not_reported_as_sent() {
+    let (app, token) = logged_in_app_with_token().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": "tay@gmail.com" })).await;
+    let issue_id = create_draft(&app, &token).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish(&app, &token, &issue_id, "publish").await;
+    app.dispatch_all_pending_workers().await;
+
+    let issue = get_issue(&app, &token, &issue_id).await;
+    assert_eq!(
+        issue["delivery"],
+        serde_json::json!({ "recipients": 1, "pending": 0, "sent": 0, "failed": 1 })
+    );
+}
+
+#[tokio::test]
+async fn published_issues_cannot_be_changed_or_published_again() {
+    let (app, token) = logged_in_app_with_token().await;
+    let issue_id = create_draft(&app, &token).await;
+    let response = publish(&app, &token, &issue_id, "publish").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let published: serde_json::Value = response.json().await.unwrap();
+
+    // Retrying with the same key gets the saved response back.
+    let response = publish(&app, &token, &issue_id, "publish").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, published);
+
+    let response = publish(&app, &token, &issue_id, "publish-again").await;
+    assert_eq!(response.status().as_u16(), 409);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        error_message(&body),
+        "This issue has already been published."
+    );
+
+    let response = api_request(
+        &app,
+        Method::PATCH,
+        &format!("/issues/{issue_id}"),
+        Some(&token),
+    )
+    .json(&serde_json::json!({ "title": "Too late" }))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn invalid_issues_are_rejected_with_json_errors() {
+    let (app, token) = logged_in_app_with_token().await;
+    let issue_id = create_draft(&app, &token).await;
+    let test_cases = vec![
+        (
+            "/issues".to_string(),
+            serde_json::json!({ "title": "", "html_content": "a", "text_content": "a" }),
+            400,
+            "The title cannot be empty.",
+        ),
+        (
+            "/issues".to_string(),
+            serde_json::json!({
+                "title": "a", "html_content": "a", "text_content": "a",
+                "variants": { "en": { "title": "a", "html_content": "a", "text_content": "a" } }
+            }),
+            400,
+            "en is the language of the issue itself, not a variant.",
+        ),
+        (
+            format!("/issues/{issue_id}/publish"),
+            serde_json::json!({ "lists": ["nope"] }),
+            400,
+            "nope is not a known mailing list.",
+        ),
+        (
+            format!("/issues/{}/publish", Uuid::new_v4()),
+            serde_json::json!({}),
+            404,
+            "",
+        ),
+    ];
+
+    for (path, body, status, message) in test_cases {
+        let key = Uuid::new_v4().to_string();
+        let response = post_issue(&app, &token, &path, &body, &key).await;
+        assert_eq!(response.status().as_u16(), status, "{path}: {body}");
+        let body: serde_json::Value = response.json().await.unwrap();
+        if !message.is_empty() {
+            assert_eq!(error_message(&body), message);
+        }
+    }
+    let issue = get_issue(&app, &token, &issue_id).await;
+    assert_eq!(issue["status"], "draft");
+}
+
+#[tokio::test]
+async fn issues_require_the_issues_publish_scope() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["subscribers:read"]).await;
+
+    let response = api_request(&app, Method::GET, "/issues", Some(&token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+    let response = post_issue(&app, &token, "/issues", &draft(), "key").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn issues_published_from_the_admin_form_report_their_recipients() {
+    let (app, token) = logged_in_app_with_token().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": "tay@gmail.com" })).await;
+    let mut form = draft();
+    form["idempotency_key"] = Uuid::new_v4().to_string().into();
+    app.post_newsletters(&form).await;
+
+    let response = api_request(&app, Method::GET, "/issues", Some(&token))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["issues"][0]["status"], "published");
+    assert_eq!(body["issues"][0]["delivery"]["recipients"], 1);
+}
@@ -0,0 +1,127 @@
+use crate::helpers::spawn_app;
+use crate::newsletter::when_sending_an_email;
+use wiremock::ResponseTemplate;
+
+#[tokio::test]
+async fn json_subscriptions_are_stored_and_confirmed_by_email() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "tay",
+            "email": "tay@gmail.com",
+            "tags": "mobile",
+            "source": "ios-app",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, serde_json::json!({"status": "pending_confirmation"}));
+    let saved = sqlx::query!("SELECT email, tags FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "tay@gmail.com");
+    assert_eq!(saved.tags, vec!["mobile"]);
+    app.dispatch_all_pending_confirmations().await;
+}
+
+#[tokio::test]
+async fn every_invalid_field_is_reported() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "",
+            "email": "not-an-email",
+            "list": "Not A Slug",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<&str> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["list", "name", "email"]);
+    assert_eq!(
+        body["errors"][2]["message"],
+        "not-an-email is not a valid subscriber email."
+    );
+}
+
+#[tokio::test]
+async fn malformed_json_gets_a_json_error() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({"name": "tay"}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["errors"][0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("missing field `email`"));
+}
+
+#[tokio::test]
+async fn rate_limited_json_requests_get_a_json_error() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({"name": "tay", "email": "tay@gmail.com"});
+
+    for _ in 0..3 {
+        app.post_api_subscriptions(&body).await;
+    }
+    let response = app.post_api_subscriptions(&body).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["errors"][0]["message"],
+        "Too many subscription requests, please try again later."
+    );
+}
+
+#[tokio::test]
+async fn configured_origins_may_call_the_api_from_a_browser() {
+    let app = spawn_app().await;
+    let preflight = |origin: &'static str| {
+        app.api_client
+            .request(
+                reqwest::Method::OPTIONS,
+                format!("{}/api/v1/subscriptions", &app.address),
+            )
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .send()
+    };
+
+    let response = preflight("http://localhost:3000").await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "http://localhost:3000"
+    );
+
+    let response = preflight("https://evil.example").await.unwrap();
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
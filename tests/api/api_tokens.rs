@@ -0,0 +1,274 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Create a token for the logged-in test user and return it.
+pub async fn create_token(app: &TestApp, scopes: &[&str]) -> String {
+    let mut form = serde_json::json!({ "name": "CI", "expires_in_days": "30" });
+    for scope in scopes {
+        form[format!("scope_{scope}")] = "on".into();
+    }
+    let response = app.post_form("/admin/api-tokens", &form).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    html_page
+        .split(r#"<code id="api-token">"#)
+        .nth(1)
+        .and_then(|s| s.split("</code>").next())
+        .unwrap()
+        .to_string()
+}
+
+pub fn api_request(
+    app: &TestApp,
+    method: reqwest::Method,
+    path: &str,
+    token: Option<&str>,
+) -> reqwest::RequestBuilder {
+    let request = reqwest::Client::new().request(method, format!("{}/api/v1{path}", app.address));
+    match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+async fn get_subscribers(app: &TestApp, token: Option<&str>) -> reqwest::Response {
+    api_request(app, reqwest::Method::GET, "/subscribers", token)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn create_subscriber(app: &TestApp) -> Uuid {
+    app.post_subscriptions("name=tay&email=tay%40gmail.com&tags=rust".into())
+        .await;
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+pub fn error_message(body: &serde_json::Value) -> &str {
+    body["errors"][0]["message"].as_str().unwrap()
+}
+
+#[tokio::test]
+async fn a_new_token_is_shown_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let token = create_token(&app, &["subscribers:read"]).await;
+
+    assert_eq!(token.len(), 40);
+    let html_page = app.get_url("/admin/api-tokens").await.text().await.unwrap();
+    assert!(html_page.contains("<td>CI</td><td>subscribers:read</td>"));
+    assert!(!html_page.contains(&token));
+    let stored = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = get_subscribers(&app, None).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error_message(&body), "A bearer token is required.");
+
+    let response = get_subscribers(&app, Some("not-a-token")).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_read_token_lists_subscribers_and_records_its_use() {
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["subscribers:read"]).await;
+
+    let response = get_subscribers(&app, Some(&token)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["subscribers"][0]["id"], subscriber_id.to_string());
+    assert_eq!(body["subscribers"][0]["email"], "tay@gmail.com");
+    assert_eq!(body["subscribers"][0]["tags"], serde_json::json!(["rust"]));
+    assert!(body["next_page"].is_null());
+
+    let last_used_at = sqlx::query!("SELECT last_used_at FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .last_used_at;
+    assert!(last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn tokens_can_only_be_used_within_their_scopes() {
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["issues:publish"]).await;
+
+    let response = get_subscribers(&app, Some(&token)).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        error_message(&body),
+        "This token does not have the subscribers:read scope."
+    );
+
+    let response = api_request(
+        &app,
+        reqwest::Method::DELETE,
+        &format!("/subscribers/{subscriber_id}"),
+        Some(&token),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_write_token_updates_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["subscribers:write"]).await;
+    let path = format!("/subscribers/{subscriber_id}");
+
+    let response = api_request(&app, reqwest::Method::PATCH, &path, Some(&token))
+        .json(&serde_json::json!({ "tags": ["go", "beta"], "attributes": { "talks": 2 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["tags"], serde_json::json!(["go", "beta"]));
+    assert_eq!(body["attributes"], serde_json::json!({ "talks": 2 }));
+
+    let response = api_request(&app, reqwest::Method::PATCH, &path, Some(&token))
+        .json(&serde_json::json!({ "tags": ["not a tag!"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = api_request(
+        &app,
+        reqwest::Method::PATCH,
+        &format!("/subscribers/{}", Uuid::new_v4()),
+        Some(&token),
+    )
+    .json(&serde_json::json!({ "tags": [] }))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_write_token_erases_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["subscribers:write"]).await;
+
+    let response = api_request(
+        &app,
+        reqwest::Method::DELETE,
+        &format!("/subscribers/{subscriber_id}"),
+        Some(&token),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let remaining = sqlx::query!("SELECT id FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(remaining.is_none());
+}
+
+#[tokio::test]
+async fn revoked_and_expired_tokens_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let revoked = create_token(&app, &["subscribers:read"]).await;
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+
+    let response = app
+        .post_form(
+            "/admin/api-tokens/revoke",
+            &serde_json::json!({ "api_token_id": api_token_id }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let html_page = app.get_url("/admin/api-tokens").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The token has been revoked.</i></p>"));
+    let response = get_subscribers(&app, Some(&revoked)).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let expired = create_token(&app, &["subscribers:read"]).await;
+    sqlx::query!("UPDATE api_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = get_subscribers(&app, Some(&expired)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_cannot_be_used_beyond_the_role_of_their_user() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["subscribers:read"]).await;
+    sqlx::query!(
+        "UPDATE users SET role = 'analyst' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = get_subscribers(&app, Some(&token)).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app
+        .post_form(
+            "/admin/api-tokens",
+            &serde_json::json!({ "name": "CI", "scope_subscribers:read": "on" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let html_page = app.get_url("/admin/api-tokens").await.text().await.unwrap();
+    assert!(html_page
+        .contains("<p><i>Your role (analyst) does not allow the subscribers:read scope.</i></p>"));
+}
+
+#[tokio::test]
+async fn the_tokens_of_disabled_users_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["subscribers:read"]).await;
+    sqlx::query!(
+        "UPDATE users SET status = 'disabled' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = get_subscribers(&app, Some(&token)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
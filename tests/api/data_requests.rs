@@ -0,0 +1,282 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use crate::newsletter::{create_confirmed_subscriber_with, when_sending_an_email};
+use wiremock::ResponseTemplate;
+
+const EMAIL: &str = "ursula@example.com";
+
+/// Ask for a data request link and return the one we received by email.
+async fn request_data_link(app: &TestApp) -> reqwest::Url {
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form("/data-requests", &serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/data-requests");
+    app.dispatch_all_pending_data_requests().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+fn token_of(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn is_suppressed(app: &TestApp, email: &str) -> bool {
+    zero2prod::personal_data::is_suppressed(&app.db_pool, email)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unknown_addresses_are_not_sent_a_link() {
+    let app = spawn_app().await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form("/data-requests", &serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/data-requests");
+    app.dispatch_all_pending_data_requests().await;
+
+    let html_page = app.get_url("/data-requests").await.text().await.unwrap();
+    assert!(html_page.contains("If we hold data about this address"));
+}
+
+#[tokio::test]
+async fn data_request_emails_are_sent_in_the_background_and_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // The email service is not called while answering the request.
+    let response = app
+        .post_form("/data-requests", &serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/data-requests");
+    app.dispatch_all_pending_data_requests().await;
+
+    let outbox = sqlx::query!("SELECT n_retries FROM data_request_email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(outbox.n_retries, 1);
+    let tokens = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM data_request_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.n, 0);
+}
+
+#[tokio::test]
+async fn data_requests_for_an_address_are_rate_limited_silently() {
+    let app = spawn_app_with(|c| c.subscription_protection.max_data_requests_per_email = 1).await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_form("/data-requests", &serde_json::json!({ "email": EMAIL }))
+            .await;
+        assert_is_redirect_to(&response, "/data-requests");
+    }
+
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM data_request_email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.n, 1);
+}
+
+#[tokio::test]
+async fn data_requests_from_an_address_are_rate_limited_silently() {
+    let app = spawn_app_with(|c| c.subscription_protection.max_data_requests_per_ip = 1).await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": "other@example.com" }))
+        .await;
+
+    for email in [EMAIL, "other@example.com"] {
+        let response = app
+            .post_form("/data-requests", &serde_json::json!({ "email": email }))
+            .await;
+        assert_is_redirect_to(&response, "/data-requests");
+    }
+
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM data_request_email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.n, 1);
+}
+
+#[tokio::test]
+async fn subscribers_can_download_an_archive_of_their_data() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({"email": EMAIL, "tags": "rust"}))
+        .await;
+    let link = request_data_link(&app).await;
+
+    let html_page = reqwest::get(link.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(EMAIL));
+
+    let token = token_of(&link);
+    let response = app
+        .get_url(&format!("/data-requests/archive?token={token}"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let archive: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(archive["subscription"]["email"], EMAIL);
+    assert_eq!(archive["subscription"]["status"], "confirmed");
+    assert_eq!(archive["subscription"]["tags"], serde_json::json!(["rust"]));
+    assert_eq!(archive["list_memberships"][0]["list"], "newsletter");
+    assert_eq!(archive["subscription_tokens"][0]["expired"], true);
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    let token = token_of(&request_data_link(&app).await);
+
+    let response = app
+        .post_form(
+            "/data-requests/erase",
+            &serde_json::json!({ "token": token }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/data-requests");
+
+    let html_page = app.get_url("/data-requests").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Your personal data has been erased.</i></p>"));
+    for table in ["subscriptions", "subscription_tokens", "list_memberships"] {
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{table} was not erased");
+    }
+    assert!(is_suppressed(&app, EMAIL).await);
+
+    // The link is single use
+    let response = app
+        .get_url(&format!("/data-requests/archive?token={token}"))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_links_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    let token = token_of(&request_data_link(&app).await);
+    sqlx::query!("UPDATE data_request_tokens SET created_at = now() - interval '25 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .get_url(&format!("/data-requests/confirm?token={token}"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_can_download_and_erase_the_data_of_a_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .get_url(&format!("/admin/subscribers/{subscriber_id}/archive"))
+        .await;
+    let archive: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(archive["subscription"]["id"], subscriber_id.to_string());
+
+    let response = app
+        .post_form(
+            &format!("/admin/subscribers/{subscriber_id}/erase"),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html().await;
+    assert!(html_page.contains("The personal data of the subscriber has been erased."));
+    assert!(!html_page.contains(EMAIL));
+    assert!(is_suppressed(&app, EMAIL).await);
+
+    // Erased addresses cannot be imported again
+    let response = app
+        .post_import(
+            "newsletter",
+            "confirmed",
+            &format!("name,email\nUrsula,{EMAIL}\n"),
+        )
+        .await;
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let report = app.get_url(&format!("{location}/rejected.csv")).await;
+    assert!(report
+        .text()
+        .await
+        .unwrap()
+        .contains("The email was erased at its owner's request."));
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_subscribe_again() {
+    let app = spawn_app().await;
+    zero2prod::personal_data::erase_personal_data(&app.db_pool, "u.rsula@gmail.com")
+        .await
+        .unwrap();
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Another spelling of the same Gmail mailbox, answered like any signup.
+    let response = app
+        .post_subscriptions("name=Ursula&email=Ursula%2Bnews%40googlemail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.dispatch_all_pending_confirmations().await;
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,133 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::newsletter::when_sending_an_email;
+use wiremock::ResponseTemplate;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_email_domains() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/admin/email-domains").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_form(
+            "/admin/email-domains",
+            &serde_json::json!({"domain": "example.com", "rule": "blocked"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribing_with_a_disposable_address_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=tay&email=tay%40eu.Mailinator.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "eu.mailinator.com provides disposable email addresses, please use a permanent one."
+    );
+}
+
+#[tokio::test]
+async fn blocked_and_allowed_domains_take_effect_without_a_restart() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_form(
+            "/admin/email-domains",
+            &serde_json::json!({"domain": "Spam.Example", "rule": "blocked"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/email-domains");
+    let response = app
+        .post_form(
+            "/admin/email-domains",
+            &serde_json::json!({"domain": "mailinator.com", "rule": "allowed"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/email-domains");
+
+    let html_page = app
+        .get_url("/admin/email-domains")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>mailinator.com is now allowed.</i></p>"));
+    assert!(html_page.contains("<td>spam.example</td><td>blocked</td>"));
+
+    let response = app
+        .post_subscriptions("name=tay&email=tay%40spam.example".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Subscriptions from spam.example are not accepted."
+    );
+    let response = app
+        .post_subscriptions("name=tay&email=tay%40mailinator.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.post_form(
+        "/admin/email-domains/delete",
+        &serde_json::json!({"domain": "spam.example"}),
+    )
+    .await;
+    let response = app
+        .post_subscriptions("name=tay&email=tay%40spam.example".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn invalid_domains_are_not_saved() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_form(
+        "/admin/email-domains",
+        &serde_json::json!({"domain": "user@example.com", "rule": "blocked"}),
+    )
+    .await;
+
+    let html_page = app
+        .get_url("/admin/email-domains")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>user@example.com is not a valid domain.</i></p>"));
+    let saved = sqlx::query!("SELECT domain FROM email_domain_rules")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn the_domain_lists_can_be_reloaded() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_form("/admin/email-domains/reload", &()).await;
+    assert_is_redirect_to(&response, "/admin/email-domains");
+
+    let html_page = app
+        .get_url("/admin/email-domains")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The domain lists have been reloaded"));
+}
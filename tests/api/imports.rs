@@ -0,0 +1,229 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber_with, when_sending_an_email};
+use wiremock::ResponseTemplate;
+
+/// Follow the redirect of a successful import to its report.
+async fn get_import_report(app: &TestApp, response: &reqwest::Response) -> (String, String) {
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    assert!(location.starts_with("/admin/imports/"));
+    let report_html = app.get_url(&location).await.text().await.unwrap();
+    (location, report_html)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_import("newsletter", "confirmed", "name,email\n")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn confirmed_imports_store_valid_rows_and_report_the_others() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({"email": "known@example.com"})).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "name,email\n\
+        Ada Lovelace,ada@example.com\n\
+        Grace Hopper,not-an-email\n\
+        ,nameless@example.com\n\
+        Ada Again,ADA@Example.com\n\
+        Known Person,Known@EXAMPLE.com\n";
+    let response = app.post_import("newsletter", "confirmed", csv).await;
+
+    let (location, report_html) = get_import_report(&app, &response).await;
+    assert!(report_html.contains("<p>1 subscriber(s) imported, 4 row(s) rejected.</p>"));
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.name, s.status, m.status AS membership_status
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.email = 'ada@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the imported subscriber.");
+    assert_eq!(saved.name, "Ada Lovelace");
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(saved.membership_status, "confirmed");
+
+    let response = app.get_url(&format!("{location}/rejected.csv")).await;
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let report = response.text().await.unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "line,email,reason");
+    assert!(lines[1].starts_with("3,\"not-an-email\","));
+    assert!(lines[2].starts_with("4,\"nameless@example.com\","));
+    assert_eq!(
+        lines[3],
+        "5,\"ADA@Example.com\",\"The email appears earlier in the file.\""
+    );
+    assert_eq!(
+        lines[4],
+        "6,\"Known@EXAMPLE.com\",\"The email is already subscribed.\""
+    );
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name,company\n\
+        ada@example.com,Ada Lovelace,Analytical Engines\n\
+        grace@example.com,Grace Hopper,US Navy\n";
+    let response = app
+        .post_import("newsletter", "send_confirmation", csv)
+        .await;
+
+    let (_, report_html) = get_import_report(&app, &response).await;
+    assert!(report_html.contains("<p>2 subscriber(s) imported, 0 row(s) rejected.</p>"));
+    let statuses = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses.iter().all(|s| s.status == "pending_confirmation"));
+
+    // The imported subscribers confirm like anyone else.
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn tags_and_attributes_are_imported_and_invalid_ones_reported() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let csv = "email,name,tags,attr_city,attr_age\n\
+        ada@example.com,Ada Lovelace,\"rust, Beta\",London,36\n\
+        grace@example.com,Grace Hopper,,,\n\
+        bad-tag@example.com,Bad Tag,not a tag!,,\n";
+    let response = app.post_import("newsletter", "confirmed", csv).await;
+
+    let (location, report_html) = get_import_report(&app, &response).await;
+    assert!(report_html.contains("<p>2 subscriber(s) imported, 1 row(s) rejected.</p>"));
+    let saved = sqlx::query!("SELECT email, tags, attributes FROM subscriptions ORDER BY email",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "ada@example.com");
+    assert_eq!(saved[0].tags, vec!["rust", "beta"]);
+    assert_eq!(
+        saved[0].attributes,
+        serde_json::json!({"city": "London", "age": 36})
+    );
+    assert_eq!(saved[1].email, "grace@example.com");
+    assert!(saved[1].tags.is_empty());
+    assert_eq!(saved[1].attributes, serde_json::json!({}));
+
+    let report = app
+        .get_url(&format!("{location}/rejected.csv"))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(report
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with("4,\"bad-tag@example.com\","));
+}
+
+#[tokio::test]
+async fn a_csv_without_an_email_column_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_import(
+            "newsletter",
+            "confirmed",
+            "name,address\nAda,ada@example.com\n",
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/imports");
+
+    let html_page = app.get_imports_html().await;
+    assert!(html_page.contains("<p><i>The CSV has no `email` column.</i></p>"));
+}
+
+#[tokio::test]
+async fn importing_into_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_import("does-not-exist", "confirmed", "name,email\n")
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn uploads_sending_the_csv_before_the_list_and_mode_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let boundary = "csv-first";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"csv\"; filename=\"subscribers.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\nname,email\nAda Lovelace,ada@example.com\n\r\n"
+    );
+    for (name, value) in [("list", "newsletter"), ("mode", "confirmed")] {
+        body.push_str(&format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+        ));
+    }
+    body.push_str(&format!("--{boundary}--\r\n"));
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/imports", &app.address))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
@@ -0,0 +1,128 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_lists_page() {
+    let app = spawn_app().await;
+
+    let response = app.get_lists().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_a_list() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_default_list_is_always_available() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_lists_html().await;
+
+    assert!(html_page.contains("<td>newsletter</td>"));
+}
+
+#[tokio::test]
+async fn a_created_list_is_shown_on_the_lists_page() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_lists(&serde_json::json!({"slug": "rust-weekly", "name": "Rust weekly"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The rust-weekly list has been created.</i></p>"));
+    assert!(html_page.contains("<td>rust-weekly</td><td>Rust weekly</td><td>0</td>"));
+}
+
+#[tokio::test]
+async fn list_identifiers_must_be_unique() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_lists(&serde_json::json!({"slug": "newsletter", "name": "Another newsletter"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>A list identified by newsletter already exists.</i></p>"));
+}
+
+#[tokio::test]
+async fn invalid_list_identifiers_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_lists(&serde_json::json!({"slug": "Rust Weekly", "name": "Rust weekly"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("Rust Weekly is not a valid mailing list identifier."));
+}
+
+#[tokio::test]
+async fn redirect_urls_can_be_set_and_cleared() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let set_redirects = |subscribed: &str, confirmed: &str| {
+        serde_json::json!({
+            "slug": "newsletter",
+            "subscribed_redirect_url": subscribed,
+            "confirmed_redirect_url": confirmed,
+        })
+    };
+
+    let response = app
+        .post_form(
+            "/admin/lists/redirects",
+            &set_redirects("https://example.com/thanks", "https://example.com/welcome"),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("The redirects of the newsletter list have been updated."));
+    assert!(html_page.contains(r#"value="https://example.com/welcome""#));
+
+    app.post_form("/admin/lists/redirects", &set_redirects("", ""))
+        .await;
+    let list = sqlx::query!(
+        "SELECT subscribed_redirect_url, confirmed_redirect_url FROM lists WHERE slug = 'newsletter'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(list.subscribed_redirect_url.is_none());
+    assert!(list.confirmed_redirect_url.is_none());
+}
+
+#[tokio::test]
+async fn redirect_urls_must_be_absolute_web_urls() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_form(
+        "/admin/lists/redirects",
+        &serde_json::json!({
+            "slug": "newsletter",
+            "subscribed_redirect_url": "javascript:alert(1)",
+            "confirmed_redirect_url": "",
+        }),
+    )
+    .await;
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("javascript:alert(1) is not a valid http(s) URL."));
+}
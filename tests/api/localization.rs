@@ -0,0 +1,279 @@
+use crate::helpers::{assert_is_redirect_to, signed_form_started_at, spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber_with, when_sending_an_email};
+use wiremock::ResponseTemplate;
+
+async fn post_subscriptions_in(app: &TestApp, body: &str, accept_language: &str) -> u16 {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", accept_language)
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .status()
+        .as_u16()
+}
+
+async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+async fn get_html_in(app: &TestApp, path: &str, accept_language: &str) -> String {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .header("Accept-Language", accept_language)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_confirmation_email_follows_the_language_of_the_browser() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let status = post_subscriptions_in(&app, body, "de-DE, fr-CA;q=0.9, en;q=0.8").await;
+    assert_eq!(status, 200);
+    app.dispatch_all_pending_confirmations().await;
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "fr");
+    let email = &sent_emails(&app).await[0];
+    assert_eq!(email["Subject"], "Bienvenue !");
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("pour confirmer votre inscription"));
+}
+
+#[tokio::test]
+async fn a_submitted_locale_takes_precedence_over_the_browser() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=en";
+    let status = post_subscriptions_in(&app, body, "fr").await;
+    assert_eq!(status, 200);
+    app.dispatch_all_pending_confirmations().await;
+
+    assert_eq!(sent_emails(&app).await[0]["Subject"], "Welcome!");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_an_unsupported_locale() {
+    let app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=xx";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn issues_are_delivered_in_the_language_of_each_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(
+        &app,
+        serde_json::json!({"email": "camille@example.com", "locale": "fr"}),
+    )
+    .await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({"email": "sam@example.com"})).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "title_fr": "Titre de la newsletter",
+            "text_content_fr": "Contenu de la newsletter",
+            "html_content_fr": "<p>Contenu de la newsletter</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_workers().await;
+
+    let emails = sent_emails(&app).await;
+    let sent_to = |address: &str| {
+        emails
+            .iter()
+            .rev()
+            .find(|e| e["To"] == address)
+            .unwrap()
+            .clone()
+    };
+    let french = sent_to("camille@example.com");
+    assert_eq!(french["Subject"], "Titre de la newsletter");
+    assert!(french["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Gérer vos préférences d&#x27;abonnement"));
+    let english = sent_to("sam@example.com");
+    assert_eq!(english["Subject"], "Newsletter title");
+    assert!(english["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Manage your subscription preferences"));
+}
+
+#[tokio::test]
+async fn subscribers_without_a_variant_receive_the_default_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with(
+        &app,
+        serde_json::json!({"email": "camille@example.com", "locale": "fr"}),
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_workers().await;
+
+    assert_eq!(
+        sent_emails(&app).await.last().unwrap()["Subject"],
+        "Newsletter title"
+    );
+}
+
+#[tokio::test]
+async fn an_incomplete_variant_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "title_fr": "Titre de la newsletter",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn the_hosted_page_is_shown_in_the_language_of_the_browser() {
+    let app = spawn_app().await;
+
+    let html_page = get_html_in(&app, "/subscribe", "fr").await;
+    assert!(html_page.contains(r#"<html lang="fr">"#));
+    assert!(html_page.contains("Nom"));
+    assert!(html_page.contains(r#"name="locale" value="fr""#));
+
+    // The page can also be linked to in a given language.
+    let html_page = get_html_in(&app, "/subscribe?locale=en", "fr").await;
+    assert!(html_page.contains(r#"<html lang="en">"#));
+}
+
+#[tokio::test]
+async fn the_hosted_page_thanks_the_visitor_in_their_language() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/subscribe",
+            &serde_json::json!({
+                "name": "le guin",
+                "email": "ursula_le_guin@gmail.com",
+                "locale": "fr",
+                "form_started_at": signed_form_started_at(60),
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/subscribe?list=newsletter&locale=fr");
+
+    let html_page = app
+        .get_url("/subscribe?list=newsletter&locale=fr")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Merci de votre inscription !"));
+}
+
+#[tokio::test]
+async fn the_confirmation_page_is_shown_in_the_language_of_the_subscriber() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr";
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let html_page = reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains("Inscription confirmée"));
+}
+
+#[tokio::test]
+async fn an_invalid_confirmation_link_is_explained_in_the_language_of_the_browser() {
+    let app = spawn_app().await;
+
+    let html_page = get_html_in(
+        &app,
+        "/subscriptions/confirm?subscription_token=unknown",
+        "fr",
+    )
+    .await;
+
+    assert!(html_page.contains("Lien invalide"));
+}
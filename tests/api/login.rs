@@ -1,4 +1,5 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use zero2prod::configuration::CookieSameSite;
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -32,3 +33,120 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+/// The `Set-Cookie` header of the session cookie.
+fn session_cookie(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|h| h.to_str().unwrap().to_string())
+        .find(|c| c.starts_with("id="))
+        .expect("No session cookie was set")
+}
+
+async fn login_remembered(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+        "remember_me": "on",
+    }))
+    .await
+}
+
+async fn age_session(app: &TestApp, column: &str, minutes: i32) {
+    sqlx::query(&format!(
+        "UPDATE user_sessions SET {column} = {column} - make_interval(mins => $1)"
+    ))
+    .bind(minutes)
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_login_form_has_a_remember_me_checkbox() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<input type="checkbox" name="remember_me">"#));
+}
+
+#[tokio::test]
+async fn the_session_cookie_lasts_as_long_as_the_browser_by_default() {
+    let app = spawn_app().await;
+
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let cookie = session_cookie(&response);
+    assert!(!cookie.contains("Max-Age"));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("SameSite=Lax"));
+    assert!(!cookie.contains("Secure"));
+}
+
+#[tokio::test]
+async fn remembered_users_get_a_long_lived_session_cookie() {
+    let app = spawn_app().await;
+
+    let response = login_remembered(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let cookie = session_cookie(&response);
+    // 30 days, the default remember me lifetime.
+    assert!(cookie.contains("Max-Age=2592000"));
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_session_cookie_attributes_are_configurable() {
+    let app = spawn_app_with(|c| {
+        c.session.cookie_secure = true;
+        c.session.cookie_same_site = CookieSameSite::Strict;
+    })
+    .await;
+
+    let response = app.test_user.login(&app).await;
+    let cookie = session_cookie(&response);
+    assert!(cookie.contains("Secure"));
+    assert!(cookie.contains("SameSite=Strict"));
+}
+
+#[tokio::test]
+async fn idle_sessions_are_logged_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    age_session(&app, "last_seen_at", 29).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    age_session(&app, "last_seen_at", 31).await;
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_end_after_their_absolute_lifetime() {
+    let app = spawn_app_with(|c| c.session.absolute_lifetime_minutes = 60).await;
+    app.test_user.login(&app).await;
+
+    // Still active, but logged in longer ago than the lifetime.
+    age_session(&app, "expires_at", 61).await;
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn remembered_sessions_do_not_time_out_when_idle() {
+    let app = spawn_app().await;
+    login_remembered(&app).await;
+
+    age_session(&app, "last_seen_at", 24 * 60).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    age_session(&app, "expires_at", 30 * 24 * 60).await;
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,147 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+
+const LOCKED_OUT: &str = "Too many failed login attempts, please try again later.";
+
+/// Lock usernames out after 3 failures, addresses after 5, without delays.
+async fn spawn_throttled_app() -> TestApp {
+    spawn_app_with(|c| {
+        c.authentication.max_failed_logins_per_username = 3;
+        c.authentication.max_failed_logins_per_ip = 5;
+        c.authentication.failed_login_delay_milliseconds = 0;
+    })
+    .await
+}
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": "not-the-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+async fn login(app: &TestApp) -> reqwest::Response {
+    let response = app.test_user.login(app).await;
+    // Consume the flash message.
+    app.get_login_html().await;
+    response
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    let app = spawn_throttled_app().await;
+    for _ in 0..3 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    let response = app.test_user.login(&app).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_html().await.contains(LOCKED_OUT));
+    let lockout = sqlx::query!("SELECT username, failed_attempts FROM login_lockouts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        lockout.username,
+        Some(app.test_user.username.to_lowercase())
+    );
+    assert_eq!(lockout.failed_attempts, 3);
+}
+
+#[tokio::test]
+async fn unknown_usernames_are_locked_out_the_same_way() {
+    let app = spawn_throttled_app().await;
+    for _ in 0..3 {
+        fail_login(&app, "nobody").await;
+    }
+
+    fail_login(&app, "nobody").await;
+
+    assert!(app.get_login_html().await.contains(LOCKED_OUT));
+}
+
+#[tokio::test]
+async fn an_address_is_locked_out_after_too_many_failures() {
+    let app = spawn_throttled_app().await;
+    for i in 0..5 {
+        fail_login(&app, &format!("user-{i}")).await;
+    }
+
+    let response = login(&app).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failures_of_a_username() {
+    let app = spawn_throttled_app().await;
+    for _ in 0..2 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    assert_is_redirect_to(&login(&app).await, "/admin/dashboard");
+    app.post_logout().await;
+
+    for _ in 0..2 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    assert_is_redirect_to(&login(&app).await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn lockouts_expire() {
+    let app = spawn_throttled_app().await;
+    for _ in 0..3 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    sqlx::query!("UPDATE login_lockouts SET locked_until = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&login(&app).await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn admins_can_see_and_lift_lockouts() {
+    let app = spawn_throttled_app().await;
+    login(&app).await;
+    // Another browser gets the account locked out, the admin session predates it.
+    let attacker = reqwest::Client::new();
+    for _ in 0..3 {
+        attacker
+            .post(format!("{}/login", app.address))
+            .form(&serde_json::json!({
+                "username": app.test_user.username,
+                "password": "not-the-password"
+            }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let html_page = app.get_url("/admin/lockouts").await.text().await.unwrap();
+    assert!(html_page.contains(&app.test_user.username.to_lowercase()));
+    assert!(html_page.contains("Unlock"));
+    let lockout = sqlx::query!("SELECT lockout_id FROM login_lockouts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post_form(
+            "/admin/lockouts/unlock",
+            &serde_json::json!({ "lockout_id": lockout.lockout_id }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/lockouts");
+
+    let html_page = app.get_url("/admin/lockouts").await.text().await.unwrap();
+    assert!(html_page.contains("The lockout has been lifted."));
+    assert!(html_page.contains(&format!("Lifted by {}", app.test_user.username)));
+    app.post_logout().await;
+    assert_is_redirect_to(&login(&app).await, "/admin/dashboard");
+}
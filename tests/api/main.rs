@@ -1,9 +1,28 @@
 // pub use crate::api::{health_check, helpers, subscriptions};
 mod admin_dashboard;
+mod api_issues;
+mod api_subscriptions;
+mod api_tokens;
 mod change_password;
+mod data_requests;
+mod email_domains;
 mod health_check;
 mod helpers;
+mod imports;
+mod lists;
+mod localization;
 mod login;
+mod login_lockouts;
 mod newsletter;
+mod password_hashing;
+mod password_reset;
+mod preferences;
+mod roles;
+mod sequences;
+mod sessions;
+mod subscribe_page;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod two_factor;
+mod users;
@@ -7,7 +7,7 @@ use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, MockBuilder, ResponseTemplate};
 
-fn when_sending_an_email() -> MockBuilder {
+pub fn when_sending_an_email() -> MockBuilder {
     Mock::given(path("/email")).and(method("POST"))
 }
 
@@ -197,6 +197,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .error_for_status()
         .unwrap();
 
+    app.dispatch_all_pending_confirmations().await;
     let email_request = &app
         .email_server
         .received_requests()
@@ -286,3 +287,274 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_workers().await;
     // Mock verifies on Drop that we have sent the newsletter email **once**
 }
+
+/// Subscribe `email` to `list` and click on the confirmation link.
+async fn create_confirmed_list_member(app: &TestApp, email: &str, list: &str) {
+    create_confirmed_subscriber_with(app, serde_json::json!({"email": email, "list": list})).await;
+}
+
+/// Submit the subscription form with `fields` and click on the confirmation link.
+pub async fn create_confirmed_subscriber_with(app: &TestApp, mut fields: serde_json::Value) {
+    fields["name"] = Name().fake::<String>().into();
+    let body = serde_urlencoded::to_string(fields).unwrap();
+
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed list member")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn newsletter_is_only_delivered_to_the_targeted_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+    app.post_lists(&serde_json::json!({"slug": "go", "name": "Go"}))
+        .await;
+    create_confirmed_list_member(&app, "ferris@example.com", "rust").await;
+    create_confirmed_list_member(&app, "gopher@example.com", "go").await;
+
+    when_sending_an_email()
+        .and(RecipientMatcher("ferris@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .and(RecipientMatcher("gopher@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            "lists": "rust",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_pending_workers().await;
+    // Mock verifies on Drop that only the `rust` subscriber got the issue
+}
+
+#[tokio::test]
+async fn subscribers_on_several_targeted_lists_receive_the_issue_once() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+    create_confirmed_list_member(&app, "ferris@example.com", "rust").await;
+    create_confirmed_list_member(&app, "ferris@example.com", "newsletter").await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            "lists": "rust,newsletter",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_pending_workers().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn newsletter_returns_400_for_an_unknown_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            "lists": "does-not-exist",
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+pub struct RecipientMatcher(pub &'static str);
+
+impl wiremock::Match for RecipientMatcher {
+    fn matches(&self, request: &wiremock::Request) -> bool {
+        serde_json::from_slice::<serde_json::Value>(&request.body)
+            .map(|body| body["To"] == self.0)
+            .unwrap_or(false)
+    }
+}
+
+fn newsletter_with_segment(segment: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "lists": "newsletter",
+        "segment": segment,
+    })
+}
+
+#[tokio::test]
+async fn newsletter_is_only_delivered_to_the_matching_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber_with(
+        &app,
+        serde_json::json!({"email": "ferris@example.com", "tags": "rust"}),
+    )
+    .await;
+    create_confirmed_subscriber_with(
+        &app,
+        serde_json::json!({"email": "tester@example.com", "tags": "rust,beta"}),
+    )
+    .await;
+
+    when_sending_an_email()
+        .and(RecipientMatcher("ferris@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .and(RecipientMatcher("tester@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&newsletter_with_segment("tag:rust AND NOT tag:beta"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn segments_can_filter_on_typed_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber_with(
+        &app,
+        serde_json::json!({
+            "email": "speaker@example.com",
+            "attr_signup_source": "conference",
+            "attr_talks": "3",
+        }),
+    )
+    .await;
+    create_confirmed_subscriber_with(
+        &app,
+        serde_json::json!({
+            "email": "attendee@example.com",
+            "attr_signup_source": "conference",
+            "attr_talks": "0",
+        }),
+    )
+    .await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({"email": "reader@example.com"}))
+        .await;
+
+    let test_cases = vec![
+        (r#"signup_source = "conference""#, 2),
+        (r#"signup_source != "conference""#, 1),
+        ("talks > 0", 1),
+        ("talks >= 0 OR tag:rust", 2),
+        (r#"talks = "3""#, 0),
+    ];
+    for (segment, expected) in test_cases {
+        let mut body = newsletter_with_segment(segment);
+        body["dry_run"] = "true".into();
+        let response = app.post_newsletters(&body).await;
+        assert_eq!(200, response.status().as_u16());
+        let html_page = response.text().await.unwrap();
+        assert!(
+            html_page.contains(&format!(
+                "This issue would currently be delivered to {expected} subscriber(s)."
+            )),
+            "Segment {segment} did not match {expected} subscriber(s)."
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_dry_run_does_not_publish_the_issue() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = newsletter_with_segment("");
+    body["dry_run"] = "true".into();
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(200, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This issue would currently be delivered to 1 subscriber(s)."));
+    // The form keeps what was typed so far
+    assert!(html_page.contains(r#"value="Newsletter title""#));
+
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn newsletter_returns_400_for_an_invalid_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletters(&newsletter_with_segment("tag:rust AND"))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+}
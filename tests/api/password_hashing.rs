@@ -0,0 +1,88 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use std::time::Duration;
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+}
+
+/// Hashes are upgraded in the background, after the login response.
+async fn wait_for_new_password_hash(app: &TestApp, previous: &str) -> String {
+    for _ in 0..50 {
+        let password_hash = stored_password_hash(app).await;
+        if password_hash != previous {
+            return password_hash;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The password hash was not upgraded.");
+}
+
+#[tokio::test]
+async fn weaker_hashes_are_upgraded_after_a_successful_login() {
+    let app = spawn_app_with(|c| c.authentication.password_hashing.memory_kib = 16000).await;
+    let previous = stored_password_hash(&app).await;
+    assert!(previous.contains("m=15000,t=2,p=1"));
+
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let upgraded = wait_for_new_password_hash(&app, &previous).await;
+    assert!(upgraded.starts_with("$argon2id$v=19$m=16000,t=2,p=1$"));
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn hashes_from_a_legacy_algorithm_are_upgraded() {
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let legacy = Argon2::new(
+        Algorithm::Argon2i,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        legacy,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let upgraded = wait_for_new_password_hash(&app, &legacy).await;
+    assert!(upgraded.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+}
+
+#[tokio::test]
+async fn failed_logins_do_not_upgrade_hashes() {
+    let app = spawn_app_with(|c| c.authentication.password_hashing.memory_kib = 16000).await;
+    let previous = stored_password_hash(&app).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": "not-the-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(stored_password_hash(&app).await, previous);
+}
@@ -0,0 +1,337 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use crate::newsletter::when_sending_an_email;
+use reqwest::Url;
+use wiremock::ResponseTemplate;
+
+const EMAIL: &str = "admin@example.com";
+const NEW_PASSWORD: &str = "a-brand-new-password";
+
+async fn set_test_user_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        EMAIL,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Ask for a reset link for `EMAIL` and return the link of the email.
+async fn request_reset(app: &TestApp) -> Url {
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_form("/password-reset", &serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/password-reset");
+    app.dispatch_all_pending_password_resets().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+fn token(link: &Url) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+async fn post_new_password(
+    app: &TestApp,
+    token: &str,
+    password: &str,
+    password_check: &str,
+) -> reqwest::Response {
+    app.post_form(
+        "/password-reset/confirm",
+        &serde_json::json!({
+            "token": token,
+            "password": password,
+            "password_check": password_check,
+        }),
+    )
+    .await
+}
+
+async fn login_with(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": app.test_user.username,
+        "password": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_password_reset_form() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<a href="/password-reset">Forgot your password?</a>"#));
+}
+
+#[tokio::test]
+async fn a_reset_link_lets_users_choose_a_new_password() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+
+    let link = request_reset(&app).await;
+    let html_page = app.get_url("/password-reset").await.text().await.unwrap();
+    assert!(html_page.contains(
+        "If an account uses admin@example.com, we have sent it a link to reset its password."
+    ));
+    assert_eq!(link.path(), "/password-reset/confirm");
+
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        "Choose a new password for {}.",
+        app.test_user.username
+    )));
+
+    let response = post_new_password(&app, &token(&link), NEW_PASSWORD, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your password has been reset, you can now log in.</i></p>"));
+
+    let response = login_with(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = login_with(&app, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_and_no_email() {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/password-reset",
+            &serde_json::json!({ "email": "nobody@example.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/password-reset");
+    let html_page = app.get_url("/password-reset").await.text().await.unwrap();
+    assert!(html_page.contains(
+        "If an account uses nobody@example.com, we have sent it a link to reset its password."
+    ));
+    app.dispatch_all_pending_password_resets().await;
+}
+
+#[tokio::test]
+async fn reset_emails_are_sent_in_the_background_and_retried() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form("/password-reset", &serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/password-reset");
+    let html_page = app.get_url("/password-reset").await.text().await.unwrap();
+    assert!(html_page.contains(
+        "If an account uses admin@example.com, we have sent it a link to reset its password."
+    ));
+
+    app.dispatch_all_pending_password_resets().await;
+    let outbox = sqlx::query!("SELECT n_retries FROM password_reset_email_outbox")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(outbox.len(), 1);
+    assert_eq!(outbox[0].n_retries, 1);
+    // The token of the email that failed is not kept.
+    let tokens = sqlx::query!("SELECT token_hash FROM password_reset_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
+
+#[tokio::test]
+async fn reset_requests_for_an_address_are_rate_limited_silently() {
+    let app = spawn_app_with(|c| c.authentication.max_password_resets_per_email = 1).await;
+    set_test_user_email(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = app
+            .post_form("/password-reset", &serde_json::json!({ "email": EMAIL }))
+            .await;
+        assert_is_redirect_to(&response, "/password-reset");
+        let html_page = app.get_url("/password-reset").await.text().await.unwrap();
+        assert!(html_page.contains(
+            "If an account uses admin@example.com, we have sent it a link to reset its password."
+        ));
+    }
+    app.dispatch_all_pending_password_resets().await;
+}
+
+#[tokio::test]
+async fn reset_requests_from_an_address_are_rate_limited_silently() {
+    let app = spawn_app_with(|c| c.authentication.max_password_resets_per_ip = 1).await;
+    set_test_user_email(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_form(
+        "/password-reset",
+        &serde_json::json!({ "email": "nobody@example.com" }),
+    )
+    .await;
+    let response = app
+        .post_form("/password-reset", &serde_json::json!({ "email": EMAIL }))
+        .await;
+
+    assert_is_redirect_to(&response, "/password-reset");
+    app.dispatch_all_pending_password_resets().await;
+}
+
+#[tokio::test]
+async fn a_reset_link_works_only_once() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    let link = request_reset(&app).await;
+
+    let response = post_new_password(&app, &token(&link), NEW_PASSWORD, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let response = post_new_password(
+        &app,
+        &token(&link),
+        "yet-another-password",
+        "yet-another-password",
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This link is not valid, has already been used or has expired."));
+    let response = login_with(&app, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    let link = request_reset(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let response = post_new_password(&app, &token(&link), NEW_PASSWORD, NEW_PASSWORD).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = login_with(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_new_password_must_be_valid_and_typed_twice() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    let link = request_reset(&app).await;
+    let reset_url = format!("/password-reset/confirm?token={}", token(&link));
+
+    let response = post_new_password(&app, &token(&link), NEW_PASSWORD, "something-else").await;
+    assert_is_redirect_to(&response, &reset_url);
+    let html_page = app.get_url(&reset_url).await.text().await.unwrap();
+    assert!(html_page.contains(
+        "<p><i>You entered two different passwords - the field values must match.</i></p>"
+    ));
+
+    let response = post_new_password(&app, &token(&link), "short", "short").await;
+    assert_is_redirect_to(&response, &reset_url);
+
+    // The link can still be used after a rejected attempt.
+    let response = post_new_password(&app, &token(&link), NEW_PASSWORD, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_the_user_out_everywhere() {
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let link = request_reset(&app).await;
+    // Reset from another browser, so the logged-in session is left untouched.
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/password-reset/confirm", app.address))
+        .form(&serde_json::json!({
+            "token": token(&link),
+            "password": NEW_PASSWORD,
+            "password_check": NEW_PASSWORD,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn resetting_a_password_lifts_the_lockout_of_the_username() {
+    let app = spawn_app_with(|c| {
+        c.authentication.max_failed_logins_per_username = 3;
+        c.authentication.max_failed_logins_per_ip = 100;
+        c.authentication.failed_login_delay_milliseconds = 0;
+    })
+    .await;
+    set_test_user_email(&app).await;
+    for _ in 0..3 {
+        login_with(&app, "not-the-password").await;
+    }
+    let response = login_with(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let link = request_reset(&app).await;
+    let response = post_new_password(&app, &token(&link), NEW_PASSWORD, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = login_with(&app, NEW_PASSWORD).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let lockout = sqlx::query!("SELECT unlocked_by FROM login_lockouts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(lockout.unlocked_by, Some(app.test_user.user_id));
+}
@@ -0,0 +1,346 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber_with, when_sending_an_email};
+use wiremock::ResponseTemplate;
+
+const EMAIL: &str = "ursula@example.com";
+
+async fn create_confirmed_subscriber(app: &TestApp) -> String {
+    create_confirmed_subscriber_with(app, serde_json::json!({ "email": EMAIL })).await;
+    app.get_preferences_token(EMAIL).await
+}
+
+async fn publish_issue(app: &TestApp, title: &str) {
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+fn preferences(token: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "name": "Ursula Le Guin",
+        "frequency": "every_issue",
+        "pause_days": "",
+        "list_newsletter": "on",
+    })
+}
+
+#[tokio::test]
+async fn preferences_are_rejected_for_an_unknown_token() {
+    let app = spawn_app().await;
+
+    let response = app.get_preferences("a1b2c3d4e5f6g7h8i9j0k1l2m").await;
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn preferences_are_rejected_for_a_malformed_token() {
+    let app = spawn_app().await;
+
+    let response = app.get_preferences("not-a-token").await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn issue_emails_link_to_the_preference_center() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_issue(&app, "Newsletter title").await;
+    app.dispatch_all_pending_workers().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let link = format!("{}/preferences?token={}", app.address, token);
+    assert!(body["HtmlBody"].as_str().unwrap().contains(&link));
+    assert!(body["TextBody"].as_str().unwrap().contains(&link));
+
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains(EMAIL));
+    assert!(html_page.contains(r#"name="list_newsletter" value="on" checked"#));
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+
+    let response = app.post_preferences(&preferences(&token)).await;
+    assert_is_redirect_to(&response, &format!("/preferences?token={token}"));
+
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Ursula Le Guin""#));
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    let mut body = preferences(&token);
+    body["name"] = "<script>".into();
+
+    let response = app.post_preferences(&body).await;
+    assert_is_redirect_to(&response, &format!("/preferences?token={token}"));
+
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("&lt;script&gt; is not a valid subscriber name."));
+    let saved = sqlx::query!("SELECT name FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.name, "<script>");
+}
+
+#[tokio::test]
+async fn unchecked_lists_no_longer_receive_issues() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+    let token = create_confirmed_subscriber(&app).await;
+    let mut body = preferences(&token);
+    body.as_object_mut().unwrap().remove("list_newsletter");
+    body["list_rust"] = "on".into();
+
+    app.post_preferences(&body).await;
+
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let memberships: Vec<_> = memberships
+        .iter()
+        .map(|m| (m.slug.as_str(), m.status.as_str()))
+        .collect();
+    assert_eq!(
+        memberships,
+        vec![("newsletter", "unsubscribed"), ("rust", "confirmed")]
+    );
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn paused_subscribers_do_not_receive_issues() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let mut body = preferences(&token);
+    body["pause_days"] = "30".into();
+
+    app.post_preferences(&body).await;
+
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("<p>Mail is paused until "));
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn pause_durations_are_validated() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+
+    for pause_days in ["-1", "366", "forever"] {
+        let mut body = preferences(&token);
+        body["pause_days"] = pause_days.into();
+        app.post_preferences(&body).await;
+
+        let html_page = app.get_preferences_html(&token).await;
+        assert!(html_page.contains("Mail can only be paused for up to 365 days."));
+    }
+}
+
+#[tokio::test]
+async fn digest_subscribers_receive_a_single_email_for_several_issues() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let mut body = preferences(&token);
+    body["frequency"] = "digest".into();
+    app.post_preferences(&body).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_issue(&app, "First issue").await;
+    publish_issue(&app, "Second issue").await;
+    app.dispatch_all_pending_workers().await;
+    // The digest is not due yet
+    app.dispatch_all_pending_digests().await;
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .all(|r| !String::from_utf8_lossy(&r.body).contains("First issue")));
+
+    sqlx::query!("UPDATE digest_queue SET queued_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_digests().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("First issue"));
+    assert!(text_body.contains("Second issue"));
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_issues() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_unsubscribe(&serde_json::json!({ "token": token }))
+        .await;
+    assert_is_redirect_to(&response, &format!("/preferences?token={token}"));
+
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(
+        html_page.contains("<p><i>You have been unsubscribed from all our mailing lists.</i></p>")
+    );
+    assert!(!html_page.contains(r#"value="on" checked"#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_drops_the_issues_still_being_sent() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+
+    app.post_unsubscribe(&serde_json::json!({ "token": token }))
+        .await;
+
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.n, 0);
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn pausing_drops_the_issues_still_being_sent() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+    let mut body = preferences(&token);
+    body["pause_days"] = "30".into();
+
+    app.post_preferences(&body).await;
+
+    app.dispatch_all_pending_workers().await;
+}
+
+#[tokio::test]
+async fn queued_issues_are_not_sent_to_subscribers_who_are_no_longer_confirmed_or_are_paused() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": "paused@example.com" }))
+        .await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "Newsletter title").await;
+
+    // Changed behind the back of the preference center.
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE email = $1",
+        EMAIL
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET paused_until = now() + interval '1 day' WHERE email = $1",
+        "paused@example.com"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_all_pending_workers().await;
+}
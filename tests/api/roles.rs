@@ -0,0 +1,168 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::when_sending_an_email;
+use wiremock::ResponseTemplate;
+
+/// The user seeded by the migrations, an owner.
+const SEEDED_ADMIN_ID: &str = "ddf8994f-d522-4659-8d02-c1d479057be6";
+
+async fn set_role(app: &TestApp, role: &str) {
+    sqlx::query!(
+        "UPDATE users SET role = $1 WHERE user_id = $2",
+        role,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn role_of(app: &TestApp, user_id: uuid::Uuid) -> String {
+    sqlx::query!("SELECT role FROM users WHERE user_id = $1", user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role
+}
+
+#[tokio::test]
+async fn analysts_can_only_view_statistics() {
+    let app = spawn_app().await;
+    set_role(&app, "analyst").await;
+    app.test_user.login(&app).await;
+
+    for path in ["/admin/lists", "/admin/sequences"] {
+        assert_eq!(app.get_url(path).await.status().as_u16(), 200, "{path}");
+    }
+    for path in ["/admin/newsletters", "/admin/subscribers", "/admin/users"] {
+        assert_eq!(app.get_url(path).await.status().as_u16(), 403, "{path}");
+    }
+    let response = app
+        .post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Your role (analyst) does not allow you to manage mailing lists"));
+
+    let lists = sqlx::query!("SELECT slug FROM lists WHERE slug = 'rust'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(lists.is_none());
+}
+
+#[tokio::test]
+async fn editors_can_publish_but_not_manage_settings_or_users() {
+    let app = spawn_app().await;
+    set_role(&app, "editor").await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    for path in ["/admin/users", "/admin/email-domains"] {
+        assert_eq!(app.get_url(path).await.status().as_u16(), 403, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn the_dashboard_only_links_to_the_pages_of_the_role() {
+    let app = spawn_app().await;
+    set_role(&app, "analyst").await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains("You are signed in as analyst."));
+    assert!(html_page.contains(r#"href="/admin/lists""#));
+    assert!(!html_page.contains(r#"href="/admin/newsletters""#));
+    assert!(!html_page.contains(r#"href="/admin/users""#));
+}
+
+#[tokio::test]
+async fn owners_can_change_the_role_of_a_user() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let seeded_admin_id = uuid::Uuid::parse_str(SEEDED_ADMIN_ID).unwrap();
+
+    let response = app
+        .post_form(
+            "/admin/users/role",
+            &serde_json::json!({ "user_id": seeded_admin_id, "role": "analyst" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    assert_eq!(role_of(&app, seeded_admin_id).await, "analyst");
+    let html_page = app.get_url("/admin/users").await.text().await.unwrap();
+    assert!(html_page.contains("is now analyst."));
+}
+
+#[tokio::test]
+async fn the_last_active_owner_cannot_be_demoted() {
+    let app = spawn_app().await;
+    set_role(&app, "owner").await;
+    app.test_user.login(&app).await;
+    let seeded_admin_id = uuid::Uuid::parse_str(SEEDED_ADMIN_ID).unwrap();
+    app.post_form(
+        "/admin/users/role",
+        &serde_json::json!({ "user_id": seeded_admin_id, "role": "editor" }),
+    )
+    .await;
+
+    app.post_form(
+        "/admin/users/role",
+        &serde_json::json!({ "user_id": app.test_user.user_id, "role": "editor" }),
+    )
+    .await;
+
+    let html_page = app.get_url("/admin/users").await.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        "{} is the last active owner and cannot be removed.",
+        app.test_user.username
+    )));
+    assert_eq!(role_of(&app, app.test_user.user_id).await, "owner");
+}
+
+#[tokio::test]
+async fn invited_users_get_the_role_they_were_invited_with() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_form(
+        "/admin/users/invitations",
+        &serde_json::json!({ "email": "colleague@example.com", "role": "analyst" }),
+    )
+    .await;
+
+    let invitation = sqlx::query!("SELECT role FROM user_invitations")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(invitation.role, "analyst");
+}
+
+#[tokio::test]
+async fn an_unknown_role_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_form(
+        "/admin/users/role",
+        &serde_json::json!({ "user_id": app.test_user.user_id, "role": "superuser" }),
+    )
+    .await;
+
+    assert_eq!(role_of(&app, app.test_user.user_id).await, "owner");
+}
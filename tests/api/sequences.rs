@@ -0,0 +1,209 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber_with, when_sending_an_email};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+const EMAIL: &str = "tay@gmail.com";
+
+/// A welcome email right away and a best-of three days later.
+async fn create_welcome_sequence(app: &TestApp) -> Uuid {
+    app.post_form(
+        "/admin/sequences",
+        &serde_json::json!({"name": "Welcome", "list": "newsletter"}),
+    )
+    .await;
+    let sequence_id = sqlx::query!("SELECT sequence_id FROM automation_sequences")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .sequence_id;
+    for (delay_days, title) in [("0", "Welcome!"), ("3", "The best of our archive")] {
+        let response = app
+            .post_form(
+                "/admin/sequences/steps",
+                &serde_json::json!({
+                    "sequence_id": sequence_id,
+                    "delay_days": delay_days,
+                    "title": title,
+                    "text_content": format!("{title} as plain text"),
+                    "html_content": format!("<p>{title} as HTML</p>"),
+                }),
+            )
+            .await;
+        assert_is_redirect_to(&response, "/admin/sequences");
+    }
+    sequence_id
+}
+
+async fn progress(app: &TestApp) -> (String, i32) {
+    let r = sqlx::query!("SELECT status, steps_sent FROM automation_progress")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (r.status, r.steps_sent)
+}
+
+async fn sent_subjects(app: &TestApp) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["Subject"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_sequences() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/admin/sequences").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_form(
+            "/admin/sequences",
+            &serde_json::json!({"name": "Welcome", "list": "newsletter"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sequences_start_on_confirmation_and_run_until_the_last_step() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_welcome_sequence(&app).await;
+
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    assert_eq!(progress(&app).await, ("active".into(), 0));
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_sequence_steps().await;
+    assert_eq!(progress(&app).await, ("active".into(), 1));
+    assert_eq!(sent_subjects(&app).await.last().unwrap(), "Welcome!");
+
+    // The best-of is not due before day 3.
+    app.dispatch_all_pending_sequence_steps().await;
+    assert_eq!(progress(&app).await, ("active".into(), 1));
+
+    sqlx::query!("UPDATE automation_progress SET next_send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_sequence_steps().await;
+    assert_eq!(progress(&app).await, ("completed".into(), 2));
+    assert_eq!(
+        sent_subjects(&app).await.last().unwrap(),
+        "The best of our archive"
+    );
+
+    let html_page = app.get_url("/admin/sequences").await.text().await.unwrap();
+    assert!(html_page.contains("0 active, 1 completed, 0 stopped"));
+}
+
+#[tokio::test]
+async fn unsubscribing_stops_the_sequence() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_welcome_sequence(&app).await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+
+    let token = app.get_preferences_token(EMAIL).await;
+    app.post_unsubscribe(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(progress(&app).await, ("stopped".into(), 0));
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_sequence_steps().await;
+}
+
+#[tokio::test]
+async fn subscribers_who_left_the_list_are_not_sent_the_next_step() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_welcome_sequence(&app).await;
+    create_confirmed_subscriber_with(&app, serde_json::json!({ "email": EMAIL })).await;
+    sqlx::query!("UPDATE list_memberships SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_sequence_steps().await;
+
+    assert_eq!(progress(&app).await, ("stopped".into(), 0));
+}
+
+#[tokio::test]
+async fn steps_cannot_be_sent_before_the_previous_one() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let sequence_id = create_welcome_sequence(&app).await;
+
+    app.post_form(
+        "/admin/sequences/steps",
+        &serde_json::json!({
+            "sequence_id": sequence_id,
+            "delay_days": "1",
+            "title": "Too early",
+            "text_content": "Too early",
+            "html_content": "<p>Too early</p>",
+        }),
+    )
+    .await;
+
+    let html_page = app.get_url("/admin/sequences").await.text().await.unwrap();
+    assert!(html_page.contains("A step cannot be sent before the previous one (day 3)."));
+    assert!(!html_page.contains("Too early"));
+}
+
+#[tokio::test]
+async fn steps_added_concurrently_take_the_next_positions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let sequence_id = create_welcome_sequence(&app).await;
+    let step = |title: &str| {
+        serde_json::json!({
+            "sequence_id": sequence_id,
+            "delay_days": "7",
+            "title": title,
+            "text_content": title,
+            "html_content": format!("<p>{title}</p>"),
+        })
+    };
+    let (first, second) = (step("First"), step("Second"));
+
+    let response1 = app.post_form("/admin/sequences/steps", &first);
+    let response2 = app.post_form("/admin/sequences/steps", &second);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_is_redirect_to(&response1, "/admin/sequences");
+    assert_is_redirect_to(&response2, "/admin/sequences");
+    let positions: Vec<i32> = sqlx::query!(
+        "SELECT position FROM automation_steps WHERE sequence_id = $1 ORDER BY position",
+        sequence_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.position)
+    .collect();
+    assert_eq!(positions, vec![1, 2, 3, 4]);
+}
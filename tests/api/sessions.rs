@@ -0,0 +1,196 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Another browser, logged in as the test user.
+async fn log_in_elsewhere(app: &TestApp, user_agent: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(user_agent)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1 AND revoked_at IS NULL",
+        app.test_user.user_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.session_id)
+    .collect()
+}
+
+async fn session_id_of(app: &TestApp, user_agent: &str) -> Uuid {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_agent = $1",
+        user_agent
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .session_id
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_the_sessions_of_the_user() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    log_in_elsewhere(&app, "Other Browser/1.0").await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(r#"<a href="/admin/sessions">Active sessions</a>"#));
+    let html_page = app.get_url("/admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains("<td>Other Browser/1.0</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+    assert!(html_page.contains(&format!(
+        r#"name="session_id" value="{}""#,
+        session_id_of(&app, "Other Browser/1.0").await
+    )));
+    assert_eq!(session_ids(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/admin/sessions").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other = log_in_elsewhere(&app, "Other Browser/1.0").await;
+    let other_session_id = session_id_of(&app, "Other Browser/1.0").await;
+
+    let response = app
+        .post_form(
+            "/admin/sessions/revoke",
+            &serde_json::json!({ "session_id": other_session_id }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_url("/admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!html_page.contains("Other Browser/1.0"));
+
+    let response = get_dashboard(&app, &other).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn users_cannot_revoke_the_sessions_of_other_users() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_user_session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, created_at, last_seen_at, expires_at)
+        VALUES ($1, 'ddf8994f-d522-4659-8d02-c1d479057be6', now(), now(), now() + interval '1 hour')
+        "#,
+        other_user_session_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_form(
+            "/admin/sessions/revoke",
+            &serde_json::json!({ "session_id": other_user_session_id }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_url("/admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>This session is no longer active.</i></p>"));
+    let revoked_at = sqlx::query!(
+        "SELECT revoked_at FROM user_sessions WHERE session_id = $1",
+        other_user_session_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .revoked_at;
+    assert!(revoked_at.is_none());
+}
+
+#[tokio::test]
+async fn logging_out_everywhere_ends_every_session() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other = log_in_elsewhere(&app, "Other Browser/1.0").await;
+
+    let response = app
+        .post_form("/admin/sessions/revoke-all", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>You have been logged out of all your sessions.</i></p>"));
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = get_dashboard(&app, &other).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(session_ids(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn logging_out_revokes_the_session() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    assert_eq!(session_ids(&app).await.len(), 1);
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(session_ids(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn changing_password_logs_out_the_other_sessions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other = log_in_elsewhere(&app, "Other Browser/1.0").await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = get_dashboard(&app, &other).await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(session_ids(&app).await.len(), 1);
+}
@@ -0,0 +1,124 @@
+use crate::helpers::{assert_is_redirect_to, signed_form_started_at, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn the_hosted_form_defaults_to_the_default_list() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/subscribe").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Subscribe to Newsletter</h1>"));
+    assert!(html_page.contains(r#"<input type="hidden" name="list" value="newsletter">"#));
+    assert!(html_page.contains(r#"name="website""#));
+}
+
+#[tokio::test]
+async fn the_hosted_form_of_an_unknown_list_is_a_404() {
+    let app = spawn_app().await;
+
+    for list in ["does-not-exist", "Not%20a%20slug"] {
+        let response = app.get_url(&format!("/subscribe?list={list}")).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn subscribing_from_the_hosted_form_shows_a_confirmation_message() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/subscribe",
+            &serde_json::json!({
+                "name": "Tay",
+                "email": "tay@gmail.com",
+                "list": "rust",
+                "source": "hosted_form",
+                "form_started_at": signed_form_started_at(60),
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/subscribe?list=rust");
+    app.dispatch_all_pending_confirmations().await;
+
+    let html_page = app
+        .get_url("/subscribe?list=rust")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Please check your inbox to confirm your subscription."));
+    let consent = sqlx::query!("SELECT source FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.source, "hosted_form");
+}
+
+#[tokio::test]
+async fn invalid_submissions_are_reported_on_the_hosted_form() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_form(
+            "/subscribe",
+            &serde_json::json!({
+                "name": "",
+                "email": "not-an-email",
+                "form_started_at": signed_form_started_at(60),
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/subscribe?list=newsletter");
+
+    let html_page = app.get_url("/subscribe").await.text().await.unwrap();
+    assert!(html_page.contains("not-an-email is not a valid subscriber email."));
+}
+
+#[tokio::test]
+async fn the_hosted_form_redirects_to_the_subscribed_url_of_the_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+    app.post_form(
+        "/admin/lists/redirects",
+        &serde_json::json!({
+            "slug": "rust",
+            "subscribed_redirect_url": "https://example.com/thanks",
+            "confirmed_redirect_url": "",
+        }),
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/subscribe",
+            &serde_json::json!({
+                "name": "Tay",
+                "email": "tay@gmail.com",
+                "list": "rust",
+                "form_started_at": signed_form_started_at(60),
+            }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "https://example.com/thanks");
+}
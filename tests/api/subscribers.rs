@@ -0,0 +1,220 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_browse_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscribers().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_with_their_tags() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=tay&email=tay%40gmail.com&tags=rust,beta".into())
+        .await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_subscribers_html().await;
+
+    assert!(html_page.contains("tay@gmail.com"));
+    assert!(html_page.contains("<td>rust, beta</td>"));
+}
+
+#[tokio::test]
+async fn tags_and_attributes_can_be_edited_by_an_admin() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=tay&email=tay%40gmail.com&tags=rust&attr_city=Lagos".into())
+        .await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains(r#"value="rust""#));
+    assert!(html_page.contains(">city=Lagos</textarea>"));
+
+    let response = app
+        .post_subscriber(
+            subscriber_id,
+            &serde_json::json!({"tags": "go, beta", "attributes": "talks=2\nspeaker=true"}),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{subscriber_id}"));
+
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("<p><i>The subscriber has been updated.</i></p>"));
+    let saved = sqlx::query!("SELECT tags, attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.tags, vec!["go", "beta"]);
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({"talks": 2, "speaker": true})
+    );
+}
+
+#[tokio::test]
+async fn invalid_attributes_are_not_saved() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=tay&email=tay%40gmail.com&tags=rust".into())
+        .await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    app.post_subscriber(
+        subscriber_id,
+        &serde_json::json!({"tags": "go", "attributes": "not a pair"}),
+    )
+    .await;
+
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("not a pair is not a `key=value` pair."));
+    let saved = sqlx::query!("SELECT tags FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.tags, vec!["rust"]);
+}
+
+#[tokio::test]
+async fn unknown_subscribers_return_a_404() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/subscribers/{}",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn consent_records_are_shown_on_the_subscriber_page() {
+    let app = spawn_app().await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "<Newsreader/1.0>")
+        .form(&serde_json::json!({
+            "name": "tay",
+            "email": "tay@gmail.com",
+            "source": "footer-form",
+            "consent_version": "v3",
+        }))
+        .send()
+        .await
+        .unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    assert!(html_page.contains("<td>newsletter</td><td>footer-form</td><td>v3</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td><td>&lt;Newsreader/1.0&gt;</td>"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/admin/subscribers/export").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=tay&email=tay%40gmail.com&tags=rust,beta".into())
+        .await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_url("/admin/subscribers/export?format=csv").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "id,email,name,status,subscribed_at,confirmed_at,tags,\
+        consent_source,consent_text_version,\
+        consent_signup_ip,consent_signup_user_agent,consent_given_at,\
+        consent_confirmation_ip,consent_confirmation_user_agent,consent_confirmed_at"
+    );
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].contains(r#","tay@gmail.com","tay",pending_confirmation,"#));
+    assert!(lines[1].contains(r#",,"rust,beta","subscribe_form","2023-12","127.0.0.1","#));
+    assert!(lines[1].ends_with(r#","","","#));
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_json_lines_filtered_by_status() {
+    let app = spawn_app().await;
+    app.post_subscriptions("name=pending&email=pending%40gmail.com".into())
+        .await;
+    app.post_subscriptions("name=unsubscribed&email=gone%40gmail.com".into())
+        .await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed' WHERE name = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app
+        .get_url("/admin/subscribers/export?format=jsonl&status=unsubscribed")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["email"], "gone@gmail.com");
+    assert_eq!(rows[0]["status"], "unsubscribed");
+    assert_eq!(rows[0]["confirmed_at"], serde_json::Value::Null);
+    assert_eq!(rows[0]["tags"], serde_json::json!([]));
+    assert_eq!(rows[0]["consent"]["source"], "subscribe_form");
+    assert_eq!(rows[0]["consent"]["confirmed_at"], serde_json::Value::Null);
+    assert_eq!(rows[0]["consent"]["signup"]["ip"], "127.0.0.1");
+    assert_eq!(rows[0]["consent"]["confirmation"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn exports_reject_unknown_formats_and_statuses() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in ["format=xml", "status=deleted"] {
+        let response = app
+            .get_url(&format!("/admin/subscribers/export?{query}"))
+            .await;
+        assert_eq!(400, response.status().as_u16(), "{query}");
+    }
+}
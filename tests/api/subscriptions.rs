@@ -1,6 +1,8 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{signed_form_started_at, spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::canonical_emails::ensure_canonical_emails;
+use zero2prod::domain::EmailNormalization;
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -100,6 +102,69 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
     let response = app.post_subscriptions(body.into()).await;
 
     assert_eq!(200, response.status().as_u16());
+    app.dispatch_all_pending_confirmations().await;
+}
+
+#[tokio::test]
+async fn subscribe_does_not_wait_for_the_confirmation_email() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=Temitayo&email=tayo@gmail.com".into())
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    let outbox = sqlx::query!("SELECT subscriber_email FROM confirmation_email_outbox")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(outbox.len(), 1);
+    assert_eq!(outbox[0].subscriber_email, "tayo@gmail.com");
+
+    // A failed delivery is rescheduled instead of dropped.
+    app.dispatch_all_pending_confirmations().await;
+    let outbox = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"delayed!\" FROM confirmation_email_outbox"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(outbox.len(), 1);
+    assert_eq!(outbox[0].n_retries, 1);
+    assert!(outbox[0].delayed);
+}
+
+#[tokio::test]
+async fn rejected_confirmation_emails_are_not_retried() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=Temitayo&email=tayo@gmail.com".into())
+        .await;
+    app.dispatch_all_pending_confirmations().await;
+
+    let outbox = sqlx::query!("SELECT outbox_id FROM confirmation_email_outbox")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(outbox.is_empty());
 }
 
 #[tokio::test]
@@ -115,6 +180,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     app.post_subscriptions(body.into()).await;
 
+    app.dispatch_all_pending_confirmations().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
 
@@ -136,10 +202,67 @@ async fn subscribe_an_existing_subscriber_sends_confirmation_email() {
     app.post_subscriptions(body.into()).await;
     app.post_subscriptions(body.into()).await;
 
+    app.dispatch_all_pending_confirmations().await;
     let email_requests = &app.email_server.received_requests().await.unwrap();
     assert_eq!(email_requests.len(), 2);
 }
 
+#[tokio::test]
+async fn subscribing_with_a_differently_cased_email_reuses_the_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=tay&email=tay%40example.com".into())
+        .await;
+    let response = app
+        .post_subscriptions("name=tay&email=Tay%40EXAMPLE.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, canonical_email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "tay@example.com");
+    assert_eq!(saved[0].canonical_email, "tay@example.com");
+}
+
+#[tokio::test]
+async fn canonical_emails_follow_a_change_of_normalization_rules() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=tay&email=T.ay%2Bnews%40gmail.com".into())
+        .await;
+    let folding = EmailNormalization {
+        fold_gmail_addresses: true,
+    };
+
+    let updated = ensure_canonical_emails(&app.db_pool, &folding)
+        .await
+        .unwrap();
+    assert_eq!(updated, 1);
+    let saved = sqlx::query!("SELECT canonical_email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.canonical_email, "tay@gmail.com");
+
+    // The same rules are only applied once.
+    let updated = ensure_canonical_emails(&app.db_pool, &folding)
+        .await
+        .unwrap();
+    assert_eq!(updated, 0);
+}
+
 #[tokio::test]
 async fn subscribe_adds_unexpired_token_to_subscriptions_token_table() {
     let app = spawn_app().await;
@@ -179,3 +302,452 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_adds_the_subscriber_to_the_default_list() {
+    let app = spawn_app().await;
+    let body = "name=tay%20tayo&email=shadrachtemitayo%40gmail.com";
+
+    app.post_subscriptions(body.into()).await;
+
+    let membership = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch list membership");
+
+    assert_eq!(membership.slug, "newsletter");
+    assert_eq!(membership.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_adds_the_subscriber_to_the_requested_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=tay%20tayo&email=shadrachtemitayo%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    let response = app.post_subscriptions(format!("{}&list=rust", body)).await;
+    assert_eq!(200, response.status().as_u16());
+    app.dispatch_all_pending_confirmations().await;
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let lists: Vec<_> = lists.into_iter().map(|r| r.slug).collect();
+    assert_eq!(lists, vec!["newsletter", "rust"]);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_an_unknown_list() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "name=tay&email=tay%40gmail.com&list=does-not-exist",
+            "unknown list",
+        ),
+        (
+            "name=tay&email=tay%40gmail.com&list=Not%20A%20Slug",
+            "invalid list",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_stores_tags_and_typed_attributes() {
+    let app = spawn_app().await;
+    let body = "name=tay&email=tay%40gmail.com&tags=Rust,beta\
+        &attr_signup_source=conference&attr_talks=3&attr_speaker=true";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let saved = sqlx::query!("SELECT tags, attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.tags, vec!["rust", "beta"]);
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({"signup_source": "conference", "talks": 3, "speaker": true})
+    );
+}
+
+#[tokio::test]
+async fn subscribing_again_merges_tags_and_attributes_once_confirmed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=tay&email=tay%40gmail.com&tags=rust&attr_city=Lagos".into())
+        .await;
+    app.post_subscriptions(
+        "name=tay&email=tay%40gmail.com&tags=go,rust&attr_city=Abuja&attr_talks=1".into(),
+    )
+    .await;
+
+    // Nothing changes until the owner of the address confirms.
+    let saved = sqlx::query!("SELECT tags, attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.tags, vec!["rust"]);
+    assert_eq!(saved.attributes, serde_json::json!({"city": "Lagos"}));
+
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    let saved = sqlx::query!("SELECT tags, attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    let mut tags = saved.tags;
+    tags.sort();
+    assert_eq!(tags, vec!["go", "rust"]);
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({"city": "Abuja", "talks": 1})
+    );
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_invalid_tags_or_attributes() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "name=tay&email=tay%40gmail.com&tags=early%20bird",
+            "invalid tag",
+        ),
+        (
+            "name=tay&email=tay%40gmail.com&attr_Signup=x",
+            "invalid attribute name",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_an_invalid_consent_source_or_version() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "name=tay&email=tay%40gmail.com&source=my%20form",
+            "invalid consent source",
+        ),
+        (
+            "name=tay&email=tay%40gmail.com&consent_version=",
+            "empty consent text version",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn submissions_with_the_honeypot_filled_in_are_silently_ignored() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(
+            "name=bot&email=bot%40gmail.com&website=http%3A%2F%2Fspam.example".into(),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn forms_submitted_too_fast_are_silently_ignored() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(format!(
+            "name=bot&email=bot%40gmail.com&form_started_at={}",
+            signed_form_started_at(0)
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_subscriptions(format!(
+            "name=tay&email=tay%40gmail.com&form_started_at={}",
+            signed_form_started_at(30)
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_confirmations().await;
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "tay@gmail.com");
+}
+
+#[tokio::test]
+async fn forged_form_timestamps_are_silently_ignored() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let backdated = chrono::Utc::now().timestamp() - 30;
+
+    let response = app
+        .post_subscriptions(format!(
+            "name=bot&email=bot%40gmail.com&form_started_at={backdated}"
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn hosted_form_submissions_without_a_form_timestamp_are_silently_ignored() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_form(
+            "/subscribe",
+            &serde_json::json!({"name": "bot", "email": "bot@gmail.com"}),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn the_home_page_form_carries_the_abuse_protection_fields() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_url("/").await.text().await.unwrap();
+
+    assert!(html_page.contains(r#"name="website""#));
+    assert!(!html_page.contains("{form_started_at}"));
+}
+
+#[tokio::test]
+async fn repeat_submissions_for_the_same_email_are_rate_limited() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["tay%40gmail.com", "Tay%40gmail.com", "TAY%40GMAIL.COM"] {
+        let response = app
+            .post_subscriptions(format!("name=tay&email={email}"))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app
+        .post_subscriptions("name=tay&email=tay%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    app.dispatch_all_pending_confirmations().await;
+}
+
+#[tokio::test]
+async fn submissions_from_the_same_ip_are_rate_limited() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..20 {
+        let response = app
+            .post_subscriptions(format!("name=tay&email=tay{i}%40gmail.com"))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app
+        .post_subscriptions("name=tay&email=another%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn forwarded_headers_do_not_escape_the_ip_rate_limit() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..21 {
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", format!("203.0.113.{i}"))
+            .body(format!("name=tay&email=tay{i}%40gmail.com"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        let expected = if i < 20 { 200 } else { 429 };
+        assert_eq!(response.status().as_u16(), expected);
+    }
+}
+
+#[tokio::test]
+async fn forwarded_headers_from_trusted_proxies_are_followed() {
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "198.51.100.7")
+        .body("name=tay&email=tay%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let saved = sqlx::query!("SELECT signup_ip FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.signup_ip.as_deref(), Some("198.51.100.7"));
+}
+
+#[tokio::test]
+async fn repeat_submissions_reuse_the_pending_confirmation_token() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=tay&email=tay%40gmail.com".into())
+        .await;
+    app.post_subscriptions("name=tay&email=tay%40gmail.com".into())
+        .await;
+
+    app.dispatch_all_pending_confirmations().await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_eq!(first_links.html, second_links.html);
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.len(), 1);
+}
@@ -26,6 +26,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
 
     app.post_subscriptions(body.into()).await;
 
+    app.dispatch_all_pending_confirmations().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
 
@@ -46,6 +47,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_confirmations().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
 
@@ -60,12 +62,63 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.email, "shadrach@gmail.com");
     assert_eq!(saved.status, "confirmed");
 }
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_completes_the_consent_record() {
+    let app = spawn_app().await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "signup-browser")
+        .form(&serde_json::json!({"name": "shadrach", "email": "shadrach@gmail.com"}))
+        .send()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let consent = sqlx::query!("SELECT confirmed_at FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(consent.confirmed_at.is_none());
+
+    reqwest::Client::new()
+        .get(confirmation_links.html)
+        .header("User-Agent", "mail-client")
+        .send()
+        .await
+        .unwrap();
+
+    let consent = sqlx::query!(
+        r#"
+        SELECT
+            source, consent_text_version, signup_ip, signup_user_agent,
+            confirmation_ip, confirmation_user_agent, confirmed_at
+        FROM consent_records
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(consent.source, "subscribe_form");
+    assert_eq!(consent.consent_text_version, "2023-12");
+    assert_eq!(consent.signup_ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(consent.signup_user_agent.as_deref(), Some("signup-browser"));
+    assert_eq!(consent.confirmation_ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(
+        consent.confirmation_user_agent.as_deref(),
+        Some("mail-client")
+    );
+    assert!(consent.confirmed_at.is_some());
+}
+
 #[tokio::test]
 async fn clicking_on_the_confirmation_link_expires_subscription_token() {
     let app = spawn_app().await;
     let body = "name=shadrach&email=shadrach@gmail.com";
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_confirmations().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.get_confirmation_links(&email_request);
 
@@ -112,3 +165,203 @@ async fn clicking_on_the_confirmation_link_expires_subscription_token() {
 //
 //     assert_eq!(response.status().as_u16(), 500);
 // }
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_only_confirms_the_requested_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({"slug": "rust", "name": "Rust"}))
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=shadrach&email=shadrach@gmail.com";
+    app.post_subscriptions(body.into()).await;
+    app.post_subscriptions(format!("{}&list=rust", body)).await;
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status, m.confirmed_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch list memberships");
+
+    assert_eq!(memberships.len(), 2);
+    assert_eq!(memberships[0].slug, "newsletter");
+    assert_eq!(memberships[0].status, "pending_confirmation");
+    assert!(memberships[0].confirmed_at.is_none());
+    assert_eq!(memberships[1].slug, "rust");
+    assert_eq!(memberships[1].status, "confirmed");
+    assert!(memberships[1].confirmed_at.is_some());
+}
+
+#[tokio::test]
+async fn the_confirmation_page_tells_the_subscriber_they_are_confirmed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=shadrach&email=shadrach@gmail.com".into())
+        .await;
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let html_page = reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Thanks! You are now subscribed to Newsletter."));
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Your subscription to Newsletter was already confirmed."));
+}
+
+#[tokio::test]
+async fn unknown_confirmation_tokens_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    for token in ["not-a-token", "aaaaaaaaaaaaaaaaaaaaaaaaa"] {
+        let response = app
+            .get_url(&format!(
+                "/subscriptions/confirm?subscription_token={token}"
+            ))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        let html_page = response.text().await.unwrap();
+        assert!(html_page.contains("This confirmation link is not valid."));
+    }
+}
+
+#[tokio::test]
+async fn old_confirmation_links_have_expired() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=shadrach&email=shadrach@gmail.com".into())
+        .await;
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<a href="/subscribe?list=newsletter">Subscribe again</a>"#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn confirming_redirects_to_the_confirmed_url_of_the_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_form(
+        "/admin/lists/redirects",
+        &serde_json::json!({
+            "slug": "newsletter",
+            "subscribed_redirect_url": "",
+            "confirmed_redirect_url": "https://example.com/welcome",
+        }),
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=shadrach&email=shadrach@gmail.com".into())
+        .await;
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let response = app
+        .api_client
+        .get(confirmation_links.html)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://example.com/welcome"
+    );
+}
+
+#[tokio::test]
+async fn a_confirmation_that_failed_halfway_can_be_retried() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=shadrach&email=shadrach@gmail.com".into())
+        .await;
+    app.dispatch_all_pending_confirmations().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // Make the consent update, which comes after the membership, fail once.
+    for statement in [
+        "CREATE FUNCTION fail_consent() RETURNS trigger AS $$ \
+            BEGIN RAISE EXCEPTION 'consent update failed'; END $$ LANGUAGE plpgsql",
+        "CREATE TRIGGER fail_consent BEFORE UPDATE ON consent_records \
+            FOR EACH ROW EXECUTE FUNCTION fail_consent()",
+    ] {
+        sqlx::query(statement).execute(&app.db_pool).await.unwrap();
+    }
+
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 500);
+    let saved = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+
+    sqlx::query("DROP TRIGGER fail_consent ON consent_records")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let consent = sqlx::query!("SELECT confirmed_at FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(consent.confirmed_at.is_some());
+}
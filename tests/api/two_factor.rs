@@ -0,0 +1,208 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+fn code_at(secret: &str, unix_time: u64) -> String {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes, None, "".into())
+        .unwrap()
+        .generate(unix_time)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// A code that was not used to turn 2FA on, still valid thanks to the allowed drift.
+fn next_code(secret: &str) -> String {
+    code_at(secret, now() + 30)
+}
+
+fn between<'a>(html: &'a str, start: &str, end: &str) -> Vec<&'a str> {
+    html.split(start)
+        .skip(1)
+        .map(|s| s.split(end).next().unwrap())
+        .collect()
+}
+
+/// Turn on 2FA for the logged-in test user, returning the secret and the recovery codes.
+async fn enroll(app: &TestApp) -> (String, Vec<String>) {
+    let html_page = app.get_url("/admin/two-factor").await.text().await.unwrap();
+    let secret = between(&html_page, r#"<code id="totp-secret">"#, "</code>")[0].to_string();
+    let response = app
+        .post_form(
+            "/admin/two-factor",
+            &serde_json::json!({ "code": code_at(&secret, now()) }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = between(&html_page, "<li><code>", "</code></li>")
+        .into_iter()
+        .map(String::from)
+        .collect();
+    (secret, recovery_codes)
+}
+
+async fn post_code(app: &TestApp, code: &str) -> reqwest::Response {
+    app.post_form("/login/two-factor", &serde_json::json!({ "code": code }))
+        .await
+}
+
+#[tokio::test]
+async fn users_can_turn_on_two_factor_authentication() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_url("/admin/two-factor").await.text().await.unwrap();
+    assert!(html_page.contains("<svg"));
+    let (_, recovery_codes) = enroll(&app).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+    let saved = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.totp_secret.is_some());
+    let html_page = app.get_url("/admin/two-factor").await.text().await.unwrap();
+    assert!(html_page.contains("You have 10 unused recovery codes."));
+}
+
+#[tokio::test]
+async fn an_invalid_code_does_not_turn_on_two_factor_authentication() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.get_url("/admin/two-factor").await;
+
+    let response = app
+        .post_form(
+            "/admin/two-factor",
+            &serde_json::json!({ "code": "abcdef" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_url("/admin/two-factor").await.text().await.unwrap();
+    assert!(html_page.contains("The code is invalid, please try again."));
+    let saved = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.totp_secret.is_none());
+}
+
+#[tokio::test]
+async fn logging_in_requires_a_code_once_two_factor_authentication_is_on() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    // Half logged-in users cannot open the admin pages.
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = post_code(&app, "abcdef").await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = post_code(&app, &next_code(&secret)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enroll(&app).await;
+    app.post_logout().await;
+    let code = next_code(&secret);
+    app.test_user.login(&app).await;
+    post_code(&app, &code).await;
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let response = post_code(&app, &code).await;
+
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_be_used_once_instead_of_a_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enroll(&app).await;
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let response = post_code(&app, &recovery_codes[3]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let response = post_code(&app, &recovery_codes[3]).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_step_requires_a_valid_password_first() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/login/two-factor").await;
+    assert_is_redirect_to(&response, "/login");
+    let response = post_code(&app, "123456").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn users_can_turn_off_two_factor_authentication_with_a_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enroll(&app).await;
+
+    let response = app
+        .post_form(
+            "/admin/two-factor/disable",
+            &serde_json::json!({ "code": next_code(&secret) }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn users_must_set_up_two_factor_authentication_when_it_is_required() {
+    let app = spawn_app_with(|c| c.authentication.require_two_factor = true).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_url("/admin/two-factor").await.text().await.unwrap();
+    assert!(html_page.contains("Two-factor authentication is required for all users"));
+
+    let (secret, _) = enroll(&app).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.post_form(
+        "/admin/two-factor/disable",
+        &serde_json::json!({ "code": next_code(&secret) }),
+    )
+    .await;
+    let html_page = app.get_url("/admin/two-factor").await.text().await.unwrap();
+    assert!(html_page.contains("Two-factor authentication is required for all users."));
+}
@@ -0,0 +1,355 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::when_sending_an_email;
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+const INVITED_EMAIL: &str = "colleague@example.com";
+const PASSWORD: &str = "a-long-enough-password";
+
+/// The user seeded by the migrations.
+const SEEDED_ADMIN_ID: &str = "ddf8994f-d522-4659-8d02-c1d479057be6";
+
+/// Invite `INVITED_EMAIL` and return the link of the invitation email.
+async fn invite(app: &TestApp) -> Url {
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_form(
+            "/admin/users/invitations",
+            &serde_json::json!({ "email": INVITED_EMAIL }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+/// A client of its own, so that the invited user gets their own session.
+fn new_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+fn query_param(link: &Url, name: &str) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+async fn accept(
+    app: &TestApp,
+    client: &reqwest::Client,
+    link: &Url,
+    username: &str,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/invitations/accept", app.address))
+        .form(&serde_json::json!({
+            "invitation_id": query_param(link, "invitation_id"),
+            "tag": query_param(link, "tag"),
+            "username": username,
+            "password": PASSWORD,
+            "password_check": PASSWORD,
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn login(app: &TestApp, client: &reqwest::Client, username: &str) -> reqwest::Response {
+    client
+        .post(format!("{}/login", app.address))
+        .form(&serde_json::json!({ "username": username, "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn user_id(app: &TestApp, username: &str) -> Uuid {
+    sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    let app = spawn_app().await;
+
+    let response = app.get_url("/admin/users").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_form(
+            "/admin/users/invitations",
+            &serde_json::json!({ "email": INVITED_EMAIL }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_invited_user_can_create_an_account_and_log_in() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let link = invite(&app).await;
+    let html_page = app.get_url("/admin/users").await.text().await.unwrap();
+    assert!(html_page.contains("An invitation has been sent to colleague@example.com."));
+    assert!(html_page.contains(&format!("<tr><td>{INVITED_EMAIL}</td>")));
+
+    let client = new_client();
+    let html_page = client.get(link.clone()).send().await.unwrap();
+    assert_eq!(html_page.status().as_u16(), 200);
+    assert!(html_page.text().await.unwrap().contains(INVITED_EMAIL));
+
+    let response = accept(&app, &client, &link, "colleague").await;
+    assert_is_redirect_to(&response, "/login");
+    let response = login(&app, &client, "colleague").await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let saved = sqlx::query!("SELECT email, status FROM users WHERE username = 'colleague'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email.as_deref(), Some(INVITED_EMAIL));
+    assert_eq!(saved.status, "active");
+}
+
+#[tokio::test]
+async fn an_invitation_can_only_be_used_once() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    let client = new_client();
+    accept(&app, &client, &link, "colleague").await;
+
+    let response = accept(&app, &client, &link, "someone-else").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let response = client.get(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn tampered_and_expired_invitations_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    let client = new_client();
+
+    let mut tampered = link.clone();
+    tampered.set_query(Some(&format!(
+        "invitation_id={}&tag={}",
+        Uuid::new_v4(),
+        query_param(&link, "tag")
+    )));
+    let response = client.get(tampered).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    sqlx::query!("UPDATE user_invitations SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = accept(&app, &client, &link, "colleague").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn accepting_an_invitation_requires_matching_passwords() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    let client = new_client();
+
+    let response = client
+        .post(format!("{}/invitations/accept", app.address))
+        .form(&serde_json::json!({
+            "invitation_id": query_param(&link, "invitation_id"),
+            "tag": query_param(&link, "tag"),
+            "username": "colleague",
+            "password": PASSWORD,
+            "password_check": "another-long-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(location.starts_with("/invitations/accept?invitation_id="));
+    let html_page = client
+        .get(format!("{}{}", app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("You entered two different passwords"));
+}
+
+#[tokio::test]
+async fn an_address_with_an_account_cannot_be_invited_again() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    accept(&app, &new_client(), &link, "colleague").await;
+
+    app.post_form(
+        "/admin/users/invitations",
+        &serde_json::json!({ "email": INVITED_EMAIL }),
+    )
+    .await;
+
+    let html_page = app.get_url("/admin/users").await.text().await.unwrap();
+    assert!(html_page.contains("colleague@example.com already has an account."));
+}
+
+#[tokio::test]
+async fn accepting_an_invitation_for_an_address_taken_in_the_meantime_is_a_form_error() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        INVITED_EMAIL,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let client = new_client();
+
+    let response = accept(&app, &client, &link, "colleague").await;
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(location.starts_with("/invitations/accept?invitation_id="));
+    let html_page = client
+        .get(format!("{}{}", app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("colleague@example.com already has an account."));
+    let accepted_at = sqlx::query!("SELECT accepted_at FROM user_invitations")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .accepted_at;
+    assert!(accepted_at.is_none());
+}
+
+#[tokio::test]
+async fn disabled_users_are_logged_out_and_cannot_log_in() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    let client = new_client();
+    accept(&app, &client, &link, "colleague").await;
+    login(&app, &client, "colleague").await;
+
+    let colleague_id = user_id(&app, "colleague").await;
+    let response = app
+        .post_form(
+            "/admin/users/disable",
+            &serde_json::json!({ "user_id": colleague_id }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = client
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let response = login(&app, &client, "colleague").await;
+    assert_is_redirect_to(&response, "/login");
+
+    app.post_form(
+        "/admin/users/enable",
+        &serde_json::json!({ "user_id": colleague_id }),
+    )
+    .await;
+    let response = login(&app, &client, "colleague").await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn deleted_users_are_removed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let link = invite(&app).await;
+    let client = new_client();
+    accept(&app, &client, &link, "colleague").await;
+
+    let response = app
+        .post_form(
+            "/admin/users/delete",
+            &serde_json::json!({ "user_id": user_id(&app, "colleague").await }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let html_page = app.get_url("/admin/users").await.text().await.unwrap();
+    assert!(html_page.contains("colleague has been deleted."));
+    let response = login(&app, &client, "colleague").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_last_active_owner_cannot_be_disabled_or_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_form(
+            "/admin/users/disable",
+            &serde_json::json!({ "user_id": SEEDED_ADMIN_ID }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    for action in ["disable", "delete"] {
+        app.post_form(
+            &format!("/admin/users/{action}"),
+            &serde_json::json!({ "user_id": app.test_user.user_id }),
+        )
+        .await;
+
+        let html_page = app.get_url("/admin/users").await.text().await.unwrap();
+        assert!(html_page.contains(&format!(
+            "{} is the last active owner and cannot be removed.",
+            app.test_user.username
+        )));
+    }
+    let response = app.get_url("/admin/dashboard").await;
+    assert_eq!(response.status().as_u16(), 200);
+}